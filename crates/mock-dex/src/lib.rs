@@ -0,0 +1,183 @@
+/*!
+Mock DEX for testing pool rebalances.
+NOTES:
+  - Swaps tokens received with `ft_transfer_call` at fixed rates set by the owner.
+  - The message is `{"token_out": "...", "min_amount_out": "...", "output_msg": "..."}`. The
+    output is sent back to the sender with `ft_transfer_call` and `output_msg`.
+  - If the sender refunds the output, the input tokens are refunded too.
+  - The DEX has to hold enough of the output token and be registered with both tokens.
+*/
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    env, near_bindgen, AccountId, Balance, Gas, PanicOnDefault, PromiseError, PromiseOrValue,
+    ONE_YOCTO,
+};
+
+const GAS_FOR_OUTPUT_TRANSFER: Gas = Gas(60_000_000_000_000);
+const GAS_FOR_RESOLVE_SWAP: Gas = Gas(10_000_000_000_000);
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapMsg {
+    pub token_out: AccountId,
+    pub min_amount_out: U128,
+    pub output_msg: String,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    owner_id: AccountId,
+    /// Output amount per input amount, as (numerator, denominator)
+    rates: LookupMap<String, (Balance, Balance)>,
+}
+
+fn rate_key(token_in: &AccountId, token_out: &AccountId) -> String {
+    format!("{}:{}", token_in, token_out)
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        Self {
+            owner_id,
+            rates: LookupMap::new(b"r".to_vec()),
+        }
+    }
+
+    /// Set the rate of swaps from `token_in` to `token_out`. Only callable by the owner.
+    pub fn set_rate(
+        &mut self,
+        token_in: AccountId,
+        token_out: AccountId,
+        numerator: U128,
+        denominator: U128,
+    ) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Only the owner can set rates"
+        );
+        assert!(denominator.0 > 0, "Denominator should be positive");
+        self.rates.insert(
+            &rate_key(&token_in, &token_out),
+            &(numerator.0, denominator.0),
+        );
+    }
+
+    pub fn get_amount_out(
+        &self,
+        token_in: AccountId,
+        token_out: AccountId,
+        amount_in: U128,
+    ) -> U128 {
+        let (numerator, denominator) = self
+            .rates
+            .get(&rate_key(&token_in, &token_out))
+            .unwrap_or_else(|| env::panic_str("No rate for the token pair"));
+        let amount_out = amount_in
+            .0
+            .checked_mul(numerator)
+            .unwrap_or_else(|| env::panic_str("Amount overflow"))
+            / denominator;
+        amount_out.into()
+    }
+
+    /// Keep the input tokens if the output was accepted, refund them otherwise
+    #[private]
+    pub fn resolve_swap(
+        &mut self,
+        amount_in: U128,
+        #[callback_result] call_result: Result<U128, PromiseError>,
+    ) -> U128 {
+        match call_result {
+            Ok(used_amount) if used_amount.0 > 0 => U128(0),
+            _ => amount_in,
+        }
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_in = env::predecessor_account_id();
+        let SwapMsg {
+            token_out,
+            min_amount_out,
+            output_msg,
+        } = near_sdk::serde_json::from_str(&msg).expect("invalid message");
+        let amount_out = self.get_amount_out(token_in, token_out.clone(), amount);
+        assert!(
+            amount_out.0 >= min_amount_out.0,
+            "Exceeded slippage tolerance"
+        );
+
+        ext_ft_core::ext(token_out)
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(GAS_FOR_OUTPUT_TRANSFER)
+            .ft_transfer_call(sender_id, amount_out, None, output_msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SWAP)
+                    .resolve_swap(amount),
+            )
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn test_swap() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(accounts(0));
+        contract.set_rate(accounts(1), accounts(2), U128(3), U128(2));
+        assert_eq!(
+            contract
+                .get_amount_out(accounts(1), accounts(2), U128(100))
+                .0,
+            150
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let msg = r#"{"token_out": "charlie", "min_amount_out": "150", "output_msg": ""}"#;
+        assert!(matches!(
+            contract.ft_on_transfer(accounts(3), U128(100), msg.to_string()),
+            PromiseOrValue::Promise(_)
+        ));
+
+        assert_eq!(contract.resolve_swap(U128(100), Ok(U128(150))).0, 0);
+        assert_eq!(contract.resolve_swap(U128(100), Ok(U128(0))).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeded slippage tolerance")]
+    fn test_swap_slippage() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(accounts(0));
+        contract.set_rate(accounts(1), accounts(2), U128(3), U128(2));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let msg = r#"{"token_out": "charlie", "min_amount_out": "151", "output_msg": ""}"#;
+        contract.ft_on_transfer(accounts(3), U128(100), msg.to_string());
+    }
+}
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+alter table perp_event.edit_fees drop column destination;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+alter table perp_event.edit_fees add column destination text;
@@ -3,7 +3,7 @@ use diesel::prelude::*;
 use diesel::result::QueryResult;
 
 use tonic_perps_sdk::event_types;
-use tonic_perps_sdk::prelude::FeeType;
+use tonic_perps_sdk::prelude::{FeeDestination, FeeType};
 
 use crate::schema;
 use crate::schema::*;
@@ -21,6 +21,7 @@ pub struct EditFees {
     new_accumulated_fees_usd: String,
     increase: bool,
     asset_id: String,
+    destination: Option<String>,
 }
 
 pub fn save(
@@ -48,6 +49,14 @@ pub fn save(
             new_accumulated_fees_usd: ev.new_accumulated_fees_usd.to_string(),
             increase: ev.increase,
             asset_id: ev.asset_id,
+            destination: Some(
+                match ev.destination {
+                    FeeDestination::Treasury => "treasury",
+                    FeeDestination::Pool => "pool",
+                    FeeDestination::Staking => "staking",
+                }
+                .to_string(),
+            ),
         })
         .execute(conn)?;
 
@@ -39,6 +39,7 @@ pub mod perp_event {
             new_accumulated_fees_usd -> Text,
             increase -> Bool,
             asset_id -> Text,
+            destination -> Nullable<Text>,
         }
     }
 
@@ -39,6 +39,7 @@ pub enum EventType {
     SetReferrerTier(SetReferrerTierEvent),
     LpPriceUpdate(LpPriceUpdateEvent),
     OracleUpdate(OracleUpdateEvent),
+    OracleUpdateFailure(OracleUpdateFailureEvent),
     PlaceLimitOrder(PlaceLimitOrderEvent),
     RemoveLimitOrder(RemoveLimitOrderEvent),
     EditFees(EditFeesEvent),
@@ -46,6 +47,10 @@ pub enum EventType {
     EditReservedAmount(EditReservedAmountEvent),
     EditGuaranteedUsd(EditGuaranteedUsdEvent),
     TokenDepositWithdraw(TokenDepositWithdrawEvent),
+    PlaceTwapOrder(PlaceTwapOrderEvent),
+    TwapSlice(TwapSliceEvent),
+    RemoveTwapOrder(RemoveTwapOrderEvent),
+    PriceOverride(PriceOverrideEvent),
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -75,7 +80,7 @@ pub struct EditGuaranteedUsdEvent {
     pub asset_id: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(crate = "near_sdk::serde", rename_all = "lowercase")]
 pub enum FeeType {
     Burn,
@@ -86,6 +91,17 @@ pub enum FeeType {
     WithrawFee,
 }
 
+/// Recipient of collected fees
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "lowercase")]
+pub enum FeeDestination {
+    #[default]
+    Treasury,
+    /// Added to the pool balance, raising the LP token price
+    Pool,
+    Staking,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EditFeesEvent {
     pub fee_native: u128,
@@ -96,6 +112,8 @@ pub struct EditFeesEvent {
     pub increase: bool,
     pub account_id: AccountId,
     pub asset_id: String,
+    #[serde(default)]
+    pub destination: FeeDestination,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -291,6 +309,24 @@ pub struct OracleUpdateEvent {
     pub source: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename = "oracle_update_failure")]
+pub struct OracleUpdateFailureEvent {
+    pub asset_id: String,
+    pub source: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename = "price_override")]
+pub struct PriceOverrideEvent {
+    pub asset_id: String,
+    pub price: U128,
+    pub effective_timestamp_ms: u64,
+    /// "schedule" or "cancel"
+    pub action: String,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(crate = "near_sdk::serde", rename = "lp_price_update")]
 pub struct LpPriceUpdateEvent {
@@ -312,6 +348,16 @@ pub struct PlaceLimitOrderEvent {
     pub price_usd: U128,
     pub expiry: U128,
     pub is_long: bool,
+    /// Output token of swap orders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_token: Option<String>,
+    #[serde(default)]
+    pub reduce_only: bool,
+    #[serde(default)]
+    pub close_position: bool,
+    /// Fee paid to the keeper executing the order, in the collateral token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_fee_native: Option<U128>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -325,6 +371,9 @@ pub enum RemoveOrderReason {
     Invalid,
 
     Removed,
+
+    /// Replaced by the updated order
+    Updated,
 }
 
 impl Display for RemoveOrderReason {
@@ -334,6 +383,7 @@ impl Display for RemoveOrderReason {
             RemoveOrderReason::Executed => "executed",
             RemoveOrderReason::Invalid => "invalid",
             RemoveOrderReason::Removed => "removed",
+            RemoveOrderReason::Updated => "updated",
         })
     }
 }
@@ -348,6 +398,65 @@ pub struct RemoveLimitOrderEvent {
     pub liquidator_id: Option<AccountId>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename = "place_twap_order")]
+pub struct PlaceTwapOrderEvent {
+    pub account_id: AccountId,
+    pub twap_order_id: u64,
+    pub collateral_token: String,
+    pub underlying_token: String,
+    pub collateral_native: U128,
+    /// Size of the whole order
+    pub size_delta_usd: U128,
+    pub num_slices: u16,
+    pub interval_sec: u64,
+    pub is_long: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "lowercase")]
+pub enum TwapSliceStatus {
+    Executed,
+    /// The slice failed validation and was not executed
+    Skipped,
+}
+
+impl Display for TwapSliceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TwapSliceStatus::Executed => "executed",
+            TwapSliceStatus::Skipped => "skipped",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename = "twap_slice")]
+pub struct TwapSliceEvent {
+    pub account_id: AccountId,
+    pub twap_order_id: u64,
+    pub underlying_token: String,
+    pub status: TwapSliceStatus,
+    /// Why the slice was skipped
+    pub reason: Option<String>,
+    pub collateral_native: U128,
+    pub size_delta_usd: U128,
+    pub remaining_slices: u16,
+    /// Keeper's account
+    pub liquidator_id: AccountId,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde", rename = "remove_twap_order")]
+pub struct RemoveTwapOrderEvent {
+    pub account_id: AccountId,
+    pub twap_order_id: u64,
+    pub underlying_token: String,
+    pub reason: RemoveOrderReason,
+    /// Escrowed collateral returned to the owner
+    pub refund_native: U128,
+}
+
 // TODO:
 // Collect Swap Fees
 // Collect Margin Fees
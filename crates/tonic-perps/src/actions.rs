@@ -1,7 +1,10 @@
 use near_sdk::json_types::U128;
 use near_sdk::serde::{Deserialize, Serialize};
 
-use crate::{IncreasePositionRequest, LimitOrderParameters};
+use crate::{
+    IncreasePositionRequest, LimitOrderParameters, SwapLimitOrderParameters, TwapOrderParameters,
+    UpdateLimitOrderParameters,
+};
 
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
@@ -16,6 +19,9 @@ pub struct SwapParams {
     pub output_token_id: String,
     pub min_out: Option<U128>,
     pub referrer_id: Option<String>,
+    /// Swap only the amount needed to get `amount_out` and refund the rest.
+    /// `min_out` is ignored if set.
+    pub amount_out: Option<U128>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,7 +34,26 @@ pub enum Action {
     /// Mint LP tokens from a fungible token
     MintLp(MintLpParams),
 
+    /// Add a fungible token to the LP basket, to be minted with other assets
+    AddToLpBasket,
+
+    /// Mint LP tokens from a fungible token, swapping it into the most
+    /// under-weighted pool asset first
+    ZapInLp(MintLpParams),
+
+    /// Output of a pool rebalance swap, sent by the rebalance DEX
+    RebalanceSwapOut,
+
     /// Increase position when paying collateral with a fungible token
     IncreasePosition(IncreasePositionRequest),
     PlaceLimitOrder(LimitOrderParameters),
+
+    /// Place a limit order swapping the fungible token into another asset
+    PlaceSwapLimitOrder(SwapLimitOrderParameters),
+
+    /// Update a limit order, topping up its collateral with the fungible token
+    UpdateLimitOrder(UpdateLimitOrderParameters),
+
+    /// Place a TWAP order with the fungible token as collateral
+    PlaceTwapOrder(TwapOrderParameters),
 }
@@ -4,8 +4,10 @@ use serde::Deserialize;
 use crate::{
     asset_parameter, borsh, contract_parameter, env, near_bindgen, require_predecessor, AccountId,
     Asset, AssetId, AssetPositionLimits, AssetState, BorshDeserialize, BorshSerialize, Contract,
-    FeeParameters, OpenInterestLimits, Serialize, SwitchboardAddress, VContract, VContractExt,
-    LEVERAGE_MULTIPLIER, MAX_FEE_BPS, MAX_LIQUIDATION_REWARD_USD,
+    FeeParameters, FeeSplitParameters, KeeperRewardParameters, LimitOrderLimits,
+    OpenInterestLimits, OutflowLimits, PriceImpactParameters, RebalanceParameters, Serialize,
+    SwitchboardAddress, VContract, VContractExt, BPS_DIVISOR, LEVERAGE_MULTIPLIER, MAX_FEE_BPS,
+    MAX_LIQUIDATION_REWARD_USD, MIN_PRICE_OVERRIDE_DELAY_SEC,
 };
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -129,9 +131,24 @@ impl VContract {
         contract.owner_id = account_id;
     }
 
+    /// Set the account receiving withdrawn fees
+    pub fn set_treasury_id(&mut self, account_id: AccountId) {
+        let contract = self.contract_mut();
+        contract.assert_owner();
+        contract.treasury_id = account_id;
+    }
+
+    pub fn get_treasury_id(&self) -> AccountId {
+        self.contract().treasury_id.clone()
+    }
+
     pub fn set_state(&mut self, state: ContractState) {
         let contract = self.contract_mut();
         contract.assert_admin();
+        assert!(
+            contract.pending_migration.is_none(),
+            "Contract migration in progress"
+        );
         contract.state = state;
     }
 
@@ -235,6 +252,28 @@ impl VContract {
         contract.set_asset(&asset_id.into(), asset);
     }
 
+    /// Set the sliding window of the LP outflow limits, in ms
+    pub fn set_outflow_window(&mut self, sliding_window_duration: u64) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract
+            .outflow_history
+            .update_sliding_window_duration(sliding_window_duration);
+        contract.outflow_history.clean(env::block_timestamp_ms());
+    }
+
+    /// If true, LP token holders are registered for free. Otherwise new
+    /// holders have to pay for storage with `storage_deposit`.
+    pub fn set_lp_free_storage(&mut self, free_storage: bool) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.lp_token.free_storage = free_storage;
+    }
+
+    pub fn get_lp_free_storage(&self) -> bool {
+        self.contract().lp_token.free_storage
+    }
+
     pub fn enable_asset(&mut self, asset_id: String) {
         let contract = self.contract_mut();
         contract.assert_admin();
@@ -253,6 +292,10 @@ impl VContract {
 }
 
 asset_parameter!(buffer_amount, U128);
+asset_parameter!(depeg_band_bps, u16, |_, b| u128::from(*b) <= BPS_DIVISOR);
+asset_parameter!(mark_price_smoothing_bps, u16, |_, b| {
+    *b > 0 && u128::from(*b) <= BPS_DIVISOR
+});
 asset_parameter!(max_pool_amount, U128);
 asset_parameter!(min_profit_bps, U128);
 asset_parameter!(open_interest_limits, OpenInterestLimits);
@@ -261,6 +304,13 @@ asset_parameter!(shortable, bool);
 asset_parameter!(state, AssetState, |_, _| true, asset_state);
 type OptionalSwitchboardAddress = Option<SwitchboardAddress>;
 asset_parameter!(switchboard_aggregator_address, OptionalSwitchboardAddress);
+type OptionalStalenessDuration = Option<u64>;
+asset_parameter!(
+    max_staleness_duration_sec,
+    OptionalStalenessDuration,
+    |_, _| true,
+    asset_max_staleness_duration_sec
+);
 
 contract_parameter!(dynamic_position_fees, bool);
 contract_parameter!(dynamic_swap_fees, bool);
@@ -272,17 +322,41 @@ contract_parameter!(fee_parameters, FeeParameters, |_, f| {
         && f.stable_swap_fee_bps <= MAX_FEE_BPS
         && f.margin_fee_bps <= MAX_FEE_BPS
 });
+contract_parameter!(fee_split_parameters, FeeSplitParameters, |_, f| {
+    f.is_valid()
+});
+contract_parameter!(keeper_reward_parameters, KeeperRewardParameters);
+contract_parameter!(outflow_limits, OutflowLimits);
+contract_parameter!(rebalance_parameters, RebalanceParameters, |_, p| {
+    p.swap_discount_bps <= MAX_FEE_BPS && u128::from(p.max_slippage_bps) <= BPS_DIVISOR
+});
+contract_parameter!(price_impact_parameters, PriceImpactParameters, |_, p| {
+    u128::from(MAX_FEE_BPS) + u128::from(p.max_impact_bps) < BPS_DIVISOR
+});
+type OptionalAccountId = Option<AccountId>;
+contract_parameter!(wnear_id, OptionalAccountId, |contract, id| {
+    !id.as_ref()
+        .is_some_and(|id| contract.assets.get(&AssetId::Ft(id.clone())).is_some())
+});
 contract_parameter!(max_limit_order_life_sec, u64);
 contract_parameter!(max_leverage, u16, |contract, max| {
     max > &contract.min_leverage
 });
 contract_parameter!(max_staleness_duration_sec, u64);
+contract_parameter!(switchboard_refresh_interval_sec, u64);
+contract_parameter!(price_override_delay_sec, u64, |_, delay| {
+    *delay >= MIN_PRICE_OVERRIDE_DELAY_SEC
+});
+contract_parameter!(staking_reward_duration_sec, u64);
 contract_parameter!(min_leverage, u16, |contract, min| {
     contract.max_leverage > *min && *min > LEVERAGE_MULTIPLIER
 });
 contract_parameter!(min_profit_time_seconds, u64);
 contract_parameter!(leverage_enabled, bool);
 contract_parameter!(limit_orders_state, LimitOrdersState);
+contract_parameter!(permissionless_limit_order_execution, bool);
+contract_parameter!(limit_order_limits, LimitOrderLimits);
+contract_parameter!(lp_cooldown_sec, u64);
 contract_parameter!(liquidation_reward_usd, U128, |_, r| {
     r.0 <= MAX_LIQUIDATION_REWARD_USD
 });
@@ -1,3 +1,7 @@
+mod split;
+
+pub use split::*;
+
 use crate::{ratio, Asset, AssetId, Balance, Contract, DollarBalance, BN, FUNDING_RATE_PRECISION};
 
 pub const ONE_PERCENT_BPS: u128 = 100;
@@ -30,8 +34,13 @@ impl Contract {
         let asset_out = self.assets.unwrap(asset_out);
         let is_stableswap = asset_in.stable && asset_out.stable;
 
+        let discount_bps =
+            self.get_rebalance_discount_bps(&asset_in, &asset_out, amount_in, amount_out);
         if is_stableswap {
-            return self.fee_parameters.stable_swap_fee_bps;
+            return self
+                .fee_parameters
+                .stable_swap_fee_bps
+                .saturating_sub(discount_bps);
         }
 
         let total_usd = self.get_total_value();
@@ -53,10 +62,10 @@ impl Contract {
             total_usd,
         );
 
-        fee_bps_in.max(fee_bps_out)
+        fee_bps_in.max(fee_bps_out).saturating_sub(discount_bps)
     }
 
-    fn get_total_value(&self) -> DollarBalance {
+    pub fn get_total_value(&self) -> DollarBalance {
         let total_usd: DollarBalance = self
             .assets
             .0
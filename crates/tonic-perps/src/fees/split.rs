@@ -0,0 +1,96 @@
+use tonic_perps_sdk::prelude::FeeType;
+
+use crate::{
+    borsh, ratio, AccountId, Asset, Balance, BorshDeserialize, BorshSerialize, Contract,
+    Deserialize, Serialize, BPS_DIVISOR,
+};
+
+/// Shares of a collected fee, in bps. Shares must add up to 100%.
+#[derive(Clone, Copy, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSplit {
+    /// Withdrawable to the treasury account
+    pub treasury_bps: u16,
+    /// Added to the pool balance, raising the LP token price
+    pub lp_bps: u16,
+    /// Distributed to LP token stakers
+    pub staking_bps: u16,
+}
+
+impl Default for FeeSplit {
+    fn default() -> Self {
+        Self {
+            treasury_bps: BPS_DIVISOR as u16,
+            lp_bps: 0,
+            staking_bps: 0,
+        }
+    }
+}
+
+impl FeeSplit {
+    pub fn is_valid(&self) -> bool {
+        u128::from(self.treasury_bps) + u128::from(self.lp_bps) + u128::from(self.staking_bps)
+            == BPS_DIVISOR
+    }
+}
+
+/// How fees of each type are split. Can be updated with admin method.
+#[derive(Clone, Default, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSplitParameters {
+    pub swap: FeeSplit,
+    pub mint: FeeSplit,
+    pub burn: FeeSplit,
+    pub position: FeeSplit,
+    pub funding: FeeSplit,
+}
+
+impl FeeSplitParameters {
+    pub fn get(&self, fee_type: &FeeType) -> FeeSplit {
+        match fee_type {
+            FeeType::Swap => self.swap,
+            FeeType::Mint => self.mint,
+            FeeType::Burn => self.burn,
+            FeeType::Position => self.position,
+            FeeType::Funding => self.funding,
+            FeeType::WithrawFee => FeeSplit::default(),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        [self.swap, self.mint, self.burn, self.position, self.funding]
+            .iter()
+            .all(FeeSplit::is_valid)
+    }
+}
+
+impl Contract {
+    /// Collect fees and split them between the treasury, the pool and stakers
+    pub fn collect_fees(
+        &self,
+        asset: &mut Asset,
+        fees: Balance,
+        fee_type: FeeType,
+        account_id: &AccountId,
+    ) {
+        let split = self.fee_split_parameters.get(&fee_type);
+        let lp_fees = ratio(fees, split.lp_bps, BPS_DIVISOR);
+        // Nobody to share the staking fees with, they go to the treasury
+        let staking_fees = if self.staking.total_staked > 0 {
+            ratio(fees, split.staking_bps, BPS_DIVISOR)
+        } else {
+            0
+        };
+        let treasury_fees = fees - lp_fees - staking_fees;
+
+        if lp_fees > 0 {
+            asset.add_pool_fees(lp_fees, fee_type.clone(), account_id);
+        }
+        if staking_fees > 0 {
+            asset.add_staking_fees(staking_fees, fee_type.clone(), account_id);
+        }
+        if treasury_fees > 0 || fees == 0 {
+            asset.add_fees(treasury_fees, fee_type, account_id);
+        }
+    }
+}
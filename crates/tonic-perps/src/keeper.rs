@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use near_sdk::json_types::U128;
+use tonic_perps_sdk::prelude::FeeType;
+
+use crate::{
+    borsh, env, near_bindgen, u128_dec_format, AccountId, Asset, AssetId, Balance,
+    BorshDeserialize, BorshSerialize, Contract, Deserialize, DollarBalance, Serialize,
+    TransferInfo, VContract, VContractExt,
+};
+
+/// Parameters of the reward paid to keepers for refreshing stale prices.
+/// Can be updated with admin method.
+#[derive(Clone, Default, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct KeeperRewardParameters {
+    /// Reward for each refreshed asset price, in USD. 0 disables rewards.
+    #[serde(with = "u128_dec_format")]
+    pub reward_usd: DollarBalance,
+
+    /// Minimum age of the previous price for a refresh to be rewarded
+    pub min_staleness_sec: u64,
+
+    /// Length of a reward epoch
+    pub epoch_sec: u64,
+
+    /// Maximum amount of rewards paid to all keepers in an epoch, in USD
+    #[serde(with = "u128_dec_format")]
+    pub epoch_cap_usd: DollarBalance,
+}
+
+/// Rewards earned by a keeper
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+pub struct KeeperRewards {
+    /// Rewards not yet claimed, in native tokens
+    pub claimable: HashMap<AssetId, Balance>,
+
+    /// Sum of all rewards earned, in USD at the time they were earned
+    pub total_earned_usd: DollarBalance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct KeeperRewardsView {
+    pub claimable: HashMap<String, U128>,
+    pub total_earned_usd: U128,
+}
+
+impl Contract {
+    /// Pay a keeper for refreshing the price of an asset. The reward is taken
+    /// from the accumulated fees of the asset and can be claimed later.
+    pub fn reward_keeper(&mut self, keeper_id: &AccountId, asset: &mut Asset, price_age_ms: u64) {
+        let params = self.keeper_reward_parameters.clone();
+        if params.reward_usd == 0
+            || asset.price == 0
+            || price_age_ms < Duration::from_secs(params.min_staleness_sec).as_millis() as u64
+        {
+            return;
+        }
+
+        let epoch = env::block_timestamp_ms()
+            / Duration::from_secs(params.epoch_sec.max(1)).as_millis() as u64;
+        if epoch != self.keeper_reward_epoch {
+            self.keeper_reward_epoch = epoch;
+            self.keeper_rewards_epoch_paid_usd = 0;
+        }
+
+        let reward_usd = u128::min(
+            params.reward_usd,
+            params
+                .epoch_cap_usd
+                .saturating_sub(self.keeper_rewards_epoch_paid_usd),
+        );
+        let reward = u128::min(
+            asset.from_min_usd_price(reward_usd),
+            asset.withdrawable_fees(),
+        );
+        if reward == 0 {
+            return;
+        }
+        let reward_usd = asset.to_min_usd_price(reward);
+
+        asset.remove_fees(reward, FeeType::WithrawFee, keeper_id);
+        self.keeper_rewards_epoch_paid_usd += reward_usd;
+
+        let mut rewards = self.keeper_rewards.get(keeper_id).unwrap_or_default();
+        *rewards.claimable.entry(asset.asset_id.clone()).or_default() += reward;
+        rewards.total_earned_usd += reward_usd;
+        self.keeper_rewards.insert(keeper_id, &rewards);
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Send all claimable keeper rewards to the caller
+    pub fn claim_keeper_rewards(&mut self) {
+        let contract = self.contract_mut();
+        let keeper_id = env::predecessor_account_id();
+        let mut rewards = contract
+            .keeper_rewards
+            .get(&keeper_id)
+            .unwrap_or_else(|| env::panic_str("No keeper rewards to claim"));
+
+        for (asset_id, amount) in rewards.claimable.drain() {
+            let transfer_info = TransferInfo::new(&keeper_id, &asset_id, amount);
+            contract.internal_send(transfer_info, "claim_keeper_rewards");
+        }
+        contract.keeper_rewards.insert(&keeper_id, &rewards);
+    }
+
+    pub fn get_keeper_rewards(&self, account_id: AccountId) -> KeeperRewardsView {
+        let rewards = self
+            .contract()
+            .keeper_rewards
+            .get(&account_id)
+            .unwrap_or_default();
+        KeeperRewardsView {
+            claimable: rewards
+                .claimable
+                .into_iter()
+                .map(|(asset_id, amount)| (asset_id.into_string(), amount.into()))
+                .collect(),
+            total_earned_usd: rewards.total_earned_usd.into(),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 #![allow(clippy::ptr_offset_with_cast, clippy::assign_op_pattern)]
 #![deny(warnings)]
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{env, near_bindgen, AccountId, Balance, BorshStorageKey};
 use std::collections::{HashMap, HashSet};
@@ -13,10 +13,16 @@ mod admin;
 mod constants;
 mod events;
 mod fees;
+mod keeper;
 mod lp_token;
 mod oracle;
+mod order_storage;
+mod outflow;
 mod perps;
+mod price_impact;
+mod rebalance;
 mod referrals;
+mod staking;
 mod switchboard;
 mod token_receiver;
 mod token_transfer_history;
@@ -25,21 +31,29 @@ mod upgrade;
 mod util;
 mod vault;
 mod views;
+mod wnear;
 
 pub use actions::*;
 pub use admin::*;
 pub use constants::*;
 pub use events::*;
 pub use fees::*;
+pub use keeper::*;
 pub use lp_token::*;
 pub use oracle::*;
+pub use order_storage::*;
+pub use outflow::*;
 pub use perps::*;
+pub use price_impact::*;
+pub use rebalance::*;
 pub use referrals::*;
+pub use staking::*;
 pub use token_receiver::*;
 pub use token_transfer_history::*;
 pub use util::*;
 pub use vault::*;
 pub use views::*;
+pub use wnear::*;
 
 /// For example, 1.5x = 1500
 pub const LEVERAGE_MULTIPLIER: u16 = 1000;
@@ -62,6 +76,9 @@ pub const MAX_FEE_BPS: u16 = 500;
 /// Maximum allowed value for reward parameter.
 pub const MAX_LIQUIDATION_REWARD_USD: u128 = 100 * DOLLAR_DENOMINATION;
 
+/// Minimum delay before a scheduled price override can be applied. 10 minutes
+pub const MIN_PRICE_OVERRIDE_DELAY_SEC: u64 = 10 * 60;
+
 /// Denomination for funding rate, 10_000 = 1%.
 pub const FUNDING_RATE_PRECISION: u32 = 1_000_000;
 
@@ -86,16 +103,32 @@ pub enum StoragePrefix {
 
     LimitOrderIdsMap,
     LimitOrders,
+
+    KeeperRewards,
+    LpTokenLocks,
+    Stakers,
+    LpBaskets,
+    AccountOutflows,
+    WithdrawalQueue,
+    LpTokenStorage,
+    TwapOrders,
+    OrderStorageBalances,
+    /// Order books migrated from [VContract::V1]
+    LimitOrdersV2,
 }
 
 uint::construct_uint! {
     pub struct U256(4);
 }
 
+// Only one variant is ever loaded, V1 just before it is migrated
+#[allow(clippy::large_enum_variant)]
 #[near_bindgen]
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VContract {
-    V1(Contract),
+    /// State before [VContract::migrate], only readable by the migration
+    V1(upgrade::ContractV1),
+    V2(Contract),
 }
 
 /// Manual implementation of Default for VContract (PanicOnDefault only works on
@@ -122,7 +155,7 @@ pub struct FeeParameters {
     pub margin_fee_bps: u16,      // = 10; 0.1%
 }
 
-/// perps contract V1
+/// perps contract V2
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Contract {
     owner_id: AccountId,
@@ -145,6 +178,39 @@ pub struct Contract {
 
     lp_token: FungibleTokenFreeStorage,
 
+    /// Freshly minted LP tokens which can't be burned or transferred yet
+    lp_token_locks: LookupMap<AccountId, LpTokenLock>,
+
+    /// Cooldown after minting LP tokens. 0 disables it.
+    lp_cooldown_sec: u64,
+
+    /// Deposits waiting to be minted together
+    lp_baskets: LookupMap<AccountId, HashMap<AssetId, Balance>>,
+
+    /// LP tokens staked for a share of fees
+    staking: StakingPool,
+    /// Period over which staking fees are streamed to stakers
+    staking_reward_duration_sec: u64,
+
+    /// Limits on the USD value of LP withdrawals in a sliding window
+    outflow_limits: OutflowLimits,
+    outflow_history: TokenTransferHistory,
+    account_outflow_history: LookupMap<AccountId, TokenTransferHistory>,
+
+    /// LP withdrawals waiting for the outflow limits to allow them
+    withdrawal_queue: WithdrawalQueue,
+
+    rebalance_parameters: RebalanceParameters,
+    /// Admin rebalance waiting for the DEX swap to finish
+    pending_rebalance: Option<PendingRebalance>,
+
+    price_impact_parameters: PriceImpactParameters,
+
+    /// Wrapped NEAR token, accepted and paid out in place of NEAR
+    wnear_id: Option<AccountId>,
+    /// Wrapped NEAR held by the contract which still has to be unwrapped
+    pending_wnear_unwrap: Balance,
+
     position_ids_map: UnorderedMap<AccountId, HashSet<PositionId>>,
 
     positions: UnorderedMap<PositionId, Position>,
@@ -152,6 +218,14 @@ pub struct Contract {
     limit_order_ids_map: UnorderedMap<AccountId, HashMap<LimitOrderId, AssetId>>,
     limit_orders: UnorderedMap<AssetId, LimitOrders>,
     limit_order_sequence: u64,
+    /// Limits on the number of open limit orders of an account
+    limit_order_limits: LimitOrderLimits,
+    /// NEAR deposited by accounts to pay for the storage of their orders
+    order_storage_balances: LookupMap<AccountId, Balance>,
+
+    /// Orders increasing a position in slices over time
+    twap_orders: UnorderedMap<TwapOrderId, TwapOrder>,
+    twap_order_sequence: u64,
 
     referral_code_owners: UnorderedMap<String, (AccountId, ReferrerTier)>,
     user_referral_code: UnorderedMap<AccountId, String>,
@@ -196,8 +270,18 @@ pub struct Contract {
     // Do we allow limit orders
     limit_orders_state: LimitOrdersState,
 
+    // Anyone can execute limit orders with an execution fee, not only
+    // liquidators
+    permissionless_limit_order_execution: bool,
+
     fee_parameters: FeeParameters,
 
+    /// How collected fees are split between the treasury, LPs and stakers
+    fee_split_parameters: FeeSplitParameters,
+
+    /// Receives fees withdrawn with `withdraw_fees`
+    treasury_id: AccountId,
+
     // if true only allow "managers" to buy/sell TLP
     manager_mode: bool,
 
@@ -221,18 +305,35 @@ pub struct Contract {
     max_staleness_duration_sec: u64,
 
     max_limit_order_life_sec: u64,
+
+    // Minimum time between two Switchboard queries of the same asset.
+    switchboard_refresh_interval_sec: u64,
+
+    // Delay before an admin price override can be applied.
+    price_override_delay_sec: u64,
+
+    keeper_reward_parameters: KeeperRewardParameters,
+    keeper_rewards: UnorderedMap<AccountId, KeeperRewards>,
+    // Current keeper reward epoch and the rewards paid in it, in USD.
+    keeper_reward_epoch: u64,
+    keeper_rewards_epoch_paid_usd: DollarBalance,
+
+    /// State of [VContract::V1] which still has to be converted
+    pending_migration: Option<upgrade::PendingMigration>,
 }
 
 impl VContract {
     pub fn contract(&self) -> &Contract {
         match self {
-            Self::V1(contract) => contract,
+            Self::V2(contract) => contract,
+            Self::V1(_) => env::panic_str("The contract is not migrated"),
         }
     }
 
     pub fn contract_mut(&mut self) -> &mut Contract {
         match self {
-            Self::V1(contract) => contract,
+            Self::V2(contract) => contract,
+            Self::V1(_) => env::panic_str("The contract is not migrated"),
         }
     }
 }
@@ -246,8 +347,8 @@ impl VContract {
         let mut admins = UnorderedMap::new(StoragePrefix::Admins);
         admins.insert(&owner_id, &AdminRole::FullAdmin);
 
-        Self::V1(Contract {
-            owner_id,
+        Self::V2(Contract {
+            owner_id: owner_id.clone(),
             state: ContractState::Paused,
 
             position_ids_map: UnorderedMap::new(StoragePrefix::PositionIdsMap),
@@ -256,6 +357,14 @@ impl VContract {
             limit_order_ids_map: UnorderedMap::new(StoragePrefix::LimitOrderIdsMap),
             limit_orders: UnorderedMap::new(StoragePrefix::LimitOrders),
             limit_order_sequence: 0,
+            limit_order_limits: LimitOrderLimits {
+                max_orders_per_account: 100,
+                max_orders_per_asset: 20,
+            },
+            order_storage_balances: LookupMap::new(StoragePrefix::OrderStorageBalances),
+
+            twap_orders: UnorderedMap::new(StoragePrefix::TwapOrders),
+            twap_order_sequence: 0,
 
             liquidators: UnorderedSet::new(StoragePrefix::Liquidators),
             admins,
@@ -266,7 +375,24 @@ impl VContract {
             user_referral_code: UnorderedMap::new(StoragePrefix::UserReferralCodes),
 
             assets: AssetsMap(HashMap::new()),
-            lp_token: FungibleTokenFreeStorage::new(StoragePrefix::LpToken),
+            lp_token: FungibleTokenFreeStorage::new(
+                StoragePrefix::LpToken,
+                StoragePrefix::LpTokenStorage,
+            ),
+            lp_token_locks: LookupMap::new(StoragePrefix::LpTokenLocks),
+            lp_cooldown_sec: 0,
+            lp_baskets: LookupMap::new(StoragePrefix::LpBaskets),
+            staking: StakingPool::new(StoragePrefix::Stakers),
+            staking_reward_duration_sec: 60 * 60 * 24 * 7,
+            outflow_limits: OutflowLimits::default(),
+            outflow_history: TokenTransferHistory::default(),
+            account_outflow_history: LookupMap::new(StoragePrefix::AccountOutflows),
+            withdrawal_queue: WithdrawalQueue::new(StoragePrefix::WithdrawalQueue),
+            rebalance_parameters: RebalanceParameters::default(),
+            pending_rebalance: None,
+            price_impact_parameters: PriceImpactParameters::default(),
+            wnear_id: None,
+            pending_wnear_unwrap: 0,
 
             total_weights: 0,
 
@@ -281,6 +407,7 @@ impl VContract {
             swap_enabled: true,
             leverage_enabled: true,
             limit_orders_state: LimitOrdersState::Enabled,
+            permissionless_limit_order_execution: false,
 
             manager_mode: false,
             private_liquidation_only: true,
@@ -296,6 +423,8 @@ impl VContract {
                 stable_swap_fee_bps: 4,
                 margin_fee_bps: 10,
             },
+            fee_split_parameters: FeeSplitParameters::default(),
+            treasury_id: owner_id,
 
             min_profit_time_seconds: 60,
 
@@ -304,6 +433,15 @@ impl VContract {
             max_staleness_duration_sec: 90,
 
             max_limit_order_life_sec: 60 * 60 * 24 * 30,
+            switchboard_refresh_interval_sec: 30,
+            price_override_delay_sec: 60 * 60,
+
+            keeper_reward_parameters: Default::default(),
+            keeper_rewards: UnorderedMap::new(StoragePrefix::KeeperRewards),
+            keeper_reward_epoch: 0,
+            keeper_rewards_epoch_paid_usd: 0,
+
+            pending_migration: None,
         })
     }
 }
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+use tonic_perps_sdk::prelude::FeeType;
+
+use crate::{
+    emit_event, env, near_bindgen, ratio, AccountId, Asset, AssetId, AumPrice, Balance, Contract,
+    DollarBalance, EventType, LpSupportState, MintBurnDirection, MintBurnLpEvent, TransferInfo,
+    VContract, VContractExt, WithdrawalOutput,
+};
+
+impl Contract {
+    /// Add a deposit to the account's LP basket. Deposits are minted together
+    /// with [VContract::mint_lp_basket]. The account pays for the storage of
+    /// the basket with its order storage balance.
+    pub fn add_to_lp_basket(
+        &mut self,
+        account_id: &AccountId,
+        asset_id: &AssetId,
+        amount: Balance,
+    ) {
+        assert!(amount > 0, "Deposit amount should be positive");
+        let asset = self.assets.unwrap(asset_id);
+        assert!(asset.state.lp_support.check(LpSupportState::Enabled));
+
+        let initial_storage_usage = env::storage_usage();
+        let mut basket = self.lp_baskets.get(account_id).unwrap_or_default();
+        *basket.entry(asset_id.clone()).or_default() += amount;
+        self.lp_baskets.insert(account_id, &basket);
+        self.charge_storage(account_id, initial_storage_usage);
+    }
+
+    /// Remove the account's LP basket and credit back its storage
+    fn remove_lp_basket(&mut self, account_id: &AccountId) -> HashMap<AssetId, Balance> {
+        let initial_storage_usage = env::storage_usage();
+        let basket = self
+            .lp_baskets
+            .remove(account_id)
+            .unwrap_or_else(|| env::panic_str("LP basket is empty"));
+        self.refund_storage(
+            account_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+        basket
+    }
+
+    pub fn get_lp_basket(&self, account_id: &AccountId) -> HashMap<AssetId, Balance> {
+        self.lp_baskets.get(account_id).unwrap_or_default()
+    }
+
+    /// Assets paid out by in-kind LP burns. Close-only and depegged assets
+    /// are left in the pool, since their price can't be relied on.
+    pub fn get_in_kind_assets(&self) -> Vec<(AssetId, Asset)> {
+        self.get_assets()
+            .into_iter()
+            .filter(|(_, asset)| {
+                asset.pool_balance > 0
+                    && !asset.state.lp_support.check(LpSupportState::Disabled)
+                    && !asset.is_close_only()
+                    && !asset.is_depegged()
+            })
+            .collect()
+    }
+
+    /// USD value redeemed in kind by burning `burn_amount` LP tokens: the
+    /// share of the AUM of the in-kind `assets` only. The share of the other
+    /// assets stays in the pool for the remaining LPs.
+    pub fn get_in_kind_redemption_usd(
+        &self,
+        assets: &[(AssetId, Asset)],
+        burn_amount: Balance,
+    ) -> DollarBalance {
+        let assets_aum: DollarBalance = assets
+            .iter()
+            .map(|(_, asset)| self.get_asset_aum(asset, AumPrice::Min).aum)
+            .sum();
+        ratio(assets_aum, burn_amount, self.lp_token.total_supply)
+    }
+
+    /// Mint LP tokens for all assets in the account's basket at the same LP
+    /// price.
+    pub fn mint_lp_basket(&mut self, account_id: &AccountId, min_out: Option<Balance>) -> Balance {
+        self.assert_no_pending_rebalance();
+        self.lp_token.assert_registered(account_id);
+        let basket = self.remove_lp_basket(account_id);
+
+        let total_aum = self.get_aum(AumPrice::Max);
+        let prev_supply = self.lp_token.total_supply;
+
+        let deposits: Vec<_> = basket
+            .into_iter()
+            .map(|(asset_id, deposit)| {
+                let lp_deposit =
+                    self.deposit_lp_asset(account_id, &asset_id, deposit, total_aum, prev_supply);
+                (asset_id, deposit, lp_deposit)
+            })
+            .collect();
+
+        let mint_amount = deposits.iter().map(|(_, _, d)| d.mint_amount).sum();
+        if let Some(min_out) = min_out {
+            assert!(mint_amount >= min_out, "exceeded slippage tolerance");
+        }
+
+        self.lp_token.internal_deposit(account_id, mint_amount);
+        self.lock_lp_tokens(account_id, mint_amount);
+
+        for (asset_id, deposit, lp_deposit) in deposits {
+            self.emit_mint_lp_event(account_id, &asset_id, deposit, &lp_deposit);
+        }
+        mint_amount
+    }
+
+    /// Burn LP tokens for a share of every asset in the pool, in proportion to
+    /// the pool composition (see [Contract::get_in_kind_assets]). The share of
+    /// the assets which aren't paid out stays in the pool. Only the base burn
+    /// fee is charged, since the redemption doesn't change the pool weights. If the burn exceeds the
+    /// outflow limits, the LP tokens are queued instead and nothing is
+    /// returned.
+    ///
+    /// Return amounts of assets redeemed. It's the caller's responsibility to
+    /// send them back to the user.
+    #[must_use]
+    pub fn burn_lp_token_in_kind(
+        &mut self,
+        account_id: &AccountId,
+        burn_amount: Balance,
+        min_out_usd: Option<DollarBalance>,
+    ) -> Vec<TransferInfo> {
+        assert!(burn_amount > 0, "Burn amount should be positive");
+        self.assert_lp_tokens_unlocked(account_id, burn_amount);
+
+        let assets = self.get_in_kind_assets();
+        let redemption_usd = self.get_in_kind_redemption_usd(&assets, burn_amount);
+        if !self.check_outflow_limits(account_id, redemption_usd) {
+            self.queue_withdrawal(
+                account_id,
+                burn_amount,
+                WithdrawalOutput::InKind,
+                min_out_usd,
+            );
+            return vec![];
+        }
+
+        for (asset_id, _) in &assets {
+            self.validate_asset_price(asset_id);
+        }
+
+        let prev_price = self.lp_price();
+        let pool_usd: DollarBalance = assets
+            .iter()
+            .map(|(_, asset)| asset.dollar_value_of(asset.pool_balance))
+            .sum();
+        assert!(pool_usd > 0, "Pool is empty");
+        if let Some(min_out_usd) = min_out_usd {
+            assert!(redemption_usd >= min_out_usd, "exceeded slippage tolerance");
+        }
+
+        self.register_outflow(account_id, redemption_usd);
+        self.lp_token.internal_withdraw(account_id, burn_amount);
+
+        let fee_bps = self.fee_parameters.mint_burn_fee_bps;
+        let mut transfers = vec![];
+        for (asset_id, mut asset) in assets {
+            // Each asset pays out its share of the redemption value by pool value
+            let redemption_amount = ratio(asset.pool_balance, redemption_usd, pool_usd);
+            if redemption_amount == 0 {
+                continue;
+            }
+            assert!(
+                redemption_amount <= asset.available_liquidity(),
+                "Not enough liquidity to burn tokens {} {}",
+                redemption_amount,
+                asset.available_liquidity()
+            );
+            let burn_share = ratio(
+                burn_amount,
+                asset.dollar_value_of(asset.pool_balance),
+                pool_usd,
+            );
+
+            asset.register_withdrawal(redemption_amount);
+            let (redemption_amount_after_fees, fees) =
+                self.withhold_fees(redemption_amount, fee_bps);
+
+            asset.remove_liquidity(redemption_amount, account_id);
+            self.collect_fees(&mut asset, fees, FeeType::Burn, account_id);
+            asset.check_available_liquidity();
+
+            self.update_cumulative_funding_rate(&mut asset);
+            let fees_usd = asset.dollar_value_of(fees);
+            self.set_asset(&asset_id, asset);
+
+            emit_event(EventType::MintBurnLp(MintBurnLpEvent {
+                direction: MintBurnDirection::Burn,
+                account_id: account_id.clone(),
+                amount_in: burn_share.into(),
+                token_in: env::current_account_id().to_string(),
+                token_out: asset_id.into_string(),
+                amount_out: redemption_amount.into(),
+                fees: fees.into(),
+                fees_usd: fees_usd.into(),
+                fees_bps: fee_bps.into(),
+                lp_price_usd: prev_price.into(),
+            }));
+
+            transfers.push(TransferInfo::new(
+                account_id,
+                &asset_id,
+                redemption_amount_after_fees,
+            ));
+        }
+        transfers
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Mint LP tokens for all deposits in the caller's basket, plus attached
+    /// NEAR. Fungible tokens are added to the basket with `ft_transfer_call`
+    /// and the `AddToLpBasket` action.
+    #[payable]
+    pub fn mint_lp_basket(&mut self, min_out: Option<U128>, referrer_id: Option<String>) -> U128 {
+        self.contract_mut().assert_running();
+        if let Some(referrer_id) = referrer_id {
+            self.set_user_referral_code(referrer_id);
+        }
+        let contract = self.contract_mut();
+        let account_id = env::predecessor_account_id();
+        if env::attached_deposit() > 0 {
+            contract.add_to_lp_basket(&account_id, &AssetId::NEAR, env::attached_deposit());
+        }
+        contract
+            .mint_lp_basket(&account_id, min_out.map(|a| a.0))
+            .into()
+    }
+
+    /// Send back all deposits in the caller's basket
+    pub fn withdraw_lp_basket(&mut self) {
+        let contract = self.contract_mut();
+        let account_id = env::predecessor_account_id();
+        let basket = contract.remove_lp_basket(&account_id);
+        for (asset_id, amount) in basket {
+            let transfer_info = TransferInfo::new(&account_id, &asset_id, amount);
+            contract.internal_send(transfer_info, "withdraw_lp_basket");
+        }
+    }
+
+    pub fn get_lp_basket(&self, account_id: AccountId) -> HashMap<String, U128> {
+        self.contract()
+            .get_lp_basket(&account_id)
+            .into_iter()
+            .map(|(asset_id, amount)| (asset_id.into_string(), amount.into()))
+            .collect()
+    }
+
+    /// Burn LP tokens for a proportional share of every pool asset
+    #[payable]
+    pub fn burn_lp_token_in_kind(
+        &mut self,
+        amount: U128,
+        min_out_usd: Option<U128>,
+        referrer_id: Option<String>,
+    ) -> HashMap<String, U128> {
+        self.contract_mut().assert_running();
+        if let Some(referrer_id) = referrer_id {
+            self.set_user_referral_code(referrer_id);
+        }
+        let contract = self.contract_mut();
+        let account_id = env::predecessor_account_id();
+        let transfers =
+            contract.burn_lp_token_in_kind(&account_id, amount.0, min_out_usd.map(|a| a.0));
+        let mut amounts = HashMap::new();
+        for transfer_info in transfers {
+            amounts.insert(
+                transfer_info.asset_id().into_string(),
+                transfer_info.amount().into(),
+            );
+            contract.internal_send(transfer_info, "burn_lp_token_in_kind");
+        }
+        amounts
+    }
+}
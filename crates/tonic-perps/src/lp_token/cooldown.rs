@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use crate::{
+    borsh, env, near_bindgen, u128_dec_format, AccountId, Balance, BorshDeserialize,
+    BorshSerialize, Contract, Serialize, VContract, VContractExt,
+};
+
+/// LP tokens minted recently by an account. They can't be burned or
+/// transferred until the cooldown after the last mint has passed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LpTokenLock {
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    pub unlock_timestamp_ms: u64,
+}
+
+impl Contract {
+    /// Lock freshly minted LP tokens. Minting again before the unlock time adds
+    /// to the locked amount and restarts the cooldown.
+    pub fn lock_lp_tokens(&mut self, account_id: &AccountId, amount: Balance) {
+        if self.lp_cooldown_sec == 0 {
+            return;
+        }
+        let locked_amount = self.get_locked_lp_amount(account_id);
+        self.lp_token_locks.insert(
+            account_id,
+            &LpTokenLock {
+                amount: locked_amount + amount,
+                unlock_timestamp_ms: env::block_timestamp_ms()
+                    + Duration::from_secs(self.lp_cooldown_sec).as_millis() as u64,
+            },
+        );
+    }
+
+    /// Return the current lock of an account, if any
+    pub fn get_lp_token_lock(&self, account_id: &AccountId) -> Option<LpTokenLock> {
+        self.lp_token_locks
+            .get(account_id)
+            .filter(|lock| lock.unlock_timestamp_ms > env::block_timestamp_ms())
+    }
+
+    pub fn get_locked_lp_amount(&self, account_id: &AccountId) -> Balance {
+        self.get_lp_token_lock(account_id)
+            .map_or(0, |lock| lock.amount)
+    }
+
+    /// Panics if `amount` exceeds the account's unlocked LP token balance
+    pub fn assert_lp_tokens_unlocked(&self, account_id: &AccountId, amount: Balance) {
+        if let Some(lock) = self.get_lp_token_lock(account_id) {
+            let balance = self.lp_token.internal_unwrap_balance_of(account_id);
+            if balance.saturating_sub(lock.amount) < amount {
+                env::panic_str(&format!(
+                    "LP tokens are locked until {}",
+                    lock.unlock_timestamp_ms
+                ));
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Returns the amount of recently minted LP tokens of an account and the
+    /// time they unlock, or nothing if no tokens are locked.
+    pub fn get_lp_token_lock(&self, account_id: AccountId) -> Option<LpTokenLock> {
+        self.contract().get_lp_token_lock(&account_id)
+    }
+}
@@ -22,7 +22,7 @@ const MIN_TRANSFER_AMOUNT: u128 = 1_000_000_000_000_000;
 impl FungibleTokenCore for FungibleTokenFreeStorage {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
         assert_one_yocto();
-        self.assert_min_transfer_amount(&receiver_id, amount);
+        self.assert_receiver(&receiver_id, amount);
         let sender_id = env::predecessor_account_id();
         let amount: Balance = amount.into();
         self.internal_transfer(&sender_id, &receiver_id, amount, memo);
@@ -36,7 +36,7 @@ impl FungibleTokenCore for FungibleTokenFreeStorage {
         msg: String,
     ) -> PromiseOrValue<U128> {
         assert_one_yocto();
-        self.assert_min_transfer_amount(&receiver_id, amount);
+        self.assert_receiver(&receiver_id, amount);
 
         require!(
             env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL,
@@ -115,8 +115,10 @@ impl FungibleTokenFreeStorage {
         amount
     }
 
-    fn assert_min_transfer_amount(&self, receiver_id: &AccountId, amount: U128) {
-        if self.accounts.get(receiver_id).is_none() {
+    fn assert_receiver(&self, receiver_id: &AccountId, amount: U128) {
+        if !self.free_storage {
+            self.assert_registered(receiver_id);
+        } else if self.accounts.get(receiver_id).is_none() {
             assert!(
                 amount.0 >= MIN_TRANSFER_AMOUNT,
                 "Requires min 0.001GIN to transfer as receiver is not registered"
@@ -132,9 +134,9 @@ impl FungibleTokenFreeStorage {
 impl FungibleTokenCore for VContract {
     #[payable]
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
-        self.contract_mut()
-            .lp_token
-            .ft_transfer(receiver_id, amount, memo);
+        let contract = self.contract_mut();
+        contract.assert_lp_tokens_unlocked(&env::predecessor_account_id(), amount.0);
+        contract.lp_token.ft_transfer(receiver_id, amount, memo);
     }
 
     #[payable]
@@ -145,7 +147,9 @@ impl FungibleTokenCore for VContract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        self.contract_mut()
+        let contract = self.contract_mut();
+        contract.assert_lp_tokens_unlocked(&env::predecessor_account_id(), amount.0);
+        contract
             .lp_token
             .ft_transfer_call(receiver_id, amount, memo, msg)
     }
@@ -2,11 +2,20 @@ use near_sdk::json_types::U128;
 use tonic_perps_sdk::prelude::FeeType;
 
 use crate::{
-    convert_assets, emit_event, env, near_bindgen, ratio, AccountId, AssetId, Balance, Contract,
-    DollarBalance, EventType, LpSupportState, MintBurnDirection, MintBurnLpEvent, TransferInfo,
-    VContract, VContractExt, DOLLAR_DENOMINATION, LP_TOKEN_DENOMINATION,
+    convert_assets, emit_event, env, near_bindgen, ratio, AccountId, AssetId, AumPrice, Balance,
+    Contract, DollarBalance, EventType, LpSupportState, MintBurnDirection, MintBurnLpEvent,
+    TransferInfo, VContract, VContractExt, WithdrawalOutput, DOLLAR_DENOMINATION,
+    LP_TOKEN_DENOMINATION,
 };
 
+/// Result of depositing one asset into the pool to mint LP tokens
+pub struct LpDeposit {
+    pub mint_amount: Balance,
+    pub fees: Balance,
+    pub fees_usd: DollarBalance,
+    pub fee_bps: u16,
+}
+
 impl Contract {
     /// Mint LP tokens. Increase liquidity in the pool.
     pub fn mint_lp_token(
@@ -16,56 +25,96 @@ impl Contract {
         deposit: Balance,
         min_out: Option<Balance>,
     ) -> Balance {
+        self.assert_no_pending_rebalance();
+        self.lp_token.assert_registered(account_id);
+        let total_aum = self.get_aum(AumPrice::Max); // XXX: io
+        let prev_supply = self.lp_token.total_supply;
+
+        let lp_deposit =
+            self.deposit_lp_asset(account_id, asset_id, deposit, total_aum, prev_supply);
+        let mint_amount = lp_deposit.mint_amount;
+        if let Some(min_out) = min_out {
+            assert!(mint_amount >= min_out, "exceeded slippage tolerance");
+        }
+
+        self.lp_token.internal_deposit(account_id, mint_amount);
+        self.lock_lp_tokens(account_id, mint_amount);
+
+        self.emit_mint_lp_event(account_id, asset_id, deposit, &lp_deposit);
+        mint_amount
+    }
+
+    /// Add a deposit to the pool and return the amount of LP tokens to mint
+    /// for it. `total_aum` and `prev_supply` are taken before any deposit, so
+    /// several assets can be deposited at the same LP price. `total_aum`
+    /// should be valued at max prices, the deposit is valued at the min price.
+    pub fn deposit_lp_asset(
+        &mut self,
+        account_id: &AccountId,
+        asset_id: &AssetId,
+        deposit: Balance,
+        total_aum: DollarBalance,
+        prev_supply: Balance,
+    ) -> LpDeposit {
         self.validate_asset_price(asset_id);
         assert!(deposit > 0, "Deposit amount should be positive");
 
-        let total_aum = self.get_total_aum(); // XXX: io
-        let prev_supply = self.lp_token.total_supply;
-
         let mut asset = self.assets.unwrap(asset_id);
         assert!(asset.state.lp_support.check(LpSupportState::Enabled));
+        asset.assert_not_close_only();
+        asset.assert_pegged();
 
-        let mint_fee_bps = self.get_mint_fee_bps(&asset, deposit);
-        let (after_fee_amount, fees) = self.withhold_fees(deposit, mint_fee_bps);
+        let fee_bps = self.get_mint_fee_bps(&asset, deposit);
+        let (after_fee_amount, fees) = self.withhold_fees(deposit, fee_bps);
 
-        asset.add_fees(fees, FeeType::Mint, account_id);
+        self.collect_fees(&mut asset, fees, FeeType::Mint, account_id);
         asset.add_liquidity(after_fee_amount, account_id);
         let fees_usd = asset.dollar_value_of(fees);
         self.update_cumulative_funding_rate(&mut asset);
 
         let mint_amount = get_lp_mint_amount(
             total_aum,
-            asset.price,
+            asset.min_price(),
             asset.denomination(),
             after_fee_amount,
             prev_supply,
         );
-        if let Some(min_out) = min_out {
-            assert!(mint_amount >= min_out, "exceeded slippage tolerance");
-        }
 
         asset.register_deposit(mint_amount);
-
-        self.lp_token.internal_deposit(account_id, mint_amount);
         self.set_asset(asset_id, asset);
 
+        LpDeposit {
+            mint_amount,
+            fees,
+            fees_usd,
+            fee_bps,
+        }
+    }
+
+    pub fn emit_mint_lp_event(
+        &self,
+        account_id: &AccountId,
+        asset_id: &AssetId,
+        deposit: Balance,
+        lp_deposit: &LpDeposit,
+    ) {
         emit_event(EventType::MintBurnLp(MintBurnLpEvent {
             direction: MintBurnDirection::Mint,
             account_id: account_id.clone(),
             token_in: asset_id.into_string(),
             amount_in: deposit.into(),
             token_out: env::current_account_id().to_string(),
-            amount_out: mint_amount.into(),
-            fees: fees.into(),
-            fees_usd: fees_usd.into(),
-            fees_bps: mint_fee_bps.into(),
+            amount_out: lp_deposit.mint_amount.into(),
+            fees: lp_deposit.fees.into(),
+            fees_usd: lp_deposit.fees_usd.into(),
+            fees_bps: lp_deposit.fee_bps.into(),
             lp_price_usd: self.lp_price().into(),
         }));
-        mint_amount
     }
 
     /// Redeem some amount of the LP token for an asset. Decrease liquidity in
-    /// the pool and burn the LP token.
+    /// the pool and burn the LP token. If the burn exceeds the outflow limits,
+    /// the LP tokens are queued instead and 0 is returned.
     ///
     /// Return amount of asset redeemed. It's the caller's responsibility to
     /// send this amount from the treasury back to the user.
@@ -80,14 +129,30 @@ impl Contract {
         self.validate_asset_price(output_asset_id);
         let mut asset = self.assets.unwrap(output_asset_id);
         assert!(!asset.state.lp_support.check(LpSupportState::Disabled));
+        asset.assert_not_close_only();
+
+        self.assert_lp_tokens_unlocked(account_id, burn_amount);
 
         let prev_supply = self.lp_token.total_supply;
         let prev_price = self.lp_price();
+        let total_aum = self.get_aum(AumPrice::Min);
+        let amount_usd = ratio(total_aum, burn_amount, prev_supply);
+        if !self.check_outflow_limits(account_id, amount_usd) {
+            self.queue_withdrawal(
+                account_id,
+                burn_amount,
+                WithdrawalOutput::Asset(output_asset_id.clone()),
+                min_out,
+            );
+            return 0;
+        }
+        self.register_outflow(account_id, amount_usd);
         self.lp_token.internal_withdraw(account_id, burn_amount);
 
+        // Value the pool at min prices and the redeemed asset at its max price
         let redemption_amount = get_lp_redemption_amount(
-            self.get_total_aum(),
-            asset.price,
+            total_aum,
+            asset.max_price(),
             asset.denomination(),
             burn_amount,
             prev_supply,
@@ -110,7 +175,7 @@ impl Contract {
             self.withhold_fees(redemption_amount, burn_fee_bps);
 
         asset.remove_liquidity(redemption_amount, account_id);
-        asset.add_fees(fees, FeeType::Burn, account_id);
+        self.collect_fees(&mut asset, fees, FeeType::Burn, account_id);
         asset.check_available_liquidity();
 
         self.update_cumulative_funding_rate(&mut asset);
@@ -134,12 +199,17 @@ impl Contract {
     }
 
     pub fn lp_price(&self) -> DollarBalance {
+        self.lp_price_at(AumPrice::Index)
+    }
+
+    /// LP token price with pool assets valued at `price`
+    pub fn lp_price_at(&self, price: AumPrice) -> DollarBalance {
         assert!(
             self.lp_token.total_supply > 0,
             "Price as unavailable due to lp supply absence"
         );
         ratio(
-            self.get_total_aum(),
+            self.get_aum(price),
             LP_TOKEN_DENOMINATION,
             self.lp_token.total_supply,
         )
@@ -165,6 +235,8 @@ impl VContract {
             .into()
     }
 
+    /// Burn LP tokens for `output_token_id`. If the burn exceeds the outflow
+    /// limits, the LP tokens are queued instead and 0 is returned.
     #[payable]
     pub fn burn_lp_token(
         &mut self,
@@ -178,11 +250,25 @@ impl VContract {
             self.set_user_referral_code(referrer_id);
         }
         let contract = self.contract_mut();
-        let asset_id = &AssetId::from(output_token_id);
+        let output_id = &AssetId::from(output_token_id);
+        let asset_id = &contract.pool_asset_id(output_id);
         let account_id = env::predecessor_account_id();
-        let balance = contract.burn_lp_token(&account_id, amount.0, asset_id, min_out.map(|a| a.0));
 
-        let transfer_info = TransferInfo::new(&account_id, asset_id, balance);
+        let min_out = min_out.map(|a| a.0);
+        let amount_usd = contract.get_lp_redemption_usd(amount.0);
+        if !contract.check_outflow_limits(&account_id, amount_usd) {
+            contract.queue_withdrawal(
+                &account_id,
+                amount.0,
+                WithdrawalOutput::Asset(output_id.clone()),
+                min_out,
+            );
+            return 0.into();
+        }
+
+        let balance = contract.burn_lp_token(&account_id, amount.0, asset_id, min_out);
+
+        let transfer_info = TransferInfo::new(&account_id, output_id, balance);
         contract.internal_send(transfer_info, "burn_lp_token");
 
         balance.into()
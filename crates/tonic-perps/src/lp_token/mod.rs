@@ -1,12 +1,16 @@
+mod basket;
+mod cooldown;
 mod ft;
 mod mint;
 mod storage;
+mod zap;
 
 use crate::{borsh, env, AccountId, Balance, BorshDeserialize, BorshSerialize};
+pub use cooldown::*;
 pub use ft::*;
 pub use mint::*;
 use near_contract_standards::fungible_token::events::FtTransfer;
-use near_sdk::{collections::LookupMap, json_types::U128, require, IntoStorageKey};
+use near_sdk::{collections::LookupMap, json_types::U128, require, IntoStorageKey, StorageUsage};
 
 /// Implementation of a FungibleToken standard.
 /// https://docs.rs/near-contract-standards/latest/src/near_contract_standards/fungible_token/core_impl.rs.html
@@ -24,17 +28,44 @@ pub struct FungibleTokenFreeStorage {
 
     /// Total supply of the all token.
     pub total_supply: Balance,
+
+    /// AccountID -> NEAR deposited for storage. Only used when `free_storage`
+    /// is false.
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+
+    /// Storage used by one account, paid by new holders.
+    pub account_storage_usage: StorageUsage,
+
+    /// If true, accounts are registered for free and transfers to accounts
+    /// without a balance require a minimum amount.
+    pub free_storage: bool,
 }
 
 impl FungibleTokenFreeStorage {
-    pub fn new<S>(prefix: S) -> Self
+    pub fn new<S, T>(prefix: S, storage_prefix: T) -> Self
     where
         S: IntoStorageKey,
+        T: IntoStorageKey,
     {
-        Self {
+        let mut this = Self {
             accounts: LookupMap::new(prefix),
             total_supply: 0,
-        }
+            storage_deposits: LookupMap::new(storage_prefix),
+            account_storage_usage: 0,
+            free_storage: true,
+        };
+        this.measure_account_storage_usage();
+        this
+    }
+
+    pub(crate) fn measure_account_storage_usage(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        self.accounts.insert(&tmp_account_id, &0u128);
+        self.storage_deposits.insert(&tmp_account_id, &0u128);
+        self.account_storage_usage = env::storage_usage() - initial_storage_usage;
+        self.accounts.remove(&tmp_account_id);
+        self.storage_deposits.remove(&tmp_account_id);
     }
 
     pub fn internal_unwrap_balance_of(&self, account_id: &AccountId) -> Balance {
@@ -1,39 +1,177 @@
-use crate::{near_bindgen, AccountId, VContract, VContractExt};
+use crate::{near_bindgen, AccountId, Balance, FungibleTokenFreeStorage, VContract, VContractExt};
 
-use near_contract_standards::storage_management::StorageBalance;
-use near_sdk::{env, Promise};
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, log, Promise};
 
 /// 0.125 NEAR
 const DEFAULT_STORAGE_BALANCE: u128 = 125_000_000_000_000_000_000_000; // 125m
 
-#[near_bindgen]
-impl VContract {
-    /// Always returns 125 milliNEAR indicating that user doesn't need to be registered.
-    /// It's a workaround for integrations required NEP-125 storage compatibility.
-    #[allow(unused_variables)]
-    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+impl FungibleTokenFreeStorage {
+    /// NEAR required to register an account
+    pub fn storage_cost(&self) -> Balance {
+        if self.free_storage {
+            0
+        } else {
+            Balance::from(self.account_storage_usage) * env::storage_byte_cost()
+        }
+    }
+
+    /// Accounts holding tokens are registered, as they may have received
+    /// them while storage was free.
+    pub fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.free_storage
+            || *account_id == env::current_account_id()
+            || self.storage_deposits.contains_key(account_id)
+            || self.accounts.contains_key(account_id)
+    }
+
+    pub fn assert_registered(&self, account_id: &AccountId) {
+        if !self.is_registered(account_id) {
+            env::panic_str(&format!("The account {} is not registered", account_id));
+        }
+    }
+
+    pub fn internal_storage_balance_of(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        if self.free_storage {
+            return Some(StorageBalance {
+                total: DEFAULT_STORAGE_BALANCE.into(),
+                available: 0.into(),
+            });
+        }
+        if !self.is_registered(account_id) {
+            return None;
+        }
         Some(StorageBalance {
-            total: DEFAULT_STORAGE_BALANCE.into(),
+            total: self.storage_deposits.get(account_id).unwrap_or(0).into(),
             available: 0.into(),
         })
     }
 
-    /// Mock API method as current token implementation excludes storage tracking,
-    /// refund NEAR if it was attached.
+    /// Register the account if it isn't yet. Return the amount of attached
+    /// NEAR to refund.
+    pub fn internal_storage_deposit(&mut self, account_id: &AccountId, amount: Balance) -> Balance {
+        if self.free_storage {
+            return amount;
+        }
+        if self.storage_deposits.contains_key(account_id) {
+            log!("The account is already registered, refunding the deposit");
+            return amount;
+        }
+        let storage_cost = self.storage_cost();
+        if amount < storage_cost {
+            env::panic_str("The attached deposit is less than the minimum storage balance");
+        }
+        self.storage_deposits.insert(account_id, &storage_cost);
+        amount - storage_cost
+    }
+
+    /// Unregister the account and return its storage deposit. With `force`,
+    /// remaining tokens of the account are burned.
+    pub fn internal_storage_unregister(
+        &mut self,
+        account_id: &AccountId,
+        force: bool,
+    ) -> Option<Balance> {
+        let storage_deposit = self.storage_deposits.get(account_id)?;
+        let balance = self.internal_unwrap_balance_of(account_id);
+        if balance > 0 {
+            if !force {
+                env::panic_str(
+                    "Can't unregister the account with the positive balance without force",
+                );
+            }
+            self.internal_withdraw(account_id, balance);
+            FtBurn {
+                owner_id: account_id,
+                amount: &U128(balance),
+                memo: Some("storage_unregister"),
+            }
+            .emit();
+        }
+        self.storage_deposits.remove(account_id);
+        Some(storage_deposit)
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for VContract {
+    /// Registers the account for the LP token. When storage is free, nothing
+    /// is charged and attached NEAR is refunded. Only the minimum balance is
+    /// accepted, so `registration_only` has no effect.
     #[allow(unused_variables)]
     #[payable]
-    pub fn storage_deposit(
+    fn storage_deposit(
         &mut self,
         account_id: Option<AccountId>,
         registration_only: Option<bool>,
     ) -> StorageBalance {
-        let amount = env::attached_deposit();
-        if amount > 0 {
-            Promise::new(env::predecessor_account_id()).transfer(amount);
-        };
-        StorageBalance {
-            total: DEFAULT_STORAGE_BALANCE.into(),
-            available: 0.into(),
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let lp_token = &mut self.contract_mut().lp_token;
+        let refund = lp_token.internal_storage_deposit(&account_id, env::attached_deposit());
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        lp_token.internal_storage_balance_of(&account_id).unwrap()
+    }
+
+    /// The storage balance is always the minimum, so nothing can be withdrawn.
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let lp_token = &self.contract().lp_token;
+        let storage_balance = lp_token
+            .internal_storage_balance_of(&account_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("The account {} is not registered", account_id))
+            });
+        if amount.is_some_and(|amount| amount.0 > 0) {
+            env::panic_str("The amount is greater than the available storage balance");
         }
+        storage_balance
+    }
+
+    /// Unregister the caller and refund the storage deposit. With `force`,
+    /// remaining LP tokens of the caller are burned. Staked LP tokens are not
+    /// affected.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let contract = self.contract_mut();
+        match contract
+            .lp_token
+            .internal_storage_unregister(&account_id, force.unwrap_or(false))
+        {
+            Some(storage_deposit) => {
+                Promise::new(account_id).transfer(storage_deposit + 1);
+                true
+            }
+            None => {
+                log!("The account {} is not registered", account_id);
+                false
+            }
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        let storage_cost = self.contract().lp_token.storage_cost();
+        StorageBalanceBounds {
+            min: storage_cost.into(),
+            max: Some(storage_cost.into()),
+        }
+    }
+
+    /// Always returns 125 milliNEAR when storage is free, indicating that the
+    /// user doesn't need to be registered. It's a workaround for integrations
+    /// requiring NEP-145 storage compatibility.
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.contract()
+            .lp_token
+            .internal_storage_balance_of(&account_id)
     }
 }
@@ -0,0 +1,202 @@
+use near_sdk::json_types::U128;
+
+use crate::{
+    env, get_lp_redemption_amount, near_bindgen, ratio, AccountId, AssetId, AumPrice, Balance,
+    Contract, LpSupportState, SwapState, TransferInfo, VContract, VContractExt, WithdrawalOutput,
+    BPS_DIVISOR,
+};
+
+impl Contract {
+    /// Return the asset which is furthest below its target weight among the
+    /// assets which can be minted with and swapped into.
+    pub fn get_zap_in_asset(&self) -> AssetId {
+        let total_usd = self.get_total_value();
+        self.assets
+            .0
+            .values()
+            .filter(|asset| {
+                asset.token_weight > 0
+                    && asset.price > 0
+                    && asset.state.lp_support.check(LpSupportState::Enabled)
+                    && !asset.state.swap.check(SwapState::Disabled)
+                    && !asset.state.swap.check(SwapState::InOnly)
+                    && !asset.is_close_only()
+                    && !asset.is_depegged()
+            })
+            .min_by_key(|asset| {
+                if total_usd == 0 || self.total_weights == 0 {
+                    return 0;
+                }
+                let target_usd = ratio(total_usd, asset.token_weight, self.total_weights);
+                ratio(
+                    asset.dollar_value_of(asset.pool_balance),
+                    BPS_DIVISOR,
+                    target_usd.max(1),
+                )
+            })
+            .map(|asset| asset.asset_id.clone())
+            .unwrap_or_else(|| env::panic_str("No asset to zap into"))
+    }
+
+    /// Return the asset to burn LP tokens into before swapping to `output_id`,
+    /// picking the one with the lowest total fees.
+    pub fn get_zap_out_asset(&self, burn_amount: Balance, output_id: &AssetId) -> AssetId {
+        self.find_zap_out_asset(burn_amount, output_id)
+            .unwrap_or_else(|| env::panic_str("No asset to zap out of"))
+    }
+
+    /// See [Contract::get_zap_out_asset]. Returns `None` if no asset can be
+    /// burned into.
+    pub fn find_zap_out_asset(&self, burn_amount: Balance, output_id: &AssetId) -> Option<AssetId> {
+        let total_aum = self.get_aum(AumPrice::Min);
+        let total_supply = self.lp_token.total_supply;
+        self.assets
+            .0
+            .values()
+            .filter(|asset| {
+                asset.price > 0
+                    && !asset.state.lp_support.check(LpSupportState::Disabled)
+                    && !asset.is_close_only()
+                    && (asset.asset_id == *output_id
+                        || !asset.state.swap.check(SwapState::Disabled)
+                            && !asset.state.swap.check(SwapState::OutOnly))
+            })
+            .filter_map(|asset| {
+                let redemption_amount = get_lp_redemption_amount(
+                    total_aum,
+                    asset.max_price(),
+                    asset.denomination(),
+                    burn_amount,
+                    total_supply,
+                );
+                if redemption_amount > asset.available_liquidity() {
+                    return None;
+                }
+                let mut fee_bps = self.get_burn_fee_bps(asset, burn_amount);
+                if asset.asset_id != *output_id {
+                    fee_bps += self.get_swap_fee_bps(
+                        &asset.asset_id,
+                        output_id,
+                        redemption_amount,
+                        redemption_amount,
+                    );
+                }
+                Some((
+                    fee_bps,
+                    asset.asset_id != *output_id,
+                    asset.asset_id.clone(),
+                ))
+            })
+            // Prefer burning into the output asset when fees are equal
+            .min_by_key(|(fee_bps, needs_swap, _)| (*fee_bps, *needs_swap))
+            .map(|(_, _, asset_id)| asset_id)
+    }
+
+    /// Swap a deposit into the most under-weighted pool asset and mint LP
+    /// tokens with it. Lets accounts mint with assets which can't be minted
+    /// with directly.
+    pub fn zap_in_lp(
+        &mut self,
+        account_id: &AccountId,
+        asset_id: &AssetId,
+        amount: Balance,
+        min_out: Option<Balance>,
+    ) -> Balance {
+        let target_id = self.get_zap_in_asset();
+        let deposit = if target_id == *asset_id {
+            amount
+        } else {
+            self.swap(asset_id, &target_id, amount, None)
+        };
+        self.mint_lp_token(account_id, &target_id, deposit, min_out)
+    }
+
+    /// Burn LP tokens into the asset with the lowest fees and swap the
+    /// redeemed amount to `output_id`. If the burn exceeds the outflow
+    /// limits, the LP tokens are queued instead and 0 is returned.
+    ///
+    /// Return amount of `output_id` redeemed. It's the caller's responsibility
+    /// to send this amount back to the user.
+    #[must_use]
+    pub fn zap_out_lp(
+        &mut self,
+        account_id: &AccountId,
+        burn_amount: Balance,
+        output_id: &AssetId,
+        min_out: Option<Balance>,
+    ) -> Balance {
+        let amount_usd = self.get_lp_redemption_usd(burn_amount);
+        if !self.check_outflow_limits(account_id, amount_usd) {
+            self.queue_withdrawal(
+                account_id,
+                burn_amount,
+                WithdrawalOutput::ZapOut(output_id.clone()),
+                min_out,
+            );
+            return 0;
+        }
+
+        // Wrapped NEAR output is swapped from the NEAR pool
+        let output_id = &self.pool_asset_id(output_id);
+        let burn_asset_id = self.get_zap_out_asset(burn_amount, output_id);
+        let mut amount_out = self.burn_lp_token(account_id, burn_amount, &burn_asset_id, None);
+        if burn_asset_id != *output_id {
+            amount_out = self.swap(&burn_asset_id, output_id, amount_out, None);
+        }
+        if let Some(min_out) = min_out {
+            assert!(amount_out >= min_out, "exceeded slippage tolerance");
+        }
+        amount_out
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Mint LP tokens with attached NEAR, swapping it first if needed. Other
+    /// tokens can zap in with `ft_transfer_call` and the `ZapInLp` action.
+    #[payable]
+    pub fn zap_in_lp_near(&mut self, min_out: Option<U128>, referrer_id: Option<String>) -> U128 {
+        self.contract_mut().assert_running();
+        if let Some(referrer_id) = referrer_id {
+            self.set_user_referral_code(referrer_id);
+        }
+        let contract = self.contract_mut();
+        contract
+            .zap_in_lp(
+                &env::predecessor_account_id(),
+                &AssetId::NEAR,
+                env::attached_deposit(),
+                min_out.map(|a| a.0),
+            )
+            .into()
+    }
+
+    #[payable]
+    pub fn zap_out_lp(
+        &mut self,
+        amount: U128,
+        output_token_id: String,
+        min_out: Option<U128>,
+        referrer_id: Option<String>,
+    ) -> U128 {
+        self.contract_mut().assert_running();
+        if let Some(referrer_id) = referrer_id {
+            self.set_user_referral_code(referrer_id);
+        }
+        let contract = self.contract_mut();
+        let output_id = AssetId::from(output_token_id);
+        let account_id = env::predecessor_account_id();
+        let amount_out =
+            contract.zap_out_lp(&account_id, amount.0, &output_id, min_out.map(|a| a.0));
+
+        let transfer_info = TransferInfo::new(&account_id, &output_id, amount_out);
+        contract.internal_send(transfer_info, "zap_out_lp");
+
+        amount_out.into()
+    }
+
+    /// Returns the asset zap-ins are swapped into
+    pub fn get_zap_in_asset(&self) -> String {
+        self.contract().get_zap_in_asset().into_string()
+    }
+}
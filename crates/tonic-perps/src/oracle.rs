@@ -1,10 +1,14 @@
+use std::time::Duration;
+
 use near_sdk::json_types::U128;
-use near_sdk::log;
+use near_sdk::{Promise, PromiseError};
+use sbv2_near::{AggregatorRound, SwitchboardDecimal};
 
 use crate::{
-    emit_event, env, near_bindgen, ratio, AccountId, AssetId, Contract, Deserialize, EventType,
-    LpPriceUpdateEvent, OracleUpdateEvent, Serialize, VContract, VContractExt, BPS_DIVISOR,
-    DOLLAR_DENOMINATION,
+    borsh, emit_event, env, near_bindgen, ratio, u128_dec_format, AccountId, Asset, AssetId,
+    BorshDeserialize, BorshSerialize, Contract, Deserialize, DollarBalance, EventType,
+    LpPriceUpdateEvent, OracleUpdateEvent, OracleUpdateFailureEvent, PriceOverrideEvent, Serialize,
+    VContract, VContractExt, BPS_DIVISOR,
 };
 
 use crate::switchboard::{switchboard_contract, Ix, TGAS};
@@ -19,6 +23,18 @@ pub struct UpdateIndexPriceRequest {
     pub spread: Option<u16>,
 }
 
+/// Emergency price set by an admin when oracles are down. It only takes
+/// effect after the contract override delay, puts the asset in
+/// close-only mode and is cleared by the next oracle update.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceOverride {
+    #[serde(with = "u128_dec_format")]
+    pub price: DollarBalance,
+    pub effective_timestamp_ms: u64,
+    pub active: bool,
+}
+
 pub fn switchboard_id() -> AccountId {
     if cfg!(feature = "mainnet") {
         sbv2_near::SWITCHBOARD_V2_MAINNET
@@ -29,9 +45,169 @@ pub fn switchboard_id() -> AccountId {
     .unwrap()
 }
 
+/// Precision of [crate::DOLLAR_DENOMINATION]
+const DOLLAR_DECIMALS: u32 = 6;
+
+/// Convert a Switchboard decimal into a USD price with [crate::DOLLAR_DENOMINATION]
+/// precision.
+pub fn switchboard_price(result: &SwitchboardDecimal) -> Result<DollarBalance, String> {
+    if result.mantissa <= 0 {
+        return Err(format!("Invalid mantissa {}", result.mantissa));
+    }
+    let mantissa = result.mantissa as u128;
+    let price = if result.scale >= DOLLAR_DECIMALS {
+        // Anything scaled beyond u128 is below the price precision
+        10u128
+            .checked_pow(result.scale - DOLLAR_DECIMALS)
+            .map_or(0, |denom| mantissa / denom)
+    } else {
+        mantissa
+            .checked_mul(10u128.pow(DOLLAR_DECIMALS - result.scale))
+            .ok_or_else(|| "Price overflow".to_string())?
+    };
+    if price == 0 {
+        return Err("Price is below precision".to_string());
+    }
+    Ok(price)
+}
+
 impl Contract {
+    /// Returns true if the Switchboard feed of the asset can be queried
+    pub fn can_query_switchboard(&self, asset: &Asset) -> bool {
+        asset.switchboard_aggregator_address.is_some()
+            && env::block_timestamp_ms()
+                >= asset.last_switchboard_query_ms
+                    + Duration::from_secs(self.switchboard_refresh_interval_sec).as_millis() as u64
+    }
+
+    fn query_switchboard(&mut self, asset_id: &AssetId) -> Promise {
+        let mut asset = self.assets.unwrap(asset_id);
+        let address = asset
+            .switchboard_aggregator_address
+            .unwrap_or_else(|| env::panic_str("Asset has no Switchboard aggregator"));
+        assert!(
+            self.can_query_switchboard(&asset),
+            "Switchboard was queried too recently"
+        );
+        asset.last_switchboard_query_ms = env::block_timestamp_ms();
+        self.set_asset(asset_id, asset);
+
+        switchboard_contract::ext(switchboard_id())
+            .with_static_gas(near_sdk::Gas(5 * TGAS))
+            .aggregator_read(Ix::new(address))
+            .then(
+                VContract::ext(env::current_account_id())
+                    .with_static_gas(near_sdk::Gas(5 * TGAS))
+                    .query_switchboard_callback(asset_id),
+            )
+    }
+
+    fn schedule_price_override(&mut self, asset_id: &AssetId, price: DollarBalance) {
+        assert!(price > 0, "Price should be greater than 0");
+        let mut asset = self.assets.unwrap(asset_id);
+        let effective_timestamp_ms = env::block_timestamp_ms()
+            + Duration::from_secs(self.price_override_delay_sec).as_millis() as u64;
+        asset.price_override = Some(PriceOverride {
+            price,
+            effective_timestamp_ms,
+            active: false,
+        });
+        emit_event(EventType::PriceOverride(PriceOverrideEvent {
+            asset_id: asset_id.into_string(),
+            price: U128(price),
+            effective_timestamp_ms,
+            action: "schedule".to_string(),
+        }));
+        self.set_asset(asset_id, asset);
+    }
+
+    fn cancel_price_override(&mut self, asset_id: &AssetId) {
+        let mut asset = self.assets.unwrap(asset_id);
+        let price_override = asset
+            .price_override
+            .take()
+            .unwrap_or_else(|| env::panic_str("No price override scheduled"));
+        emit_event(EventType::PriceOverride(PriceOverrideEvent {
+            asset_id: asset_id.into_string(),
+            price: U128(price_override.price),
+            effective_timestamp_ms: price_override.effective_timestamp_ms,
+            action: "cancel".to_string(),
+        }));
+        self.set_asset(asset_id, asset);
+    }
+
+    fn apply_price_override(&mut self, asset_id: &AssetId) {
+        let mut asset = self.assets.unwrap(asset_id);
+        let mut price_override = asset
+            .price_override
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No price override scheduled"));
+        assert!(!price_override.active, "Price override is already active");
+        assert!(
+            env::block_timestamp_ms() >= price_override.effective_timestamp_ms,
+            "Price override is not effective yet"
+        );
+
+        self.update_cumulative_funding_rate(&mut asset);
+        asset.price = price_override.price;
+        asset.mark_price = price_override.price;
+        asset.last_change_timestamp_ms = env::block_timestamp_ms();
+        price_override.active = true;
+        asset.price_override = Some(price_override);
+        emit_event(EventType::OracleUpdate(OracleUpdateEvent {
+            asset_id: asset_id.into_string(),
+            price: U128(asset.price),
+            spread_bps: asset.spread_bps,
+            source: "admin_override".to_string(),
+        }));
+        self.set_asset(asset_id, asset);
+    }
+
+    /// Validate a Switchboard round and return the price it reports, or
+    /// `None` if the round is older than the current price of the asset.
+    fn parse_switchboard_round(
+        &self,
+        asset: &Asset,
+        round: &AggregatorRound,
+    ) -> Result<Option<DollarBalance>, String> {
+        let now_sec = Duration::from_millis(env::block_timestamp_ms()).as_secs();
+        if now_sec.saturating_sub(round.round_open_timestamp)
+            > self.get_max_staleness_duration_sec(asset)
+        {
+            return Err(format!(
+                "Round opened at {} is too stale",
+                round.round_open_timestamp
+            ));
+        }
+        let price = switchboard_price(&round.result)?;
+        let round_open_timestamp_ms =
+            Duration::from_secs(round.round_open_timestamp).as_millis() as u64;
+        if round_open_timestamp_ms < asset.last_change_timestamp_ms {
+            return Ok(None);
+        }
+        Ok(Some(price))
+    }
+
+    fn record_switchboard_failure(&mut self, asset_id: &AssetId, reason: String) {
+        let mut asset = self.assets.unwrap(asset_id);
+        asset.switchboard_failures += 1;
+        self.set_asset(asset_id, asset);
+        emit_event(EventType::OracleUpdateFailure(OracleUpdateFailureEvent {
+            asset_id: asset_id.into_string(),
+            source: "switchboard".to_string(),
+            reason,
+        }));
+    }
+
     /// Used to update the index price from keeper script
-    fn update_index_price(&mut self, reqs: Vec<UpdateIndexPriceRequest>) {
+    ///
+    /// `keeper_id` is rewarded for refreshing stale prices, see [crate::KeeperRewardParameters].
+    /// Switchboard refreshes are not rewarded.
+    fn update_index_price(
+        &mut self,
+        reqs: Vec<UpdateIndexPriceRequest>,
+        keeper_id: Option<&AccountId>,
+    ) {
         let time = env::block_timestamp_ms();
         for req in reqs {
             let asset_id: AssetId = req.asset_id.into();
@@ -62,6 +238,15 @@ impl Contract {
                 if let Some(spread) = req.spread {
                     asset.spread_bps = spread;
                 }
+                asset.update_mark_price(
+                    Duration::from_millis(time - asset.last_change_timestamp_ms).as_secs(),
+                );
+                // Fresh oracle price, drop any emergency override
+                asset.price_override = None;
+                if let Some(keeper_id) = keeper_id.filter(|_| asset.last_change_timestamp_ms > 0) {
+                    let price_age_ms = time - asset.last_change_timestamp_ms;
+                    self.reward_keeper(keeper_id, &mut asset, price_age_ms);
+                }
                 asset.last_change_timestamp_ms = time;
                 emit_event(EventType::OracleUpdate(OracleUpdateEvent {
                     asset_id: asset_id.into_string(),
@@ -77,6 +262,7 @@ impl Contract {
             self.update_cumulative_funding_rate(&mut asset);
             self.set_asset(&asset_id.clone(), asset);
         }
+        self.distribute_staking_fees();
 
         // Won't have any supply upon initialization
         if self.lp_token.total_supply > 0 {
@@ -93,55 +279,87 @@ impl VContract {
     pub fn update_index_price(&mut self, reqs: Vec<UpdateIndexPriceRequest>) {
         let contract = self.contract_mut();
         contract.assert_price_oracle();
-        contract.update_index_price(reqs);
+        contract.update_index_price(reqs, Some(&env::predecessor_account_id()));
+    }
+
+    /// Schedule an emergency price for an asset. The price can be applied once
+    /// the override delay has passed, unless an oracle update arrives first.
+    pub fn schedule_price_override(&mut self, asset_id: String, price: U128) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.schedule_price_override(&asset_id.into(), price.0);
+    }
+
+    /// Apply a scheduled price override whose delay has passed. The asset
+    /// is close-only until the next oracle update.
+    pub fn apply_price_override(&mut self, asset_id: String) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.apply_price_override(&asset_id.into());
     }
 
-    /// Initiates query to Switchboard which will update asset prices in callbacks
+    pub fn cancel_price_override(&mut self, asset_id: String) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.cancel_price_override(&asset_id.into());
+    }
+
+    pub fn get_price_override(&self, asset_id: String) -> Option<PriceOverride> {
+        self.contract()
+            .assets
+            .unwrap(&asset_id.into())
+            .price_override
+            .clone()
+    }
+
+    /// Initiates query to Switchboard which will update asset prices in callbacks.
+    /// Assets queried too recently are skipped.
     pub fn query_switchboard(&mut self) {
-        let contract = self.contract();
-        contract.assert_owner();
-        let gas = near_sdk::Gas(5 * TGAS);
-
-        for asset in &contract.assets.0 {
-            if let Some(address) = asset.1.switchboard_aggregator_address {
-                let ix = Ix::new(address);
-
-                switchboard_contract::ext(switchboard_id())
-                    .with_static_gas(gas)
-                    .aggregator_read(ix)
-                    .then(
-                        Self::ext(env::current_account_id())
-                            .with_static_gas(near_sdk::Gas(5 * TGAS))
-                            .query_switchboard_callback(asset.0),
-                    );
-            }
+        let contract = self.contract_mut();
+        let asset_ids: Vec<AssetId> = contract
+            .assets
+            .0
+            .iter()
+            .filter(|(_, asset)| contract.can_query_switchboard(asset))
+            .map(|(asset_id, _)| asset_id.clone())
+            .collect();
+
+        for asset_id in asset_ids {
+            contract.query_switchboard(&asset_id);
         }
     }
 
+    /// Initiates query to Switchboard for a single asset
+    pub fn query_switchboard_asset(&mut self, asset_id: String) -> Promise {
+        self.contract_mut().query_switchboard(&asset_id.into())
+    }
+
     #[private]
     pub fn query_switchboard_callback(
         &mut self,
-        #[callback_result] call_result: Result<sbv2_near::AggregatorRound, near_sdk::PromiseError>,
+        #[callback_result] call_result: Result<AggregatorRound, PromiseError>,
         asset_id: &AssetId,
     ) {
-        if let Ok(res) = call_result {
-            let contract = self.contract_mut();
-
-            if res.result.mantissa < 0 {
-                env::panic_str("Price of asset cannot be negative");
-            }
-            let mantissa = res.result.mantissa as u128;
-            let scale = res.result.scale;
-
-            let price: u128 = ratio(mantissa, DOLLAR_DENOMINATION, 10u128.pow(scale));
-
-            contract.update_index_price(vec![UpdateIndexPriceRequest {
-                asset_id: asset_id.into_string(),
-                price: U128(price),
-                spread: None,
-            }]);
-        } else {
-            log!("Error in Switchboard callback");
+        let contract = self.contract_mut();
+        let asset = contract.assets.unwrap(asset_id);
+        let price = match call_result {
+            Ok(round) => contract.parse_switchboard_round(&asset, &round),
+            Err(_) => Err("Switchboard read failed".to_string()),
+        };
+        drop(asset);
+
+        match price {
+            // A newer price was already set, ignore the round
+            Ok(None) => {}
+            Ok(Some(price)) => contract.update_index_price(
+                vec![UpdateIndexPriceRequest {
+                    asset_id: asset_id.into_string(),
+                    price: U128(price),
+                    spread: None,
+                }],
+                None,
+            ),
+            Err(reason) => contract.record_switchboard_failure(asset_id, reason),
         }
     }
 }
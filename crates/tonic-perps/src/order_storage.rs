@@ -0,0 +1,130 @@
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, StorageUsage};
+
+use crate::{
+    borsh, emit_event, env, near_bindgen, AccountId, AssetId, Balance, BorshDeserialize,
+    BorshSerialize, Contract, Deserialize, EventType, Serialize, TokenDepositWithdrawEvent,
+    TransferInfo, VContract, VContractExt,
+};
+
+/// Limits on the number of open limit orders of an account. 0 disables a
+/// limit.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LimitOrderLimits {
+    /// Open orders of an account on all assets
+    pub max_orders_per_account: u32,
+    /// Open orders of an account on one underlying asset
+    pub max_orders_per_asset: u32,
+}
+
+impl Contract {
+    /// Check that `account_id` can open another order on `underlying_id`
+    pub fn check_limit_order_limits(&self, account_id: &AccountId, underlying_id: &AssetId) {
+        let ids = self.limit_order_ids_map.get(account_id).unwrap_or_default();
+        let limits = &self.limit_order_limits;
+        assert!(
+            limits.max_orders_per_account == 0
+                || ids.len() < limits.max_orders_per_account as usize,
+            "Too many open limit orders"
+        );
+        assert!(
+            limits.max_orders_per_asset == 0
+                || ids.values().filter(|id| *id == underlying_id).count()
+                    < limits.max_orders_per_asset as usize,
+            "Too many open limit orders for this asset"
+        );
+    }
+
+    pub fn get_order_storage_balance(&self, account_id: &AccountId) -> Balance {
+        self.order_storage_balances.get(account_id).unwrap_or(0)
+    }
+
+    /// Charge the order storage balance of `account_id` for the storage used
+    /// since `initial_storage_usage`. Returns the number of bytes charged.
+    pub fn charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) -> StorageUsage {
+        let bytes = env::storage_usage().saturating_sub(initial_storage_usage);
+        if bytes == 0 {
+            return 0;
+        }
+        let cost = Balance::from(bytes) * env::storage_byte_cost();
+        let balance = self.get_order_storage_balance(account_id);
+        if cost > balance {
+            env::panic_str(&format!(
+                "Not enough order storage balance, {} required",
+                cost - balance
+            ));
+        }
+        self.order_storage_balances
+            .insert(account_id, &(balance - cost));
+        bytes
+    }
+
+    /// Credit back `bytes` of storage charged with [Contract::charge_storage]
+    pub fn refund_storage(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        if bytes == 0 {
+            return;
+        }
+        let balance = self.get_order_storage_balance(account_id)
+            + Balance::from(bytes) * env::storage_byte_cost();
+        self.order_storage_balances.insert(account_id, &balance);
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Deposit NEAR paying for the storage of limit orders and LP
+    /// withdrawals. Storage of an order is charged when it is placed and
+    /// credited back when it is removed, executed or expired. Queued LP
+    /// withdrawals are charged the same way. The first deposit pays for the
+    /// storage of the balance itself.
+    #[payable]
+    pub fn deposit_order_storage(&mut self, account_id: Option<AccountId>) -> U128 {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let contract = self.contract_mut();
+        let initial_storage_usage = env::storage_usage();
+        let balance = contract.get_order_storage_balance(&account_id) + env::attached_deposit();
+        contract
+            .order_storage_balances
+            .insert(&account_id, &balance);
+        contract.charge_storage(&account_id, initial_storage_usage);
+        let balance = contract.get_order_storage_balance(&account_id);
+
+        emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
+            amount_native: env::attached_deposit().into(),
+            deposit: true,
+            method: "deposit_order_storage".to_string(),
+            receiver_id: env::current_account_id(),
+            account_id,
+            asset_id: AssetId::NEAR.into_string(),
+        }));
+
+        balance.into()
+    }
+
+    /// Withdraw NEAR of the order storage balance not used by open orders.
+    /// Withdraws everything if `amount` is not set.
+    #[payable]
+    pub fn withdraw_order_storage(&mut self, amount: Option<U128>) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let contract = self.contract_mut();
+        let balance = contract.get_order_storage_balance(&account_id);
+        let amount = amount.map_or(balance, |a| a.0);
+        assert!(amount <= balance, "Not enough order storage balance");
+        contract
+            .order_storage_balances
+            .insert(&account_id, &(balance - amount));
+
+        contract.internal_send(
+            TransferInfo::new(&account_id, &AssetId::NEAR, amount),
+            "withdraw_order_storage",
+        );
+
+        amount.into()
+    }
+}
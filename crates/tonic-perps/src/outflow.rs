@@ -0,0 +1,514 @@
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, log, IntoStorageKey, StorageUsage};
+
+use crate::{
+    borsh, env, get_lp_redemption_amount, near_bindgen, ratio, u128_dec_format, AccountId, Asset,
+    AssetId, AumPrice, Balance, BorshDeserialize, BorshSerialize, Contract, Deserialize,
+    DollarBalance, LpSupportState, Serialize, TokenTransfer, TokenTransferHistory, TransferInfo,
+    TransferType, VContract, VContractExt, BPS_DIVISOR,
+};
+
+/// Limits on the USD value of LP withdrawals in the outflow sliding window.
+/// 0 disables a limit.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OutflowLimits {
+    /// Limit for all accounts together
+    #[serde(with = "u128_dec_format")]
+    pub global_limit_usd: DollarBalance,
+    /// Limit for each account
+    #[serde(with = "u128_dec_format")]
+    pub account_limit_usd: DollarBalance,
+}
+
+/// How the LP tokens of a queued withdrawal are redeemed
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub enum WithdrawalOutput {
+    /// Burn into an asset, paid out as wrapped NEAR if set to it
+    Asset(AssetId),
+    /// Burn for a share of every pool asset
+    InKind,
+    /// Burn into the asset with the lowest fees and swap to this one
+    ZapOut(AssetId),
+}
+
+/// LP token burn waiting for the outflow limits to allow it. The LP tokens
+/// are held by the contract account until the withdrawal is released or
+/// cancelled.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct QueuedWithdrawal {
+    pub account_id: AccountId,
+    pub amount: Balance,
+    pub output: WithdrawalOutput,
+    /// Minimum output of the burn. In USD for in-kind burns. The withdrawal
+    /// is cancelled if it can't be met.
+    pub min_out: Option<Balance>,
+    pub queued_timestamp_ms: u64,
+    /// Storage paid by the account for the withdrawal
+    pub storage_usage: StorageUsage,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct QueuedWithdrawalView {
+    pub id: u64,
+    pub account_id: AccountId,
+    pub amount: U128,
+    /// Not set for in-kind burns
+    pub output_token_id: Option<String>,
+    pub zap_out: bool,
+    pub min_out: Option<U128>,
+    pub queued_timestamp_ms: u64,
+}
+
+/// Whether a queued withdrawal can be released
+enum WithdrawalStatus {
+    Ready,
+    /// Can't be released now, stays queued
+    Wait,
+    /// Can never be released, the LP tokens are given back
+    Cancel,
+}
+
+/// First-in, first-out queue of LP withdrawals
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct WithdrawalQueue {
+    pub withdrawals: LookupMap<u64, QueuedWithdrawal>,
+    /// Id of the oldest withdrawal which may still be queued
+    pub head: u64,
+    /// Id of the next queued withdrawal
+    pub tail: u64,
+}
+
+impl WithdrawalQueue {
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self {
+            withdrawals: LookupMap::new(prefix),
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    pub fn push(&mut self, withdrawal: &QueuedWithdrawal) -> u64 {
+        let id = self.tail;
+        self.withdrawals.insert(&id, withdrawal);
+        self.tail += 1;
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<QueuedWithdrawal> {
+        let withdrawal = self.withdrawals.remove(&id);
+        while self.head < self.tail && !self.withdrawals.contains_key(&self.head) {
+            self.head += 1;
+        }
+        withdrawal
+    }
+}
+
+fn within_outflow_limit(
+    outflow_usd: DollarBalance,
+    amount_usd: DollarBalance,
+    limit_usd: DollarBalance,
+) -> bool {
+    limit_usd == 0 || outflow_usd + amount_usd <= limit_usd
+}
+
+impl Contract {
+    /// USD value of LP withdrawals in the current window, for all accounts
+    /// or for one account
+    pub fn get_outflow_usd(&self, account_id: Option<&AccountId>) -> DollarBalance {
+        match account_id {
+            Some(account_id) => self
+                .account_outflow_history
+                .get(account_id)
+                .map_or(0, |history| history.amount()),
+            None => self.outflow_history.amount(),
+        }
+    }
+
+    /// Returns true if withdrawing `amount_usd` is within the outflow limits
+    pub fn check_outflow_limits(&self, account_id: &AccountId, amount_usd: DollarBalance) -> bool {
+        self.check_global_outflow_limit(amount_usd)
+            && within_outflow_limit(
+                self.get_outflow_usd(Some(account_id)),
+                amount_usd,
+                self.outflow_limits.account_limit_usd,
+            )
+    }
+
+    fn check_global_outflow_limit(&self, amount_usd: DollarBalance) -> bool {
+        within_outflow_limit(
+            self.get_outflow_usd(None),
+            amount_usd,
+            self.outflow_limits.global_limit_usd,
+        )
+    }
+
+    /// Returns true if `amount_usd` alone is over one of the outflow limits,
+    /// so it can never be withdrawn at once
+    fn exceeds_outflow_limits(&self, amount_usd: DollarBalance) -> bool {
+        let limits = &self.outflow_limits;
+        !within_outflow_limit(0, amount_usd, limits.global_limit_usd)
+            || !within_outflow_limit(0, amount_usd, limits.account_limit_usd)
+    }
+
+    /// Records an LP withdrawal in the outflow window. The history of the
+    /// account is only kept while the account limit is set. The contract pays
+    /// for the storage of the outflow history.
+    pub fn register_outflow(&mut self, account_id: &AccountId, amount_usd: DollarBalance) {
+        self.outflow_history.clean(env::block_timestamp_ms());
+        if !self.check_outflow_limits(account_id, amount_usd) {
+            env::panic_str("Exceeded outflow limit");
+        }
+        let transfer = TokenTransfer::new(
+            amount_usd,
+            env::block_timestamp_ms(),
+            TransferType::Withdraw,
+        );
+        self.outflow_history.push(transfer.clone());
+        if self.outflow_limits.account_limit_usd == 0 {
+            return;
+        }
+
+        let window_ms = self.outflow_history.sliding_window_duration_ms();
+        let mut history = self
+            .account_outflow_history
+            .get(account_id)
+            .unwrap_or_else(|| TokenTransferHistory::new(window_ms));
+        history.update_sliding_window_duration(window_ms);
+        history.clean(env::block_timestamp_ms());
+        history.push(transfer);
+        self.account_outflow_history.insert(account_id, &history);
+    }
+
+    /// USD value redeemed by burning `burn_amount` LP tokens
+    pub fn get_lp_redemption_usd(&self, burn_amount: Balance) -> DollarBalance {
+        ratio(
+            self.get_aum(AumPrice::Min),
+            burn_amount,
+            self.lp_token.total_supply,
+        )
+    }
+
+    /// Move LP tokens to the contract account and queue their burn. The
+    /// account pays for the storage of the queued withdrawal with its order
+    /// storage balance.
+    pub fn queue_withdrawal(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        output: WithdrawalOutput,
+        min_out: Option<Balance>,
+    ) -> u64 {
+        assert!(amount > 0, "Burn amount should be positive");
+        if self.exceeds_outflow_limits(self.get_lp_redemption_usd(amount)) {
+            env::panic_str("Withdrawal exceeds outflow limit");
+        }
+        match &output {
+            WithdrawalOutput::Asset(asset_id) => {
+                let asset = self.assets.unwrap(&self.pool_asset_id(asset_id));
+                assert!(!asset.state.lp_support.check(LpSupportState::Disabled));
+            }
+            WithdrawalOutput::ZapOut(asset_id) => {
+                self.assets.unwrap(&self.pool_asset_id(asset_id));
+            }
+            WithdrawalOutput::InKind => {}
+        }
+        self.assert_lp_tokens_unlocked(account_id, amount);
+
+        let initial_storage_usage = env::storage_usage();
+        self.lp_token
+            .internal_transfer(account_id, &env::current_account_id(), amount, None);
+        let mut withdrawal = QueuedWithdrawal {
+            account_id: account_id.clone(),
+            amount,
+            output,
+            min_out,
+            queued_timestamp_ms: env::block_timestamp_ms(),
+            storage_usage: 0,
+        };
+        let id = self.withdrawal_queue.push(&withdrawal);
+        withdrawal.storage_usage = self.charge_storage(account_id, initial_storage_usage);
+        self.withdrawal_queue.withdrawals.insert(&id, &withdrawal);
+        log!("Withdrawal {} queued for {}", id, account_id);
+        id
+    }
+
+    /// Cancel a queued withdrawal and give the LP tokens back
+    pub fn cancel_queued_withdrawal(&mut self, account_id: &AccountId, id: u64) {
+        let withdrawal = self
+            .withdrawal_queue
+            .withdrawals
+            .get(&id)
+            .unwrap_or_else(|| env::panic_str("Queued withdrawal not found"));
+        assert!(
+            withdrawal.account_id == *account_id,
+            "Can not cancel other account's withdrawal"
+        );
+        self.withdrawal_queue.remove(id);
+        self.lp_token.internal_transfer(
+            &env::current_account_id(),
+            account_id,
+            withdrawal.amount,
+            None,
+        );
+        self.refund_storage(account_id, withdrawal.storage_usage);
+    }
+
+    /// Amount of an asset redeemed by burning `burn_amount` LP tokens
+    fn get_lp_redemption_amount_of(&self, asset: &Asset, burn_amount: Balance) -> Balance {
+        get_lp_redemption_amount(
+            self.get_aum(AumPrice::Min),
+            asset.max_price(),
+            asset.denomination(),
+            burn_amount,
+            self.lp_token.total_supply,
+        )
+    }
+
+    /// Returns true if `amount` of the asset can be redeemed now
+    fn can_redeem(&self, asset: &Asset, amount: Balance) -> bool {
+        self.is_asset_price_valid(asset)
+            && !asset.is_close_only()
+            && amount + asset.buffer_amount <= asset.available_liquidity()
+            && asset.check_withdrawal_limit(amount)
+    }
+
+    /// Check a queued withdrawal without panicking, so that one withdrawal
+    /// can't block the queue
+    fn get_withdrawal_status(&self, withdrawal: &QueuedWithdrawal) -> WithdrawalStatus {
+        let below_min_out = |amount_out: Balance| {
+            withdrawal
+                .min_out
+                .is_some_and(|min_out| amount_out < min_out)
+        };
+        match &withdrawal.output {
+            WithdrawalOutput::Asset(asset_id) => {
+                let asset = match self.assets.get(&self.pool_asset_id(asset_id)) {
+                    Some(asset) if !asset.state.lp_support.check(LpSupportState::Disabled) => asset,
+                    _ => return WithdrawalStatus::Cancel,
+                };
+                let redemption_amount = self.get_lp_redemption_amount_of(asset, withdrawal.amount);
+                if !self.can_redeem(asset, redemption_amount) {
+                    WithdrawalStatus::Wait
+                } else if below_min_out(redemption_amount) {
+                    WithdrawalStatus::Cancel
+                } else {
+                    WithdrawalStatus::Ready
+                }
+            }
+            WithdrawalOutput::InKind => {
+                let assets = self.get_in_kind_assets();
+                let redemption_usd = self.get_in_kind_redemption_usd(&assets, withdrawal.amount);
+                let pool_usd: DollarBalance = assets
+                    .iter()
+                    .map(|(_, asset)| asset.dollar_value_of(asset.pool_balance))
+                    .sum();
+                let can_redeem = pool_usd > 0
+                    && assets.iter().all(|(_, asset)| {
+                        self.can_redeem(asset, ratio(asset.pool_balance, redemption_usd, pool_usd))
+                    });
+                if !can_redeem {
+                    WithdrawalStatus::Wait
+                } else if below_min_out(redemption_usd) {
+                    WithdrawalStatus::Cancel
+                } else {
+                    WithdrawalStatus::Ready
+                }
+            }
+            WithdrawalOutput::ZapOut(output_id) => {
+                let output_id = self.pool_asset_id(output_id);
+                let amount_out = self
+                    .find_zap_out_asset(withdrawal.amount, &output_id)
+                    .and_then(|asset_id| {
+                        let asset = self.assets.unwrap(&asset_id);
+                        let redemption_amount =
+                            self.get_lp_redemption_amount_of(&asset, withdrawal.amount);
+                        if !self.can_redeem(&asset, redemption_amount) {
+                            return None;
+                        }
+                        let fee_bps = self.get_burn_fee_bps(&asset, withdrawal.amount);
+                        let amount =
+                            redemption_amount - ratio(redemption_amount, fee_bps, BPS_DIVISOR);
+                        if asset_id == output_id {
+                            Some(amount)
+                        } else {
+                            self.get_swap_amount_out(&asset_id, &output_id, amount)
+                        }
+                    });
+                match amount_out {
+                    None => WithdrawalStatus::Wait,
+                    Some(amount_out) if below_min_out(amount_out) => WithdrawalStatus::Cancel,
+                    Some(_) => WithdrawalStatus::Ready,
+                }
+            }
+        }
+    }
+
+    /// Burn the LP tokens of a withdrawal taken off the queue
+    fn release_withdrawal(&mut self, withdrawal: &QueuedWithdrawal) -> Vec<TransferInfo> {
+        let account_id = &withdrawal.account_id;
+        self.lp_token.internal_transfer(
+            &env::current_account_id(),
+            account_id,
+            withdrawal.amount,
+            None,
+        );
+        match &withdrawal.output {
+            WithdrawalOutput::Asset(output_id) => {
+                let amount_out = self.burn_lp_token(
+                    account_id,
+                    withdrawal.amount,
+                    &self.pool_asset_id(output_id),
+                    None,
+                );
+                vec![TransferInfo::new(account_id, output_id, amount_out)]
+            }
+            WithdrawalOutput::InKind => {
+                self.burn_lp_token_in_kind(account_id, withdrawal.amount, None)
+            }
+            WithdrawalOutput::ZapOut(output_id) => {
+                let amount_out = self.zap_out_lp(account_id, withdrawal.amount, output_id, None);
+                vec![TransferInfo::new(account_id, output_id, amount_out)]
+            }
+        }
+    }
+
+    /// Release queued withdrawals in order while the global outflow limit
+    /// allows. Withdrawals over the account limit or the asset liquidity, or
+    /// with a stale or overridden price, are skipped and stay queued.
+    /// Withdrawals which can't meet their `min_out` or whose asset can't be
+    /// burned into anymore are cancelled. Looks at `max_count` queued
+    /// withdrawals at most.
+    ///
+    /// Return the number of withdrawals released and the amounts of assets
+    /// redeemed. It's the caller's responsibility to send them back to the
+    /// users.
+    #[must_use]
+    pub fn process_withdrawal_queue(&mut self, max_count: u64) -> (u64, Vec<TransferInfo>) {
+        let mut released = 0;
+        let mut transfers = vec![];
+        let mut id = self.withdrawal_queue.head;
+        let end = self
+            .withdrawal_queue
+            .tail
+            .min(self.withdrawal_queue.head + max_count);
+        while id < end {
+            let withdrawal = match self.withdrawal_queue.withdrawals.get(&id) {
+                Some(withdrawal) => withdrawal,
+                None => {
+                    id += 1;
+                    continue;
+                }
+            };
+            let amount_usd = self.get_lp_redemption_usd(withdrawal.amount);
+            // The limits may have been lowered or the LP price increased
+            // since the withdrawal was queued
+            if self.exceeds_outflow_limits(amount_usd) {
+                log!(
+                    "Cancelling withdrawal {}, it exceeds the outflow limits",
+                    id
+                );
+                self.cancel_queued_withdrawal(&withdrawal.account_id, id);
+                id += 1;
+                continue;
+            }
+            if !self.check_global_outflow_limit(amount_usd) {
+                break;
+            }
+            if !self.check_outflow_limits(&withdrawal.account_id, amount_usd) {
+                id += 1;
+                continue;
+            }
+            match self.get_withdrawal_status(&withdrawal) {
+                WithdrawalStatus::Ready => {}
+                WithdrawalStatus::Wait => {
+                    id += 1;
+                    continue;
+                }
+                WithdrawalStatus::Cancel => {
+                    log!("Cancelling withdrawal {}, it can't be released", id);
+                    self.cancel_queued_withdrawal(&withdrawal.account_id, id);
+                    id += 1;
+                    continue;
+                }
+            }
+
+            let initial_storage_usage = env::storage_usage();
+            self.withdrawal_queue.remove(id);
+            let released_storage_usage = initial_storage_usage.saturating_sub(env::storage_usage());
+            self.refund_storage(
+                &withdrawal.account_id,
+                withdrawal.storage_usage.min(released_storage_usage),
+            );
+            transfers.extend(self.release_withdrawal(&withdrawal));
+            log!("Withdrawal {} released", id);
+            released += 1;
+            id += 1;
+        }
+        (released, transfers)
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Release queued LP withdrawals which fit in the outflow limits. Callable
+    /// by anyone. Returns the number of withdrawals released.
+    pub fn process_withdrawal_queue(&mut self, max_count: Option<u64>) -> u64 {
+        self.contract_mut().assert_running();
+        let contract = self.contract_mut();
+        let (released, transfers) = contract.process_withdrawal_queue(max_count.unwrap_or(10));
+        for transfer_info in transfers {
+            contract.internal_send(transfer_info, "process_withdrawal_queue");
+        }
+        released
+    }
+
+    /// Cancel a queued withdrawal of the caller and get the LP tokens back
+    #[payable]
+    pub fn cancel_queued_withdrawal(&mut self, id: u64) {
+        assert_one_yocto();
+        self.contract_mut()
+            .cancel_queued_withdrawal(&env::predecessor_account_id(), id);
+    }
+
+    pub fn get_withdrawal_queue(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<QueuedWithdrawalView> {
+        let queue = &self.contract().withdrawal_queue;
+        let from_index = from_index.unwrap_or(queue.head).max(queue.head);
+        let end = queue.tail.min(from_index + limit.unwrap_or(50));
+        (from_index..end)
+            .filter_map(|id| {
+                queue.withdrawals.get(&id).map(|withdrawal| {
+                    let (output_token_id, zap_out) = match withdrawal.output {
+                        WithdrawalOutput::Asset(asset_id) => (Some(asset_id.into_string()), false),
+                        WithdrawalOutput::InKind => (None, false),
+                        WithdrawalOutput::ZapOut(asset_id) => (Some(asset_id.into_string()), true),
+                    };
+                    QueuedWithdrawalView {
+                        id,
+                        account_id: withdrawal.account_id,
+                        amount: withdrawal.amount.into(),
+                        output_token_id,
+                        zap_out,
+                        min_out: withdrawal.min_out.map(Into::into),
+                        queued_timestamp_ms: withdrawal.queued_timestamp_ms,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// USD value of LP withdrawals in the current outflow window, for all
+    /// accounts or for one account
+    pub fn get_outflow_usd(&self, account_id: Option<AccountId>) -> U128 {
+        self.contract().get_outflow_usd(account_id.as_ref()).into()
+    }
+}
@@ -1,5 +1,5 @@
 use near_sdk::json_types::{U128, U64};
-use near_sdk::{AccountId, Balance};
+use near_sdk::{AccountId, Balance, StorageUsage};
 use serde::{Deserialize, Serialize};
 use std::collections::btree_map::Range;
 use std::collections::BTreeMap;
@@ -7,9 +7,10 @@ use std::fmt::Display;
 use std::ops::Bound::Included;
 
 use crate::{
-    borsh, emit_event, env, get_delta, ratio, AssetId, BorshDeserialize, BorshSerialize, Contract,
-    DollarBalance, EventType, LimitOrderId, LimitOrderView, LiquidationStatus,
-    PlaceLimitOrderEvent, Position, RemoveLimitOrderEvent, RemoveOrderReason, TransferInfo,
+    borsh, convert_assets, emit_event, env, get_delta, ratio, AssetId, BorshDeserialize,
+    BorshSerialize, Contract, DollarBalance, EventType, LimitOrderDepthLevel, LimitOrderId,
+    LimitOrderView, LiquidationStatus, PlaceLimitOrderEvent, Position, RemoveLimitOrderEvent,
+    RemoveOrderReason, TransferInfo, DOLLAR_DENOMINATION,
 };
 
 #[derive(
@@ -18,6 +19,8 @@ use crate::{
 pub enum OrderType {
     Increase,
     Decrease,
+    /// Spot swap of the escrowed collateral into the output asset
+    Swap,
 }
 
 impl Display for OrderType {
@@ -25,6 +28,7 @@ impl Display for OrderType {
         f.write_str(match self {
             OrderType::Increase => "increase",
             OrderType::Decrease => "decrease",
+            OrderType::Swap => "swap",
         })
     }
 }
@@ -70,6 +74,19 @@ pub struct LimitOrder {
     pub order_type: OrderType,
     /// Above or below threshold
     pub threshold: ThresholdType,
+    /// The asset bought by swap orders. The price of swap orders is the
+    /// price of the collateral in units of this asset.
+    pub output_id: Option<AssetId>,
+    /// Decrease orders only: cap the deltas at the position instead of
+    /// invalidating the order when the position got smaller
+    pub reduce_only: bool,
+    /// Decrease orders only: close the entire position
+    pub close_position: bool,
+    /// Escrowed in the collateral asset and paid to the keeper executing or
+    /// expiring the order
+    pub execution_fee: Balance,
+    /// Bytes of storage paid from the order storage balance of the owner
+    pub storage_usage: StorageUsage,
 }
 
 impl LimitOrder {
@@ -86,8 +103,27 @@ impl LimitOrder {
             order_type: params.order_type,
             expiry: params.expiry.unwrap(),
             threshold,
+            output_id: params.output_id,
+            reduce_only: params.reduce_only,
+            close_position: params.close_position,
+            execution_fee: params.execution_fee,
+            storage_usage: 0,
         }
     }
+
+    pub fn is_swap(&self) -> bool {
+        matches!(self.order_type, OrderType::Swap)
+    }
+
+    /// Collateral and size deltas of a decrease order on `position`
+    pub fn get_decrease_deltas(&self, position: &Position) -> (DollarBalance, DollarBalance) {
+        position.get_decrease_deltas(
+            self.collateral_delta,
+            self.size_delta,
+            self.reduce_only,
+            self.close_position,
+        )
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -101,6 +137,45 @@ pub struct LimitOrderParameters {
     pub expiry: Option<U64>,
     // If sell only
     pub collateral_delta: Option<U128>,
+    /// Sell only: cap the deltas at the position when executed
+    pub reduce_only: Option<bool>,
+    /// Sell only: close the entire position when executed
+    pub close_position: Option<bool>,
+    /// Part of the attached tokens paid to the keeper executing the order.
+    /// Defaults to all attached tokens on sell orders.
+    pub execution_fee: Option<U128>,
+}
+
+/// Swap the attached tokens into `output_token_id` once the price of the
+/// attached token in units of the output token crosses `price`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SwapLimitOrderParameters {
+    pub output_token_id: String,
+    pub price: U128, // Output token per input token, with dollar precision
+    pub expiry: Option<U64>,
+    /// Part of the attached tokens paid to the keeper executing the order
+    pub execution_fee: Option<U128>,
+}
+
+/// Changes to a resting limit order. Fields left unset are kept. Tokens
+/// attached to the update pay for `top_up` and any increase of the execution
+/// fee, the rest is refunded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UpdateLimitOrderParameters {
+    pub limit_order_id: LimitOrderId,
+    pub price: Option<U128>,
+    pub size_delta: Option<U128>,
+    /// Decrease orders only: USD collateral to withdraw from the position
+    pub collateral_delta: Option<U128>,
+    /// Increase and swap orders only: collateral added to the escrow.
+    /// Defaults to the attached tokens not used by the execution fee.
+    pub top_up: Option<U128>,
+    /// Increase and swap orders only: collateral taken out of the escrow and
+    /// refunded
+    pub withdraw: Option<U128>,
+    pub expiry: Option<U64>,
+    /// New execution fee. Lowering it refunds the difference.
+    pub execution_fee: Option<U128>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -115,9 +190,98 @@ pub struct AddLimitOrderParams {
     pub is_long: bool,
     pub order_type: OrderType,
     pub expiry: Option<u64>,
+    /// Only set for swap orders
+    pub output_id: Option<AssetId>,
+    pub reduce_only: bool,
+    pub close_position: bool,
+    pub execution_fee: Balance,
+}
+
+/// Split the tokens attached to a new order into collateral and execution
+/// fee
+pub fn split_execution_fee(
+    order_type: OrderType,
+    deposit: Balance,
+    execution_fee: Option<U128>,
+) -> (Balance, Balance) {
+    let execution_fee = match (execution_fee, order_type) {
+        (Some(fee), _) => fee.0,
+        (None, OrderType::Decrease) => deposit,
+        (None, _) => 0,
+    };
+    assert!(
+        execution_fee <= deposit,
+        "Execution fee exceeds the attached tokens"
+    );
+    (deposit - execution_fee, execution_fee)
 }
 
 impl Contract {
+    /// Price of `token_in` in units of `token_out`, with dollar precision
+    pub fn get_swap_order_price(&self, token_in: &AssetId, token_out: &AssetId) -> DollarBalance {
+        let asset_out = self.assets.unwrap(token_out);
+        assert!(asset_out.price > 0, "Output asset has no price");
+        ratio(
+            self.assets.unwrap(token_in).price,
+            DOLLAR_DENOMINATION,
+            asset_out.price,
+        )
+    }
+
+    /// Amount of the output token the attached tokens are worth at the
+    /// order price
+    fn get_swap_order_min_amount_out(&self, limit_order: &LimitOrder) -> Balance {
+        let output_id = limit_order.output_id.as_ref().unwrap();
+        convert_assets(
+            limit_order.attached_collateral,
+            limit_order.price,
+            self.assets.unwrap(output_id).denomination(),
+            DOLLAR_DENOMINATION,
+            self.assets
+                .unwrap(&limit_order.collateral_id)
+                .denomination(),
+        )
+    }
+
+    fn check_swap_limit_order(&self, limit_order: &LimitOrder) {
+        assert!(
+            limit_order.expiry <= env::block_timestamp_ms() + self.max_limit_order_life_sec * 1000,
+            "Max order lifetime exeeded"
+        );
+        assert!(
+            limit_order.attached_collateral > 0,
+            "Cannot create a swap order without tokens"
+        );
+        assert!(limit_order.price > 0, "Swap order price should be positive");
+        let output_id = limit_order.output_id.as_ref().unwrap();
+        assert!(
+            *output_id != limit_order.collateral_id,
+            "Swap tokens should be different"
+        );
+        self.assets.unwrap(output_id);
+    }
+
+    /// Price the limit order is compared against: the price of the
+    /// underlying, or the swap price for swap orders
+    fn get_limit_order_current_price(
+        &self,
+        underlying_id: &AssetId,
+        output_id: Option<&AssetId>,
+    ) -> DollarBalance {
+        match output_id {
+            Some(output_id) => self.get_swap_order_price(underlying_id, output_id),
+            None => self.assets.unwrap(underlying_id).price,
+        }
+    }
+
+    fn get_threshold(current_price: DollarBalance, price: DollarBalance) -> ThresholdType {
+        if current_price > price {
+            ThresholdType::Below
+        } else {
+            ThresholdType::Above
+        }
+    }
+
     fn check_limit_order(&self, limit_order: &LimitOrder) {
         assert!(
             limit_order.expiry <= env::block_timestamp_ms() + self.max_limit_order_life_sec * 1000,
@@ -133,6 +297,11 @@ impl Contract {
                 || limit_order.collateral_delta == 0,
             "You must not provide collateral delta on a buy order"
         );
+        assert!(
+            matches!(limit_order.order_type, OrderType::Decrease)
+                || !(limit_order.reduce_only || limit_order.close_position),
+            "Reduce-only and close-position flags are only valid on sell orders"
+        );
         assert!(limit_order.is_long || self.assets.get(&limit_order.collateral_id).unwrap().stable);
         assert!(
             !limit_order.is_long || limit_order.underlying_id == limit_order.collateral_id,
@@ -142,7 +311,9 @@ impl Contract {
         let limit_order_collateral_usd = self.get_collateral_in_usd(limit_order);
 
         assert!(
-            limit_order.size_delta > 0 || limit_order_collateral_usd > 0,
+            limit_order.size_delta > 0
+                || limit_order_collateral_usd > 0
+                || limit_order.close_position,
             "Can't create a limit order that changes nothing"
         );
 
@@ -213,6 +384,7 @@ impl Contract {
     /// Returns the id of the order (new or old if merge happened)
     pub fn add_limit_order(&mut self, mut params: AddLimitOrderParams) -> LimitOrderId {
         self.assert_running();
+        let initial_storage_usage = env::storage_usage();
         if let Some(expiry) = params.expiry {
             assert!(
                 expiry > env::block_timestamp_ms(),
@@ -220,20 +392,16 @@ impl Contract {
             );
         }
 
-        let underlying = self.assets.unwrap(&params.underlying_id);
-
         params.expiry =
             Some(params.expiry.unwrap_or_else(|| {
                 env::block_timestamp_ms() + self.max_limit_order_life_sec * 1000
             }));
 
+        let current_price =
+            self.get_limit_order_current_price(&params.underlying_id, params.output_id.as_ref());
         let mut limit_order = LimitOrder::new(
             params.clone(),
-            if underlying.price > params.price {
-                ThresholdType::Below
-            } else {
-                ThresholdType::Above
-            },
+            Self::get_threshold(current_price, params.price),
         );
 
         let mut limit_orders = self
@@ -254,16 +422,22 @@ impl Contract {
                 lo.owner == params.owner
                     && lo.order_type == params.order_type
                     && lo.collateral_id == params.collateral_id
+                    && lo.output_id == params.output_id
+                    && lo.reduce_only == params.reduce_only
+                    && lo.close_position == params.close_position
             }) {
             limit_order.collateral_delta += existing_order.collateral_delta;
             limit_order.attached_collateral += existing_order.attached_collateral;
             limit_order.size_delta += existing_order.size_delta;
+            limit_order.execution_fee += existing_order.execution_fee;
+            limit_order.storage_usage = existing_order.storage_usage;
 
-            self.check_limit_order(&limit_order);
+            self.check_any_limit_order(&limit_order);
 
             *existing_id
         } else {
-            self.check_limit_order(&limit_order);
+            self.check_any_limit_order(&limit_order);
+            self.check_limit_order_limits(&params.owner, &params.underlying_id);
             let id = LimitOrderId::new(&limit_order, self.get_limit_order_sequence_number());
             self.insert_limit_order_id(&id, &params);
 
@@ -271,14 +445,29 @@ impl Contract {
         };
 
         limit_orders.insert(id, limit_order.clone());
-
         self.limit_orders
             .insert(&params.underlying_id, &limit_orders);
 
-        self.validate_order_for_position(&limit_order);
+        let bytes = self.charge_storage(&params.owner, initial_storage_usage);
+        if bytes > 0 {
+            limit_order.storage_usage += bytes;
+            limit_orders.insert(id, limit_order.clone());
+            self.limit_orders
+                .insert(&params.underlying_id, &limit_orders);
+        }
+
+        if !limit_order.is_swap() {
+            self.validate_order_for_position(&limit_order);
+        }
+
+        Self::emit_place_limit_order(&id, limit_order);
 
+        id
+    }
+
+    fn emit_place_limit_order(id: &LimitOrderId, limit_order: LimitOrder) {
         emit_event(EventType::PlaceLimitOrder(PlaceLimitOrderEvent {
-            account_id: params.owner,
+            account_id: limit_order.owner,
             limit_order_id: id.into(),
             collateral_token: limit_order.collateral_id.into_string(),
             underlying_token: limit_order.underlying_id.into_string(),
@@ -290,17 +479,183 @@ impl Contract {
             price_usd: limit_order.price.into(),
             expiry: (limit_order.expiry as u128).into(),
             is_long: limit_order.is_long,
+            output_token: limit_order.output_id.map(|id| id.into_string()),
+            reduce_only: limit_order.reduce_only,
+            close_position: limit_order.close_position,
+            execution_fee_native: (limit_order.execution_fee > 0)
+                .then_some(limit_order.execution_fee.into()),
         }));
+    }
 
-        id
+    /// Change a resting limit order and re-check it. The order is re-keyed,
+    /// as its ID encodes the price. `deposit` of `deposit_id` pays for the
+    /// collateral top up and the execution fee increase. Returns the new ID
+    /// and the tokens to refund.
+    pub fn update_limit_order(
+        &mut self,
+        owner_id: &AccountId,
+        params: UpdateLimitOrderParameters,
+        deposit_id: &AssetId,
+        deposit: Balance,
+    ) -> (LimitOrderId, TransferInfo) {
+        self.assert_running();
+        let initial_storage_usage = env::storage_usage();
+        let limit_order_id = params.limit_order_id;
+        let mut limit_order_ids = self
+            .limit_order_ids_map
+            .get(owner_id)
+            .expect("You do not have any orders");
+        let asset_id = limit_order_ids
+            .remove(&limit_order_id)
+            .expect("You do not have any order with this ID");
+
+        let mut limit_orders = self.limit_orders.get(&asset_id).unwrap();
+        let mut limit_order = limit_orders.remove(&limit_order_id).unwrap();
+        let is_decrease = matches!(limit_order.order_type, OrderType::Decrease);
+        self.assert_limit_order_state(!is_decrease);
+
+        assert!(
+            deposit == 0 || *deposit_id == limit_order.collateral_id,
+            "Tokens must be attached in the collateral of the order"
+        );
+        let execution_fee = params
+            .execution_fee
+            .map_or(limit_order.execution_fee, |fee| fee.0);
+        let fee_increase = execution_fee.saturating_sub(limit_order.execution_fee);
+        assert!(fee_increase <= deposit, "Not enough tokens attached");
+        let top_up = match params.top_up {
+            Some(top_up) => top_up.0,
+            None if is_decrease => 0,
+            None => deposit - fee_increase,
+        };
+        let withdraw = params.withdraw.map_or(0, |withdraw| withdraw.0);
+        assert!(
+            top_up + fee_increase <= deposit,
+            "Not enough tokens attached"
+        );
+        if is_decrease {
+            assert!(
+                top_up == 0 && withdraw == 0,
+                "You cannot attach tokens on a sell order"
+            );
+            if let Some(collateral_delta) = params.collateral_delta {
+                limit_order.collateral_delta = collateral_delta.0;
+            }
+        } else {
+            assert!(
+                params.collateral_delta.is_none(),
+                "Collateral delta is only set on sell orders"
+            );
+            assert!(
+                withdraw <= limit_order.attached_collateral + top_up,
+                "Not enough collateral attached"
+            );
+            limit_order.attached_collateral = limit_order.attached_collateral + top_up - withdraw;
+        }
+        let refund = deposit - top_up - fee_increase
+            + withdraw
+            + limit_order.execution_fee.saturating_sub(execution_fee);
+        limit_order.execution_fee = execution_fee;
+        if let Some(price) = params.price {
+            limit_order.price = price.0;
+        }
+        if let Some(size_delta) = params.size_delta {
+            assert!(!limit_order.is_swap(), "Swap orders have no size");
+            limit_order.size_delta = size_delta.0;
+        }
+        if let Some(expiry) = params.expiry {
+            assert!(
+                expiry.0 > env::block_timestamp_ms(),
+                "Limit order already expired"
+            );
+            limit_order.expiry = expiry.0;
+        }
+        let current_price = self.get_limit_order_current_price(
+            &limit_order.underlying_id,
+            limit_order.output_id.as_ref(),
+        );
+        limit_order.threshold = Self::get_threshold(current_price, limit_order.price);
+        self.check_any_limit_order(&limit_order);
+
+        let id = LimitOrderId::new(&limit_order, self.get_limit_order_sequence_number());
+        limit_orders.insert(id, limit_order.clone());
+        self.limit_orders.insert(&asset_id, &limit_orders);
+        limit_order_ids.insert(id, asset_id.clone());
+        self.limit_order_ids_map.insert(owner_id, &limit_order_ids);
+
+        let storage_usage = env::storage_usage();
+        if storage_usage > initial_storage_usage {
+            limit_order.storage_usage += self.charge_storage(owner_id, initial_storage_usage);
+        } else {
+            let bytes = (initial_storage_usage - storage_usage).min(limit_order.storage_usage);
+            self.refund_storage(owner_id, bytes);
+            limit_order.storage_usage -= bytes;
+        }
+        limit_orders.insert(id, limit_order.clone());
+        self.limit_orders.insert(&asset_id, &limit_orders);
+
+        if !limit_order.is_swap() {
+            self.validate_order_for_position(&limit_order);
+        }
+
+        emit_event(EventType::RemoveLimitOrder(RemoveLimitOrderEvent {
+            account_id: owner_id.clone(),
+            underlying_token: limit_order.underlying_id.into_string(),
+            limit_order_id: limit_order_id.into(),
+            reason: RemoveOrderReason::Updated,
+            liquidator_id: None,
+        }));
+        let transfer_info = TransferInfo::new(owner_id, &limit_order.collateral_id, refund);
+        Self::emit_place_limit_order(&id, limit_order);
+
+        (id, transfer_info)
+    }
+
+    /// Escrow `amount` of `token_in` until the swap order is executed or
+    /// removed
+    pub fn add_swap_limit_order(
+        &mut self,
+        owner: &AccountId,
+        token_in: &AssetId,
+        amount: Balance,
+        params: SwapLimitOrderParameters,
+    ) -> LimitOrderId {
+        self.assert_limit_order_state(true);
+        let (attached_collateral, execution_fee) =
+            split_execution_fee(OrderType::Swap, amount, params.execution_fee);
+        self.add_limit_order(AddLimitOrderParams {
+            owner: owner.clone(),
+            collateral_id: token_in.clone(),
+            underlying_id: token_in.clone(),
+            collateral_delta_usd: 0,
+            attached_collateral_native: attached_collateral,
+            size: 0,
+            price: params.price.0,
+            is_long: true,
+            order_type: OrderType::Swap,
+            expiry: params.expiry.map(|e| e.0),
+            output_id: Some(AssetId::from(params.output_token_id)),
+            reduce_only: false,
+            close_position: false,
+            execution_fee,
+        })
+    }
+
+    fn check_any_limit_order(&self, limit_order: &LimitOrder) {
+        if limit_order.is_swap() {
+            self.check_swap_limit_order(limit_order);
+        } else {
+            self.check_limit_order(limit_order);
+        }
     }
 
     pub fn limit_order_is_eligible(&self, limit_order: &LimitOrder) -> bool {
-        let underlying = self.assets.get(&limit_order.underlying_id).unwrap();
-        (matches!(limit_order.threshold, ThresholdType::Above)
-            && underlying.price >= limit_order.price)
-            || (matches!(limit_order.threshold, ThresholdType::Below)
-                && underlying.price <= limit_order.price)
+        let price = self.get_limit_order_current_price(
+            &limit_order.underlying_id,
+            limit_order.output_id.as_ref(),
+        );
+        (matches!(limit_order.threshold, ThresholdType::Above) && price >= limit_order.price)
+            || (matches!(limit_order.threshold, ThresholdType::Below) && price <= limit_order.price)
     }
 
     pub fn get_eligible_orders(&self, asset_id: &AssetId, max: Option<u64>) -> Vec<LimitOrderId> {
@@ -311,7 +666,7 @@ impl Contract {
         };
 
         let underlying = self.assets.unwrap(asset_id);
-        let iter = limit_orders
+        let perps_orders = limit_orders
             .get_range_higher_than_price(underlying.price, true, ThresholdType::Below)
             .chain(limit_orders.get_range_lower_than_price(
                 underlying.price,
@@ -327,7 +682,29 @@ impl Contract {
                 underlying.price,
                 false,
                 ThresholdType::Above,
-            ));
+            ))
+            .filter(|(_, lo)| !lo.is_swap());
+
+        // Swap orders are stored as longs priced in units of their output
+        // asset, so they are checked against the price of each output asset.
+        let swap_orders = self
+            .assets
+            .0
+            .iter()
+            .filter(|(id, asset)| *id != asset_id && asset.price > 0)
+            .flat_map(|(output_id, _)| {
+                let price = self.get_swap_order_price(asset_id, output_id);
+                limit_orders
+                    .get_range_higher_than_price(price, true, ThresholdType::Below)
+                    .chain(limit_orders.get_range_lower_than_price(
+                        price,
+                        true,
+                        ThresholdType::Above,
+                    ))
+                    .filter(move |(_, lo)| lo.output_id.as_ref() == Some(output_id))
+            });
+
+        let iter = perps_orders.chain(swap_orders);
         if let Some(max) = max {
             iter.take(max as usize).map(|e| e.0).cloned().collect()
         } else {
@@ -345,6 +722,17 @@ impl Contract {
         }
     }
 
+    /// Credit back the storage freed since `initial_storage_usage` by
+    /// removing `limit_order`, up to the bytes the order paid for
+    fn refund_limit_order_storage(
+        &mut self,
+        limit_order: &LimitOrder,
+        initial_storage_usage: StorageUsage,
+    ) {
+        let bytes = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.refund_storage(&limit_order.owner, bytes.min(limit_order.storage_usage));
+    }
+
     fn insert_limit_order_id(&mut self, id: &LimitOrderId, params: &AddLimitOrderParams) {
         let mut limit_orders_ids = self
             .limit_order_ids_map
@@ -355,12 +743,14 @@ impl Contract {
             .insert(&params.owner, &limit_orders_ids);
     }
 
+    /// Execute an eligible limit order, or remove it if it expired. The
+    /// execution fee is paid to the caller.
     #[must_use]
     pub fn execute_limit_order(
         &mut self,
         asset_id: &AssetId,
         limit_order_id: &LimitOrderId,
-    ) -> Option<TransferInfo> {
+    ) -> Vec<TransferInfo> {
         self.assert_running();
         let mut limit_orders = self
             .limit_orders
@@ -376,8 +766,16 @@ impl Contract {
             return self.remove_outdated_limit_order(&limit_order.underlying_id, limit_order_id);
         }
 
+        if matches!(limit_order.order_type, OrderType::Decrease)
+            && !(self.permissionless_limit_order_execution && limit_order.execution_fee > 0)
+        {
+            self.assert_liquidator();
+        }
+
+        let initial_storage_usage = env::storage_usage();
         self.limit_orders.insert(asset_id, &limit_orders);
         self.remove_limit_order_id(limit_order_id, &limit_order.owner);
+        self.refund_limit_order_storage(&limit_order, initial_storage_usage);
 
         let res = match limit_order.order_type {
             OrderType::Increase => {
@@ -399,10 +797,12 @@ impl Contract {
                     &limit_order.underlying_id,
                     limit_order.is_long,
                 ) {
+                    let position = self.positions.get(&position_id).unwrap();
+                    let (collateral_delta, size_delta) = limit_order.get_decrease_deltas(&position);
                     let transfer_info = self.decrease_position(
                         position_id,
-                        limit_order.collateral_delta,
-                        limit_order.size_delta,
+                        collateral_delta,
+                        size_delta,
                         Some(*limit_order_id),
                         false,
                         None,
@@ -412,9 +812,39 @@ impl Contract {
                     Err(())
                 }
             }
+            OrderType::Swap => {
+                let output_id = limit_order.output_id.clone().unwrap();
+                let min_amount_out = self.get_swap_order_min_amount_out(&limit_order);
+                // Orders which can't be filled at their price after fees keep
+                // resting until they can
+                let amount_out = self.get_swap_amount_out(
+                    &limit_order.collateral_id,
+                    &output_id,
+                    limit_order.attached_collateral,
+                );
+                if !amount_out.is_some_and(|amount_out| amount_out >= min_amount_out) {
+                    env::panic_str("Swap order can not be filled at its price");
+                }
+                let amount_out = self.swap(
+                    &limit_order.collateral_id,
+                    &output_id,
+                    limit_order.attached_collateral,
+                    Some(min_amount_out),
+                );
+                Ok(Some(TransferInfo::new(
+                    &limit_order.owner,
+                    &output_id,
+                    amount_out,
+                )))
+            }
         };
 
-        match res {
+        let keeper_fee = TransferInfo::new(
+            &env::predecessor_account_id(),
+            &limit_order.collateral_id,
+            limit_order.execution_fee,
+        );
+        let transfer_info = match res {
             Ok(r) => {
                 emit_event(EventType::RemoveLimitOrder(RemoveLimitOrderEvent {
                     account_id: limit_order.owner,
@@ -427,24 +857,33 @@ impl Contract {
             }
             Err(()) => {
                 emit_event(EventType::RemoveLimitOrder(RemoveLimitOrderEvent {
-                    account_id: limit_order.owner,
+                    account_id: limit_order.owner.clone(),
                     underlying_token: limit_order.underlying_id.into(),
                     limit_order_id: limit_order_id.into(),
                     reason: RemoveOrderReason::Invalid,
                     liquidator_id: Some(env::predecessor_account_id()),
                 }));
-                None
+                // Refund the tokens attached to the order
+                Some(TransferInfo::new(
+                    &limit_order.owner,
+                    &limit_order.collateral_id,
+                    limit_order.attached_collateral,
+                ))
             }
-        }
+        };
+
+        transfer_info.into_iter().chain([keeper_fee]).collect()
     }
 
+    /// Remove an order and refund its collateral and execution fee to the
+    /// owner
     #[must_use]
     pub fn remove_limit_order(
         &mut self,
         owner_id: &AccountId,
         limit_order_id: &LimitOrderId,
         reason: RemoveOrderReason,
-    ) -> Option<TransferInfo> {
+    ) -> TransferInfo {
         self.assert_running();
         let limit_order_ids = self
             .limit_order_ids_map
@@ -458,8 +897,10 @@ impl Contract {
         let mut limit_orders = self.limit_orders.get(asset_id).unwrap();
         let limit_order = limit_orders.remove(limit_order_id).unwrap();
 
+        let initial_storage_usage = env::storage_usage();
         self.remove_limit_order_id(limit_order_id, &limit_order.owner);
         self.limit_orders.insert(asset_id, &limit_orders);
+        self.refund_limit_order_storage(&limit_order, initial_storage_usage);
 
         emit_event(EventType::RemoveLimitOrder(RemoveLimitOrderEvent {
             account_id: limit_order.owner.clone(),
@@ -469,22 +910,21 @@ impl Contract {
             liquidator_id: None,
         }));
 
-        match limit_order.order_type {
-            OrderType::Increase => Some(TransferInfo::new(
-                &limit_order.owner,
-                &limit_order.collateral_id,
-                limit_order.attached_collateral,
-            )),
-            OrderType::Decrease => None,
-        }
+        TransferInfo::new(
+            &limit_order.owner,
+            &limit_order.collateral_id,
+            limit_order.attached_collateral + limit_order.execution_fee,
+        )
     }
 
+    /// Remove an expired order. The collateral is refunded to the owner and
+    /// the execution fee paid to the caller.
     #[must_use]
     pub fn remove_outdated_limit_order(
         &mut self,
         asset_id: &AssetId,
         limit_order_id: &LimitOrderId,
-    ) -> Option<TransferInfo> {
+    ) -> Vec<TransferInfo> {
         self.assert_running();
         let mut limit_orders = self.limit_orders.get(asset_id).unwrap();
         let limit_order = limit_orders.remove(limit_order_id).unwrap();
@@ -493,8 +933,10 @@ impl Contract {
             env::panic_str("Limit order has not reached max limit order lifetime");
         }
 
+        let initial_storage_usage = env::storage_usage();
         self.remove_limit_order_id(limit_order_id, &limit_order.owner);
         self.limit_orders.insert(asset_id, &limit_orders);
+        self.refund_limit_order_storage(&limit_order, initial_storage_usage);
 
         emit_event(EventType::RemoveLimitOrder(RemoveLimitOrderEvent {
             account_id: limit_order.owner.clone(),
@@ -504,14 +946,18 @@ impl Contract {
             liquidator_id: Some(env::predecessor_account_id()),
         }));
 
-        match limit_order.order_type {
-            OrderType::Increase => Some(TransferInfo::new(
+        vec![
+            TransferInfo::new(
                 &limit_order.owner,
                 &limit_order.collateral_id,
                 limit_order.attached_collateral,
-            )),
-            OrderType::Decrease => None,
-        }
+            ),
+            TransferInfo::new(
+                &env::predecessor_account_id(),
+                &limit_order.collateral_id,
+                limit_order.execution_fee,
+            ),
+        ]
     }
 
     fn get_limit_order_sequence_number(&mut self) -> u64 {
@@ -539,7 +985,8 @@ impl Contract {
 
                 let limit_order = limit_orders.get_by_id(&limit_order_id).unwrap();
 
-                if limit_order.collateral_id == position.collateral_id.clone().into()
+                if !limit_order.is_swap()
+                    && limit_order.collateral_id == position.collateral_id.clone().into()
                     && limit_order.is_long == position.is_long
                 {
                     let collateral_usd = self.get_collateral_in_usd(limit_order);
@@ -574,10 +1021,7 @@ impl Contract {
                         &limit_order_id,
                         RemoveOrderReason::Invalid,
                     );
-
-                    if let Some(transfer_info) = transfer_info {
-                        amount_out += transfer_info.amount();
-                    }
+                    amount_out += transfer_info.amount();
                 }
             }
         }
@@ -600,18 +1044,20 @@ impl Contract {
                 current_size + limit_order.size_delta,
             )),
             OrderType::Decrease => {
-                if limit_order.collateral_delta > current_collateral {
+                let (collateral_delta, size_delta) = limit_order.get_decrease_deltas(position);
+                if collateral_delta > current_collateral {
                     return Err("Collateral delta must be lower or equal to position collateral");
                 }
-                if limit_order.size_delta > current_size {
+                if size_delta > current_size {
                     return Err("Size delta must be lower or equal to position size");
                 }
 
                 Ok((
-                    current_collateral - collateral_usd,
-                    current_size - limit_order.size_delta,
+                    current_collateral - collateral_delta,
+                    current_size - size_delta,
                 ))
             }
+            OrderType::Swap => Err("Swap orders do not change positions"),
         }
     }
 
@@ -650,6 +1096,67 @@ impl Contract {
         user_orders
     }
 
+    /// Resting increase and decrease orders on `asset_id` aggregated in
+    /// price buckets of `bucket_size`, for each side and threshold. Levels
+    /// are sorted by price within a side. Decrease sizes are capped at the
+    /// current position like on execution. Swap orders are not included.
+    pub fn get_limit_order_depth(
+        &self,
+        asset_id: &AssetId,
+        bucket_size: DollarBalance,
+        price_min: Option<DollarBalance>,
+        price_max: Option<DollarBalance>,
+    ) -> Vec<LimitOrderDepthLevel> {
+        assert!(bucket_size > 0, "Bucket size must be greater than 0");
+        let limit_orders = self.limit_orders.get(asset_id).unwrap_or_default();
+        let mut levels: Vec<LimitOrderDepthLevel> = vec![];
+
+        for is_long in [true, false] {
+            for threshold in [ThresholdType::Below, ThresholdType::Above] {
+                let orders = limit_orders
+                    .get_price_range(price_min, price_max, is_long, threshold)
+                    .filter(|(_, lo)| !lo.is_swap());
+                for (_, limit_order) in orders {
+                    let price = limit_order.price / bucket_size * bucket_size;
+                    if !matches!(levels.last(), Some(level) if level.price.0 == price
+                        && level.is_long == is_long
+                        && level.threshold == threshold)
+                    {
+                        levels.push(LimitOrderDepthLevel {
+                            price: price.into(),
+                            is_long,
+                            threshold,
+                            increase_size: 0.into(),
+                            decrease_size: 0.into(),
+                            num_orders: 0,
+                        });
+                    }
+                    let level = levels.last_mut().unwrap();
+                    level.num_orders += 1;
+                    match limit_order.order_type {
+                        OrderType::Increase => level.increase_size.0 += limit_order.size_delta,
+                        _ => {
+                            level.decrease_size.0 += match self.get_position_for_user(
+                                &limit_order.owner,
+                                &limit_order.collateral_id,
+                                &limit_order.underlying_id,
+                                limit_order.is_long,
+                            ) {
+                                Some(position_id) => {
+                                    let position = self.positions.get(&position_id).unwrap();
+                                    limit_order.get_decrease_deltas(&position).1
+                                }
+                                None => limit_order.size_delta,
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        levels
+    }
+
     /// Check that there is only one limit order of certain type for current position.
     /// Panic if position already has such order.
     fn validate_order_for_position(&self, limit_order: &LimitOrder) {
@@ -722,18 +1229,36 @@ impl LimitOrders {
         price_max: u128,
         is_long: bool,
         threshold: ThresholdType,
-    ) -> Range<LimitOrderId, LimitOrder> {
+    ) -> Range<'_, LimitOrderId, LimitOrder> {
         let min_id = LimitOrderId::get_min_id_from_price(price_min, is_long, threshold);
         let max_id = LimitOrderId::get_max_id_from_price(price_max, is_long, threshold);
         self.0.range((Included(min_id), Included(max_id)))
     }
 
+    /// Orders of one side priced between the bounds. Missing bounds are
+    /// open.
+    pub fn get_price_range(
+        &self,
+        price_min: Option<u128>,
+        price_max: Option<u128>,
+        is_long: bool,
+        threshold: ThresholdType,
+    ) -> Range<'_, LimitOrderId, LimitOrder> {
+        let min_id =
+            LimitOrderId::get_min_id_from_price(price_min.unwrap_or(0), is_long, threshold);
+        let max_id = match price_max {
+            Some(price_max) => LimitOrderId::get_max_id_from_price(price_max, is_long, threshold),
+            None => LimitOrderId::get_max_id(is_long, threshold),
+        };
+        self.0.range((Included(min_id), Included(max_id)))
+    }
+
     pub fn get_range_higher_than_price(
         &self,
         price: u128,
         is_long: bool,
         threshold: ThresholdType,
-    ) -> Range<LimitOrderId, LimitOrder> {
+    ) -> Range<'_, LimitOrderId, LimitOrder> {
         let min_id = LimitOrderId::get_min_id_from_price(price, is_long, threshold);
         let max_id = LimitOrderId::get_max_id(is_long, threshold);
         self.0.range((Included(min_id), Included(max_id)))
@@ -744,7 +1269,7 @@ impl LimitOrders {
         price: u128,
         is_long: bool,
         threshold: ThresholdType,
-    ) -> Range<LimitOrderId, LimitOrder> {
+    ) -> Range<'_, LimitOrderId, LimitOrder> {
         let min_id = LimitOrderId::get_min_id(is_long, threshold);
         let max_id = LimitOrderId::get_max_id_from_price(price, is_long, threshold);
         self.0.range((Included(min_id), Included(max_id)))
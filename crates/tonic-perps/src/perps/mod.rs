@@ -11,11 +11,13 @@ use std::time::Duration;
 mod limit_order;
 mod limit_order_id;
 mod position_id;
+mod twap_order;
 pub use limit_order::*;
 pub use limit_order_id::*;
-use near_sdk::{assert_one_yocto, json_types::U128, log};
+use near_sdk::{assert_one_yocto, json_types::U128, log, ONE_YOCTO};
 pub use position_id::*;
 use tonic_perps_sdk::prelude::{FeeType, RemoveOrderReason, TokenDepositWithdrawEvent};
+pub use twap_order::*;
 
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VPosition {
@@ -143,6 +145,29 @@ impl FeeResult {
     }
 }
 
+impl Position {
+    /// Collateral and size deltas to decrease the position by. Reduce-only
+    /// deltas are capped at the position, closing takes the whole size.
+    pub fn get_decrease_deltas(
+        &self,
+        collateral_delta: DollarBalance,
+        size_delta: DollarBalance,
+        reduce_only: bool,
+        close_position: bool,
+    ) -> (DollarBalance, DollarBalance) {
+        if close_position {
+            (0, self.size)
+        } else if reduce_only {
+            (
+                collateral_delta.min(self.collateral),
+                size_delta.min(self.size),
+            )
+        } else {
+            (collateral_delta, size_delta)
+        }
+    }
+}
+
 impl Drop for Position {
     fn drop(&mut self) {
         if self.size != 0 {
@@ -167,37 +192,69 @@ impl Contract {
         underlying_id: &AssetId,
         is_long: bool,
     ) {
-        let collateral = self.assets.unwrap(collateral_id);
-        let underlying = self.assets.unwrap(underlying_id);
+        if let Err(message) = self.check_tokens_new_position(
+            &self.assets.unwrap(collateral_id),
+            &self.assets.unwrap(underlying_id),
+            is_long,
+        ) {
+            env::panic_str(message);
+        }
+    }
+
+    fn check_tokens_new_position(
+        &self,
+        collateral: &Asset,
+        underlying: &Asset,
+        is_long: bool,
+    ) -> Result<(), &'static str> {
         if is_long {
-            assert_eq!(
-                collateral.asset_id, underlying.asset_id,
-                "Collateral token must equal underlying to increase position"
-            );
-            assert!(!collateral.stable, "Can not open position on stable coin");
+            if collateral.asset_id != underlying.asset_id {
+                return Err("Collateral token must equal underlying to increase position");
+            }
+            if collateral.stable {
+                return Err("Can not open position on stable coin");
+            }
         } else {
-            assert!(
-                collateral.stable,
-                "Must provide stablecoin collateral for short position"
-            );
-            assert!(!underlying.stable, "Can not short stablecoin");
-            assert!(underlying.shortable, "Can not short asset");
+            if !collateral.stable {
+                return Err("Must provide stablecoin collateral for short position");
+            }
+            if underlying.stable {
+                return Err("Can not short stablecoin");
+            }
+            if !underlying.shortable {
+                return Err("Can not short asset");
+            }
         }
+        Ok(())
     }
 
     /// Ensure asset price is not stale
     pub fn validate_asset_price(&self, asset_id: &AssetId) {
         let asset = self.assets.unwrap(asset_id);
         assert!(asset.price > 0, "Price should be greater than 0");
-        if !asset.stable {
-            let timestamp = env::block_timestamp_ms();
-            assert!(
-                timestamp - asset.last_change_timestamp_ms
-                    <= std::time::Duration::from_secs(self.max_staleness_duration_sec).as_millis()
-                        as u64,
-                "Asset price is too stale"
-            );
-        }
+        let timestamp = env::block_timestamp_ms();
+        assert!(
+            timestamp - asset.last_change_timestamp_ms
+                <= Duration::from_secs(self.get_max_staleness_duration_sec(&asset)).as_millis()
+                    as u64,
+            "Asset price is too stale"
+        );
+    }
+
+    /// Returns true if the price of the asset can be used, see
+    /// [Contract::validate_asset_price]
+    pub fn is_asset_price_valid(&self, asset: &Asset) -> bool {
+        asset.price > 0
+            && env::block_timestamp_ms() - asset.last_change_timestamp_ms
+                <= Duration::from_secs(self.get_max_staleness_duration_sec(asset)).as_millis()
+                    as u64
+    }
+
+    /// Maximum price staleness of an asset, falling back to the contract-wide value
+    pub fn get_max_staleness_duration_sec(&self, asset: &Asset) -> u64 {
+        asset
+            .max_staleness_duration_sec
+            .unwrap_or(self.max_staleness_duration_sec)
     }
 
     /// Find a user's existing position for a given collateral/underlying/direction
@@ -293,6 +350,46 @@ impl Contract {
             true => underlying.min_price(),
             false => underlying.max_price(),
         };
+        self.get_delta_at_price(
+            underlying,
+            price,
+            position_size,
+            average_price,
+            is_long,
+            last_increased_time,
+        )
+    }
+
+    /// Get total profit/loss on a position at the mark price. Used to
+    /// determine liquidations.
+    pub fn get_mark_delta(
+        &self,
+        underlying: &Asset,
+        position_size: DollarBalance,
+        average_price: DollarBalance,
+        is_long: bool,
+        last_increased_time: u64,
+    ) -> (bool, DollarBalance) {
+        assert!(average_price > 0, "Average price for position must be > 0");
+        self.get_delta_at_price(
+            underlying,
+            underlying.get_mark_price(),
+            position_size,
+            average_price,
+            is_long,
+            last_increased_time,
+        )
+    }
+
+    fn get_delta_at_price(
+        &self,
+        underlying: &Asset,
+        price: DollarBalance,
+        position_size: DollarBalance,
+        average_price: DollarBalance,
+        is_long: bool,
+        last_increased_time: u64,
+    ) -> (bool, DollarBalance) {
         let (has_profit, delta) = get_delta(average_price, price, position_size, is_long);
 
         let min_bps = if env::block_timestamp_ms()
@@ -381,8 +478,18 @@ impl Contract {
         );
 
         // Add fees separately for logging
-        collateral.add_fees(fees.funding_fee_native, FeeType::Funding, &account_id);
-        collateral.add_fees(fees.margin_fee_native, FeeType::Position, &account_id);
+        self.collect_fees(
+            collateral,
+            fees.funding_fee_native,
+            FeeType::Funding,
+            &account_id,
+        );
+        self.collect_fees(
+            collateral,
+            fees.margin_fee_native,
+            FeeType::Position,
+            &account_id,
+        );
 
         fees
     }
@@ -423,7 +530,8 @@ impl Contract {
         )
     }
 
-    /// Calculate liquidation price for a position
+    /// Calculate liquidation price for a position. Liquidations are checked
+    /// against the mark price of the underlying.
     pub fn get_liquidation_price(
         &self,
         position: &Position,
@@ -465,7 +573,7 @@ impl Contract {
         is_long: bool,
         is_liquidation: bool,
     ) -> (LiquidationStatus, FeeResult, u16) {
-        let (has_profit, delta) = self.get_delta(
+        let (has_profit, delta) = self.get_mark_delta(
             underlying,
             position.size,
             position.average_price,
@@ -606,13 +714,42 @@ impl Contract {
     }
 
     fn check_open_interest(&self, size: u128, asset_id: &AssetId, is_long: bool) {
+        if let Err(message) = self.validate_open_interest(size, asset_id, is_long) {
+            env::panic_str(message);
+        }
+    }
+
+    fn validate_open_interest(
+        &self,
+        size: u128,
+        asset_id: &AssetId,
+        is_long: bool,
+    ) -> Result<(), &'static str> {
         let asset = self.assets.unwrap(asset_id);
         if is_long && size + asset.global_long_size > asset.open_interest_limits.long {
-            env::panic_str("Too much open interest for longs");
+            return Err("Too much open interest for longs");
         }
         if size + asset.global_short_size > asset.open_interest_limits.short {
-            env::panic_str("Too much open interest for shorts");
+            return Err("Too much open interest for shorts");
         }
+        Ok(())
+    }
+
+    /// Check that the pool can reserve enough collateral for `size_delta`
+    fn validate_reserve(
+        &self,
+        collateral: &Asset,
+        size_delta: DollarBalance,
+        is_long: bool,
+    ) -> Result<(), &'static str> {
+        if is_long {
+            if collateral.from_max_usd_price(size_delta) > collateral.available_liquidity() {
+                return Err("Not enough reserve to allow the long position");
+            }
+        } else if size_delta > collateral.available_liquidity() {
+            return Err("Not enough reserve to allow the short position");
+        }
+        Ok(())
     }
 
     fn check_position_size(
@@ -727,6 +864,11 @@ impl Contract {
         let mut collateral = self.assets.unwrap(&collateral_id);
         assert!(collateral.state.perps.check(PerpsState::Enabled));
         assert!(underlying.state.perps.check(PerpsState::Enabled));
+        collateral.assert_not_close_only();
+        underlying.assert_not_close_only();
+        if !is_long {
+            collateral.assert_pegged();
+        }
 
         let collateral_delta_usd = collateral.to_min_usd_price(collateral_delta);
         let collateral_cumulative_funding_rate =
@@ -736,14 +878,8 @@ impl Contract {
 
         let reserve_delta = collateral.from_max_usd_price(size_delta);
 
-        if is_long {
-            if reserve_delta > collateral.available_liquidity() {
-                env::panic_str("Not enough reserve to allow the long position");
-            }
-        } else {
-            if size_delta > collateral.available_liquidity() {
-                env::panic_str("Not enough reserve to allow the short position");
-            }
+        if let Err(message) = self.validate_reserve(&collateral, size_delta, is_long) {
+            env::panic_str(message);
         }
 
         let position_id =
@@ -758,10 +894,8 @@ impl Contract {
         ));
         let position = self.positions.remove(&position_id);
 
-        let price = match is_long {
-            true => underlying.max_price(),
-            false => underlying.min_price(),
-        };
+        let price =
+            self.get_position_execution_price(&collateral, &underlying, size_delta, is_long, true);
 
         let (mut new_position, is_new) = match position {
             Some(mut position) => {
@@ -816,9 +950,11 @@ impl Contract {
         }
         new_position.collateral -= fees.total_fee_usd;
 
+        collateral.remove_collateral_position(new_position.size, new_position.entry_funding_rate);
         // getEntryFundingRate always returns the cumulative funding rate of the collateral token
         new_position.entry_funding_rate = collateral_cumulative_funding_rate;
         new_position.size += size_delta;
+        collateral.add_collateral_position(new_position.size, new_position.entry_funding_rate);
         new_position.last_increased_time = env::block_timestamp_ms();
 
         if new_position.size == 0 {
@@ -846,8 +982,8 @@ impl Contract {
             collateral.remove_liquidity(fees.total_fee_native, &owner_id);
 
             // As underlying token equals collateral token, modify it via collateral variable
-            collateral.increase_long_size(size_delta);
             collateral.update_long_average_price(price, size_delta);
+            collateral.increase_long_size(size_delta);
         } else {
             underlying.update_short_average_price(price, size_delta);
             underlying.increase_short_size(size_delta);
@@ -924,9 +1060,7 @@ impl Contract {
         assert!(!underlying.state.perps.check(PerpsState::Disabled));
         assert!(!collateral.state.perps.check(PerpsState::Disabled));
 
-        if limit_order_id.is_some() {
-            self.assert_liquidator();
-        } else {
+        if limit_order_id.is_none() {
             assert!(
                 is_liquidation || position.account_id == env::predecessor_account_id(),
                 "Can not decrease other account's position"
@@ -941,6 +1075,16 @@ impl Contract {
             env::panic_str("Can not take more than collateral out of position");
         }
 
+        // Liquidations are executed at the mark price without impact
+        let price = if is_liquidation {
+            match is_long {
+                true => underlying.min_price(),
+                false => underlying.max_price(),
+            }
+        } else {
+            self.get_position_execution_price(&collateral, &underlying, size_delta, is_long, false)
+        };
+
         if is_long {
             collateral.decrease_long_size(size_delta);
         } else {
@@ -952,8 +1096,9 @@ impl Contract {
         collateral.decrease_reserved_amount(reserve_delta, &owner_id);
         self.update_cumulative_funding_rate(&mut collateral);
 
-        let (has_profit, delta) = self.get_delta(
+        let (has_profit, delta) = self.get_delta_at_price(
             &underlying,
+            price,
             position.size,
             position.average_price,
             is_long,
@@ -1021,10 +1166,12 @@ impl Contract {
             };
         }
 
+        collateral.remove_collateral_position(position.size, position.entry_funding_rate);
         position.size -= size_delta;
         position.collateral -= total_collateral_reduction;
         if position.size > 0 {
             position.entry_funding_rate = collateral.cumulative_funding_rate;
+            collateral.add_collateral_position(position.size, position.entry_funding_rate);
             self.check_liquidation_status(&position, &collateral, &underlying, false);
         }
 
@@ -1065,10 +1212,7 @@ impl Contract {
             collateral_delta_usd: collateral_delta.into(),
             size_delta_usd: size_delta.into(),
             new_size_usd: new_size_usd.into(),
-            price_usd: match is_long {
-                true => underlying.min_price().into(),
-                false => underlying.max_price().into(),
-            },
+            price_usd: price.into(),
             total_fee_usd: fees.total_fee_usd.into(),
             margin_fee_usd: fees.margin_fee_usd.into(),
             position_fee_usd: fees.funding_fee_usd.into(),
@@ -1100,10 +1244,11 @@ impl Contract {
 
         if let Some(token_id) = output_token_id {
             let token_id = AssetId::from(token_id);
-            if token_id == collateral_id {
-                return TransferInfo::new(&owner_id, &collateral_id, amount_out);
+            let pool_token_id = self.pool_asset_id(&token_id);
+            if pool_token_id == collateral_id {
+                return TransferInfo::new(&owner_id, &token_id, amount_out);
             }
-            let output_amount = self.swap(&collateral_id, &token_id, amount_out, None);
+            let output_amount = self.swap(&collateral_id, &pool_token_id, amount_out, None);
             return TransferInfo::new(&owner_id, &token_id, output_amount);
         } else {
             TransferInfo::new(&owner_id, &collateral_id, amount_out)
@@ -1130,7 +1275,7 @@ impl Contract {
         let owner_id = position.account_id.clone();
         let size_delta = position.size;
 
-        let (has_profit, delta) = self.get_delta(
+        let (has_profit, delta) = self.get_mark_delta(
             &underlying,
             position.size,
             position.average_price,
@@ -1145,10 +1290,7 @@ impl Contract {
         let (status, fees, _) =
             self.get_liquidation_status(&position, &collateral, &underlying, is_long, true);
 
-        let mark_price = match is_long {
-            true => underlying.min_price(),
-            false => underlying.max_price(),
-        };
+        let mark_price = underlying.get_mark_price();
 
         log!(
             "Position liquidation status is {:?} at a price {}",
@@ -1173,9 +1315,20 @@ impl Contract {
         } else {
             underlying.decrease_short_size(position.size);
         }
+        collateral.remove_collateral_position(position.size, position.entry_funding_rate);
 
-        collateral.add_fees(fees.funding_fee_native, FeeType::Funding, &owner_id);
-        collateral.add_fees(fees.margin_fee_native, FeeType::Position, &owner_id);
+        self.collect_fees(
+            &mut collateral,
+            fees.funding_fee_native,
+            FeeType::Funding,
+            &owner_id,
+        );
+        self.collect_fees(
+            &mut collateral,
+            fees.margin_fee_native,
+            FeeType::Position,
+            &owner_id,
+        );
 
         collateral.decrease_reserved_amount(position.reserve_amount, &owner_id);
         self.update_cumulative_funding_rate(&mut collateral);
@@ -1318,6 +1471,12 @@ pub struct DecreasePositionRequest {
 
     /// Preferable token for receiving collateral and profits
     pub output_token_id: Option<String>,
+
+    /// Cap the deltas at the current position instead of failing
+    pub reduce_only: Option<bool>,
+
+    /// Close the entire position, ignoring the deltas
+    pub close_position: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1376,10 +1535,20 @@ impl VContract {
             self.set_user_referral_code(referrer_id);
         }
         let contract = self.contract_mut();
-        let transfer_info = contract.decrease_position(
-            params.position_id,
+        let position = contract
+            .positions
+            .get(&params.position_id)
+            .expect("Position not found");
+        let (collateral_delta, size_delta) = position.get_decrease_deltas(
             params.collateral_delta.0,
             params.size_delta.0,
+            params.reduce_only.unwrap_or(false),
+            params.close_position.unwrap_or(false),
+        );
+        let transfer_info = contract.decrease_position(
+            params.position_id,
+            collateral_delta,
+            size_delta,
             None,
             false,
             params.output_token_id,
@@ -1413,6 +1582,11 @@ impl VContract {
 
         contract.assert_limit_order_state(matches!(params.order_type, OrderType::Increase));
 
+        assert!(
+            !matches!(params.order_type, OrderType::Swap),
+            "Swap orders are placed with add_swap_limit_order"
+        );
+
         assert!(
             matches!(params.order_type, OrderType::Decrease) || params.collateral_delta.is_none(),
             "collateral_delta field is only required on sell orders"
@@ -1425,17 +1599,32 @@ impl VContract {
             "collateral_id field is only required on sell orders"
         );
 
+        let collateral_id = params.collateral_id.map_or(AssetId::NEAR, AssetId::from);
+        assert!(
+            env::attached_deposit() == 0 || collateral_id == AssetId::NEAR,
+            "The execution fee must be attached in the collateral of the order"
+        );
+        let (attached_collateral, execution_fee) = split_execution_fee(
+            params.order_type,
+            env::attached_deposit(),
+            params.execution_fee,
+        );
+
         let id = contract.add_limit_order(AddLimitOrderParams {
             owner: env::predecessor_account_id(),
-            collateral_id: params.collateral_id.map_or(AssetId::NEAR, AssetId::from),
+            collateral_id,
             underlying_id: AssetId::from(params.underlying_id),
             collateral_delta_usd: params.collateral_delta.unwrap_or(U128(0)).0,
-            attached_collateral_native: env::attached_deposit(),
+            attached_collateral_native: attached_collateral,
             size: params.size_delta.0,
             price: params.price.0,
             is_long: params.is_long,
             order_type: params.order_type,
             expiry: params.expiry.map(|e| e.0),
+            output_id: None,
+            reduce_only: params.reduce_only.unwrap_or(false),
+            close_position: params.close_position.unwrap_or(false),
+            execution_fee,
         });
 
         emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
@@ -1450,28 +1639,93 @@ impl VContract {
         id
     }
 
+    /// Place a limit order swapping the attached NEAR
+    #[payable]
+    pub fn add_swap_limit_order(&mut self, params: SwapLimitOrderParameters) -> LimitOrderId {
+        let contract = self.contract_mut();
+        let id = contract.add_swap_limit_order(
+            &env::predecessor_account_id(),
+            &AssetId::NEAR,
+            env::attached_deposit(),
+            params,
+        );
+
+        emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
+            amount_native: env::attached_deposit().into(),
+            deposit: true,
+            method: "add_swap_limit_order".to_string(),
+            receiver_id: env::current_account_id(),
+            account_id: env::predecessor_account_id(),
+            asset_id: AssetId::NEAR.into_string(),
+        }));
+
+        id
+    }
+
     #[payable]
     pub fn remove_limit_order(&mut self, limit_order_id: LimitOrderId) {
         assert_one_yocto();
         let owner_id = env::predecessor_account_id();
-        if let Some(transfer_info) = self.contract_mut().remove_limit_order(
+        let transfer_info = self.contract_mut().remove_limit_order(
             &owner_id,
             &limit_order_id,
             RemoveOrderReason::Removed,
-        ) {
-            self.contract()
-                .internal_send(transfer_info, "remove_limit_order");
+        );
+        self.contract()
+            .internal_send(transfer_info, "remove_limit_order");
+    }
+
+    /// Change the price, size, collateral, expiry or execution fee of a limit
+    /// order. The order gets a new ID. Attached NEAR pays for the changes of
+    /// orders paid in NEAR; other tokens are attached with
+    /// `ft_transfer_call`. Requires at least 1 yoctoNEAR, a single yoctoNEAR
+    /// is not used as a deposit.
+    #[payable]
+    pub fn update_limit_order(&mut self, params: UpdateLimitOrderParameters) -> LimitOrderId {
+        // Function call access keys can't withdraw escrowed collateral
+        assert!(
+            env::attached_deposit() >= ONE_YOCTO,
+            "Requires attached deposit of at least 1 yoctoNEAR"
+        );
+        let deposit = if env::attached_deposit() == ONE_YOCTO {
+            0
+        } else {
+            env::attached_deposit()
+        };
+        let contract = self.contract_mut();
+        let (id, transfer_info) = contract.update_limit_order(
+            &env::predecessor_account_id(),
+            params,
+            &AssetId::NEAR,
+            deposit,
+        );
+
+        if deposit > 0 {
+            emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
+                amount_native: deposit.into(),
+                deposit: true,
+                method: "update_limit_order".to_string(),
+                receiver_id: env::current_account_id(),
+                account_id: env::predecessor_account_id(),
+                asset_id: AssetId::NEAR.into_string(),
+            }));
         }
+        contract.internal_send(transfer_info, "update_limit_order");
+
+        id
     }
 
+    /// Execute an eligible limit order and collect its execution fee.
+    /// Decrease orders are executed by liquidators, or by anyone if they
+    /// have an execution fee and permissionless execution is enabled.
     #[payable]
     pub fn execute_limit_order(&mut self, asset_id: String, limit_order_id: LimitOrderId) {
         assert_one_yocto();
         let asset_id = &AssetId::from(asset_id);
-        if let Some(transfer_info) = self
+        let transfers = self
             .contract_mut()
-            .execute_limit_order(asset_id, &limit_order_id)
-        {
+            .execute_limit_order(asset_id, &limit_order_id);
+        for transfer_info in transfers {
             self.contract()
                 .internal_send(transfer_info, "execute_limit_order");
         }
@@ -1480,10 +1734,10 @@ impl VContract {
     #[payable]
     pub fn remove_outdated_limit_order(&mut self, asset_id: String, limit_order_id: LimitOrderId) {
         assert_one_yocto();
-        if let Some(transfer_info) = self
+        let transfers = self
             .contract_mut()
-            .remove_outdated_limit_order(&asset_id.into(), &limit_order_id)
-        {
+            .remove_outdated_limit_order(&asset_id.into(), &limit_order_id);
+        for transfer_info in transfers {
             self.contract()
                 .internal_send(transfer_info, "remove_outdated_limit_order");
         }
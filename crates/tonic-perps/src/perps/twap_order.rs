@@ -0,0 +1,400 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, AccountId, Balance, StorageUsage};
+
+use crate::{
+    borsh, emit_event, env, near_bindgen, AssetId, BorshDeserialize, BorshSerialize, Contract,
+    Deserialize, DollarBalance, EventType, LiquidationStatus, PerpsState, PlaceTwapOrderEvent,
+    Position, RemoveOrderReason, RemoveTwapOrderEvent, Serialize, TokenDepositWithdrawEvent,
+    TransferInfo, TwapSliceEvent, TwapSliceStatus, VContract, VContractExt, MAX_LEVERAGE_MESSAGE,
+    MIN_LEVERAGE_MESSAGE,
+};
+
+/// Maximum number of slices of a TWAP order
+pub const MAX_TWAP_SLICES: u16 = 100;
+
+pub type TwapOrderId = u64;
+
+/// An order increasing a position in equal slices over time. The collateral
+/// is escrowed when the order is placed and split between the slices.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct TwapOrder {
+    /// The account creating the order
+    pub owner: AccountId,
+    /// The ID of the collateral asset. The deposit is swapped into it when
+    /// the order is placed.
+    pub collateral_id: AssetId,
+    /// The ID of the underlying asset
+    pub underlying_id: AssetId,
+    /// Long or short
+    pub is_long: bool,
+    /// Escrowed collateral not used by executed slices yet
+    pub remaining_collateral: Balance,
+    /// Size delta of each slice, in USD
+    pub slice_size: DollarBalance,
+    pub num_slices: u16,
+    pub remaining_slices: u16,
+    /// Minimum time between two slices
+    pub interval_sec: u64,
+    /// Earliest time of the next slice, in ms
+    pub next_slice_time: u64,
+    /// Storage paid by the owner for the order
+    pub storage_usage: StorageUsage,
+}
+
+impl TwapOrder {
+    /// Collateral of the next slice. The collateral of skipped slices is
+    /// spread over the remaining ones.
+    pub fn slice_collateral(&self) -> Balance {
+        self.remaining_collateral / Balance::from(self.remaining_slices)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TwapOrderParameters {
+    pub underlying_id: String,
+    pub is_long: bool,
+    /// Size delta of the whole order, in USD
+    pub size_delta: U128,
+    pub num_slices: u16,
+    pub interval_sec: u64,
+}
+
+impl Contract {
+    fn get_twap_order_sequence_number(&mut self) -> TwapOrderId {
+        self.twap_order_sequence += 1;
+        self.twap_order_sequence
+    }
+
+    /// Escrow `amount` of `token_in` as the collateral of a TWAP order. The
+    /// first slice can be executed right away. The owner pays for the storage
+    /// of the order with its order storage balance.
+    pub fn add_twap_order(
+        &mut self,
+        owner: &AccountId,
+        token_in: &AssetId,
+        amount: Balance,
+        params: TwapOrderParameters,
+    ) -> TwapOrderId {
+        self.assert_running();
+        self.assert_limit_order_state(true);
+        self.assert_leverage_enabled();
+        assert!(
+            params.num_slices > 0 && params.num_slices <= MAX_TWAP_SLICES,
+            "Number of slices must be between 1 and {}",
+            MAX_TWAP_SLICES
+        );
+        assert!(params.interval_sec > 0, "Interval must be greater than 0");
+        assert!(amount > 0, "TWAP orders require collateral");
+        let slice_size = params.size_delta.0 / DollarBalance::from(params.num_slices);
+        assert!(slice_size > 0, "Slice size must be greater than 0");
+
+        let underlying_id = AssetId::from(params.underlying_id);
+        let (collateral_id, collateral_amount) =
+            self.swap_collateral(amount, token_in, &underlying_id, params.is_long);
+        self.validate_tokens_new_position(&collateral_id, &underlying_id, params.is_long);
+
+        let collateral_usd = self
+            .assets
+            .unwrap(&collateral_id)
+            .to_min_usd_price(collateral_amount);
+        let total_size = slice_size * DollarBalance::from(params.num_slices);
+        if collateral_usd == 0
+            || self.check_leverage(total_size, collateral_usd, false).0
+                == LiquidationStatus::MaxLeverageExceeded
+        {
+            env::panic_str("TWAP order exceeds maximum leverage");
+        }
+
+        let id = self.get_twap_order_sequence_number();
+        let initial_storage_usage = env::storage_usage();
+        let mut order = TwapOrder {
+            owner: owner.clone(),
+            collateral_id,
+            underlying_id,
+            is_long: params.is_long,
+            remaining_collateral: collateral_amount,
+            slice_size,
+            num_slices: params.num_slices,
+            remaining_slices: params.num_slices,
+            interval_sec: params.interval_sec,
+            next_slice_time: env::block_timestamp_ms(),
+            storage_usage: 0,
+        };
+        self.twap_orders.insert(&id, &order);
+        order.storage_usage = self.charge_storage(owner, initial_storage_usage);
+        self.twap_orders.insert(&id, &order);
+
+        emit_event(EventType::PlaceTwapOrder(PlaceTwapOrderEvent {
+            account_id: order.owner,
+            twap_order_id: id,
+            collateral_token: order.collateral_id.into_string(),
+            underlying_token: order.underlying_id.into_string(),
+            collateral_native: collateral_amount.into(),
+            size_delta_usd: total_size.into(),
+            num_slices: order.num_slices,
+            interval_sec: order.interval_sec,
+            is_long: order.is_long,
+        }));
+
+        id
+    }
+
+    /// Check that the next slice of the order can increase the position the
+    /// way [Contract::increase_position] would: with valid prices, within
+    /// open interest, reserve and size limits, and within leverage and
+    /// solvency limits after fees and price impact.
+    pub fn validate_twap_slice(&self, order: &TwapOrder) -> Result<(), String> {
+        if !self.leverage_enabled {
+            return Err("Leverage positions are currently disabled".to_string());
+        }
+        let mut collateral = self.assets.unwrap(&order.collateral_id);
+        let underlying = self.assets.unwrap(&order.underlying_id);
+        self.check_tokens_new_position(&collateral, &underlying, order.is_long)?;
+        if !self.is_asset_price_valid(&collateral) || !self.is_asset_price_valid(&underlying) {
+            return Err("Asset price is too stale".to_string());
+        }
+        if !collateral.state.perps.check(PerpsState::Enabled)
+            || !underlying.state.perps.check(PerpsState::Enabled)
+        {
+            return Err("Perps are disabled for the asset".to_string());
+        }
+        if collateral.is_close_only() || underlying.is_close_only() {
+            return Err("Asset is in close-only mode".to_string());
+        }
+        if !order.is_long && collateral.is_depegged() {
+            return Err("Stablecoin is outside of its peg band".to_string());
+        }
+        let cumulative_funding_rate = self.update_cumulative_funding_rate(&mut collateral);
+        self.validate_open_interest(order.slice_size, &order.underlying_id, order.is_long)?;
+        self.validate_reserve(&collateral, order.slice_size, order.is_long)?;
+
+        let price = self.get_position_execution_price(
+            &collateral,
+            &underlying,
+            order.slice_size,
+            order.is_long,
+            true,
+        );
+        let mut position = match self.get_position_for_user(
+            &order.owner,
+            &order.collateral_id,
+            &order.underlying_id,
+            order.is_long,
+        ) {
+            Some(position_id) => {
+                let mut position = self.positions.get(&position_id).unwrap();
+                position.average_price = self.get_next_average_price(
+                    &underlying,
+                    position.size,
+                    position.average_price,
+                    order.is_long,
+                    price,
+                    order.slice_size,
+                    position.last_increased_time,
+                );
+                position
+            }
+            None => Position {
+                size: 0,
+                collateral: 0,
+                average_price: price,
+                entry_funding_rate: 0,
+                reserve_amount: 0,
+                realized_pnl: 0,
+                last_increased_time: 0,
+
+                collateral_id: order.collateral_id.clone().into(),
+                underlying_id: order.underlying_id.clone().into(),
+                account_id: order.owner.clone(),
+                is_long: order.is_long,
+            },
+        };
+
+        let fees = self.get_fees(
+            &collateral,
+            order.slice_size,
+            position.size,
+            position.entry_funding_rate,
+            order.is_long,
+        );
+        position.collateral += collateral.to_min_usd_price(order.slice_collateral());
+        if position.collateral < fees.total_fee_usd {
+            return Err("Position collateral is less than the fee".to_string());
+        }
+        position.collateral -= fees.total_fee_usd;
+        position.entry_funding_rate = cumulative_funding_rate;
+        position.size += order.slice_size;
+        position.last_increased_time = env::block_timestamp_ms();
+        self.check_position_size(&underlying, position.size, order.is_long)?;
+
+        match self
+            .check_leverage(position.size, position.collateral, false)
+            .0
+        {
+            LiquidationStatus::MaxLeverageExceeded => return Err(MAX_LEVERAGE_MESSAGE.to_string()),
+            LiquidationStatus::BelowMinLeverage => return Err(MIN_LEVERAGE_MESSAGE.to_string()),
+            _ => (),
+        }
+        match self
+            .get_liquidation_status(&position, &collateral, &underlying, order.is_long, false)
+            .0
+        {
+            LiquidationStatus::Insolvent(message) => Err(message),
+            LiquidationStatus::MaxLeverageExceeded => Err(MAX_LEVERAGE_MESSAGE.to_string()),
+            LiquidationStatus::BelowMinLeverage => Err(MIN_LEVERAGE_MESSAGE.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove a TWAP order and credit back its storage to the owner
+    fn delete_twap_order(&mut self, twap_order_id: TwapOrderId, order: &TwapOrder) {
+        let initial_storage_usage = env::storage_usage();
+        self.twap_orders.remove(&twap_order_id);
+        let released_storage_usage = initial_storage_usage.saturating_sub(env::storage_usage());
+        self.refund_storage(
+            &order.owner,
+            order.storage_usage.min(released_storage_usage),
+        );
+    }
+
+    /// Execute the next slice of a TWAP order, or skip it if it fails
+    /// validation. Once the last slice is done, the order is removed and the
+    /// remaining collateral refunded.
+    #[must_use]
+    pub fn execute_twap_slice(&mut self, twap_order_id: TwapOrderId) -> TransferInfo {
+        self.assert_running();
+        self.assert_liquidator();
+        let mut order = self
+            .twap_orders
+            .get(&twap_order_id)
+            .expect("TWAP order not found");
+        if order.next_slice_time > env::block_timestamp_ms() {
+            env::panic_str("TWAP slice is not ready to be executed");
+        }
+
+        let slice_collateral = order.slice_collateral();
+        let (status, reason, mut amount_out) = match self.validate_twap_slice(&order) {
+            Ok(()) => {
+                let (_, transfer_info) = self.increase_position(
+                    &order.owner,
+                    &order.collateral_id,
+                    &order.underlying_id,
+                    slice_collateral,
+                    order.slice_size,
+                    order.is_long,
+                    None,
+                );
+                order.remaining_collateral -= slice_collateral;
+                (TwapSliceStatus::Executed, None, transfer_info.amount())
+            }
+            Err(reason) => (TwapSliceStatus::Skipped, Some(reason), 0),
+        };
+        order.remaining_slices -= 1;
+        order.next_slice_time = env::block_timestamp_ms() + order.interval_sec * 1000;
+
+        emit_event(EventType::TwapSlice(TwapSliceEvent {
+            account_id: order.owner.clone(),
+            twap_order_id,
+            underlying_token: order.underlying_id.into_string(),
+            status,
+            reason,
+            collateral_native: slice_collateral.into(),
+            size_delta_usd: order.slice_size.into(),
+            remaining_slices: order.remaining_slices,
+            liquidator_id: env::predecessor_account_id(),
+        }));
+
+        if order.remaining_slices > 0 {
+            self.twap_orders.insert(&twap_order_id, &order);
+        } else {
+            self.delete_twap_order(twap_order_id, &order);
+            amount_out += order.remaining_collateral;
+            emit_event(EventType::RemoveTwapOrder(RemoveTwapOrderEvent {
+                account_id: order.owner.clone(),
+                twap_order_id,
+                underlying_token: order.underlying_id.into_string(),
+                reason: RemoveOrderReason::Executed,
+                refund_native: order.remaining_collateral.into(),
+            }));
+        }
+
+        TransferInfo::new(&order.owner, &order.collateral_id, amount_out)
+    }
+
+    /// Cancel a TWAP order and refund the remaining collateral. Also allowed
+    /// while the contract is paused.
+    #[must_use]
+    pub fn remove_twap_order(
+        &mut self,
+        owner_id: &AccountId,
+        twap_order_id: TwapOrderId,
+    ) -> TransferInfo {
+        let order = self
+            .twap_orders
+            .get(&twap_order_id)
+            .expect("TWAP order not found");
+        assert_eq!(&order.owner, owner_id, "You do not own this TWAP order");
+        self.delete_twap_order(twap_order_id, &order);
+
+        emit_event(EventType::RemoveTwapOrder(RemoveTwapOrderEvent {
+            account_id: order.owner.clone(),
+            twap_order_id,
+            underlying_token: order.underlying_id.into_string(),
+            reason: RemoveOrderReason::Removed,
+            refund_native: order.remaining_collateral.into(),
+        }));
+
+        TransferInfo::new(
+            &order.owner,
+            &order.collateral_id,
+            order.remaining_collateral,
+        )
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Place a TWAP order with NEAR as collateral. Other collateral payment
+    /// requires `ft_transfer_call`.
+    #[payable]
+    pub fn add_twap_order(&mut self, params: TwapOrderParameters) -> U64 {
+        let id = self.contract_mut().add_twap_order(
+            &env::predecessor_account_id(),
+            &AssetId::NEAR,
+            env::attached_deposit(),
+            params,
+        );
+
+        emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
+            amount_native: env::attached_deposit().into(),
+            deposit: true,
+            method: "add_twap_order".to_string(),
+            receiver_id: env::current_account_id(),
+            account_id: env::predecessor_account_id(),
+            asset_id: AssetId::NEAR.into_string(),
+        }));
+
+        id.into()
+    }
+
+    /// Execute the next slice of a TWAP order once the slice interval has
+    /// passed. Only callable by liquidators.
+    #[payable]
+    pub fn execute_twap_slice(&mut self, twap_order_id: U64) {
+        assert_one_yocto();
+        let transfer_info = self.contract_mut().execute_twap_slice(twap_order_id.0);
+        self.contract()
+            .internal_send(transfer_info, "execute_twap_slice");
+    }
+
+    #[payable]
+    pub fn remove_twap_order(&mut self, twap_order_id: U64) {
+        assert_one_yocto();
+        let transfer_info = self
+            .contract_mut()
+            .remove_twap_order(&env::predecessor_account_id(), twap_order_id.0);
+        self.contract()
+            .internal_send(transfer_info, "remove_twap_order");
+    }
+}
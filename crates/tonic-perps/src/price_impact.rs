@@ -0,0 +1,189 @@
+use near_sdk::json_types::U128;
+
+use crate::{
+    borsh, convert_assets, near_bindgen, ratio, Asset, AssetId, Balance, BorshDeserialize,
+    BorshSerialize, Contract, Deserialize, DollarBalance, Serialize, VContract, VContractExt,
+    BPS_DIVISOR,
+};
+
+/// Parameters of the price impact applied to positions and swaps. The
+/// impact is positive (worse execution) for large trades and trades
+/// increasing the imbalance, and negative (better execution) for trades
+/// reducing the imbalance.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceImpactParameters {
+    /// Impact of a trade as large as the available liquidity
+    pub liquidity_factor_bps: u16,
+    /// Impact of a trade changing the imbalance by the available liquidity.
+    /// The imbalance is the open interest skew for positions and the
+    /// distance from target weights for swaps.
+    pub imbalance_factor_bps: u16,
+    /// Cap on the impact in both directions. Added to the maximum fee, it
+    /// must stay below 100%.
+    pub max_impact_bps: u16,
+}
+
+impl PriceImpactParameters {
+    pub fn is_enabled(&self) -> bool {
+        self.max_impact_bps > 0 && (self.liquidity_factor_bps > 0 || self.imbalance_factor_bps > 0)
+    }
+
+    /// Impact of a trade of `size_usd` changing the imbalance by
+    /// `imbalance_delta_usd`, in bps
+    pub fn get_impact_bps(
+        &self,
+        size_usd: DollarBalance,
+        imbalance_delta_usd: i128,
+        liquidity_usd: DollarBalance,
+    ) -> i128 {
+        if !self.is_enabled() || liquidity_usd == 0 {
+            return 0;
+        }
+        let size_bps = ratio(size_usd, self.liquidity_factor_bps, liquidity_usd) as i128;
+        let imbalance_bps = ratio(
+            imbalance_delta_usd.unsigned_abs(),
+            self.imbalance_factor_bps,
+            liquidity_usd,
+        ) as i128
+            * imbalance_delta_usd.signum();
+        let max_bps = i128::from(self.max_impact_bps);
+        (size_bps + imbalance_bps).clamp(-max_bps, max_bps)
+    }
+}
+
+/// Move `value` by `impact_bps`, upwards if `increase`
+pub fn apply_price_impact(value: u128, impact_bps: i128, increase: bool) -> u128 {
+    let bps = if increase { impact_bps } else { -impact_bps };
+    ratio(value, (BPS_DIVISOR as i128 + bps) as u128, BPS_DIVISOR)
+}
+
+impl Contract {
+    /// Price impact of changing a position by `size_delta`. Liquidity is
+    /// the available liquidity of the collateral, the imbalance the
+    /// difference between long and short open interest of the underlying.
+    pub fn get_position_price_impact_bps(
+        &self,
+        collateral: &Asset,
+        underlying: &Asset,
+        size_delta: DollarBalance,
+        is_long: bool,
+        increase: bool,
+    ) -> i128 {
+        let params = &self.price_impact_parameters;
+        if !params.is_enabled() || size_delta == 0 {
+            return 0;
+        }
+        let (long_size, short_size) = (underlying.global_long_size, underlying.global_short_size);
+        let apply = |size: DollarBalance| {
+            if increase {
+                size + size_delta
+            } else {
+                size.saturating_sub(size_delta)
+            }
+        };
+        let (next_long_size, next_short_size) = if is_long {
+            (apply(long_size), short_size)
+        } else {
+            (long_size, apply(short_size))
+        };
+        let imbalance_delta = next_long_size.abs_diff(next_short_size) as i128
+            - long_size.abs_diff(short_size) as i128;
+        let liquidity_usd = collateral.to_min_usd_price(collateral.available_liquidity());
+        params.get_impact_bps(size_delta, imbalance_delta, liquidity_usd)
+    }
+
+    /// Price at which a position change is executed. Buying (increasing
+    /// longs, decreasing shorts) happens at the max price, selling at the
+    /// min price, both moved by the price impact.
+    pub fn get_position_execution_price(
+        &self,
+        collateral: &Asset,
+        underlying: &Asset,
+        size_delta: DollarBalance,
+        is_long: bool,
+        increase: bool,
+    ) -> DollarBalance {
+        let is_buy = is_long == increase;
+        let price = if is_buy {
+            underlying.max_price()
+        } else {
+            underlying.min_price()
+        };
+        let impact_bps = self
+            .get_position_price_impact_bps(collateral, underlying, size_delta, is_long, increase);
+        apply_price_impact(price, impact_bps, is_buy)
+    }
+
+    /// Price impact of a swap with `amount_out` of output before the
+    /// impact. Liquidity is the available liquidity of the output asset,
+    /// the imbalance the distance of both assets from their target value.
+    pub fn get_swap_price_impact_bps(
+        &self,
+        asset_in: &Asset,
+        asset_out: &Asset,
+        amount_in: Balance,
+        amount_out: Balance,
+    ) -> i128 {
+        let params = &self.price_impact_parameters;
+        if !params.is_enabled() || amount_out == 0 {
+            return 0;
+        }
+        let total_usd = self.get_total_value();
+        let (in_usd, in_target_usd) = self.get_asset_rebalance_usd(asset_in, total_usd);
+        let (out_usd, out_target_usd) = self.get_asset_rebalance_usd(asset_out, total_usd);
+        let next_in_usd = in_usd + asset_in.dollar_value_of(amount_in);
+        let next_out_usd = out_usd.saturating_sub(asset_out.dollar_value_of(amount_out));
+        let imbalance_delta = (next_in_usd.abs_diff(in_target_usd)
+            + next_out_usd.abs_diff(out_target_usd)) as i128
+            - (in_usd.abs_diff(in_target_usd) + out_usd.abs_diff(out_target_usd)) as i128;
+
+        let size_usd = asset_out.dollar_value_of(amount_out);
+        let liquidity_usd = asset_out.dollar_value_of(asset_out.available_liquidity());
+        params.get_impact_bps(size_usd, imbalance_delta, liquidity_usd)
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Price impact in bps of changing a position. Negative values improve
+    /// the execution price.
+    pub fn get_position_price_impact_bps(
+        &self,
+        collateral_id: String,
+        underlying_id: String,
+        size_delta: U128,
+        is_long: bool,
+        increase: bool,
+    ) -> i32 {
+        let contract = self.contract();
+        contract.get_position_price_impact_bps(
+            &contract.assets.unwrap(&AssetId::from(collateral_id)),
+            &contract.assets.unwrap(&AssetId::from(underlying_id)),
+            size_delta.0,
+            is_long,
+            increase,
+        ) as i32
+    }
+
+    /// Price impact in bps of swapping `amount_in` of `token_in`. Negative
+    /// values increase the output.
+    pub fn get_swap_price_impact_bps(
+        &self,
+        token_in: String,
+        token_out: String,
+        amount_in: U128,
+    ) -> i32 {
+        let contract = self.contract();
+        let asset_in = contract.assets.unwrap(&AssetId::from(token_in));
+        let asset_out = contract.assets.unwrap(&AssetId::from(token_out));
+        let amount_out = convert_assets(
+            amount_in.0,
+            asset_in.min_price(),
+            asset_out.denomination(),
+            asset_out.max_price(),
+            asset_in.denomination(),
+        );
+        contract.get_swap_price_impact_bps(&asset_in, &asset_out, amount_in.0, amount_out) as i32
+    }
+}
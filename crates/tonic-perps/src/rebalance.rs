@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::U128;
+use near_sdk::{log, Gas, Promise, PromiseError, ONE_YOCTO};
+
+use crate::{
+    borsh, convert_assets, env, near_bindgen, ratio, u128_dec_format, AccountId, Action, Asset,
+    AssetId, Balance, BorshDeserialize, BorshSerialize, Contract, Deserialize, DollarBalance,
+    Serialize, VContract, VContractExt, BPS_DIVISOR,
+};
+
+pub const TGAS_FOR_REBALANCE_SWAP: u64 = 150;
+pub const TGAS_FOR_REBALANCE_CALLBACK: u64 = 10;
+/// Time after which admins can clear a rebalance which was never resolved
+pub const PENDING_REBALANCE_TIMEOUT_SEC: u64 = 60 * 60;
+
+/// Parameters of pool rebalancing
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RebalanceParameters {
+    /// Swap fee discount for swaps moving both assets towards their target
+    /// weights
+    pub swap_discount_bps: u16,
+    /// External DEX used by admin rebalances. Not set disables them.
+    pub dex_id: Option<AccountId>,
+    /// Maximum slippage of admin rebalances from the index price
+    pub max_slippage_bps: u16,
+}
+
+/// Message sent to the DEX with `ft_transfer_call`. The DEX swaps the
+/// received tokens and sends at least `min_amount_out` of `token_out` back
+/// with `ft_transfer_call` and `output_msg`.
+///
+/// Only DEXes which send the output inside the `ft_transfer_call` chain, i.e.
+/// before returning the unused amount, are supported. Output received after
+/// the rebalance is resolved is rejected.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DexSwapMsg {
+    pub token_out: AccountId,
+    pub min_amount_out: U128,
+    pub output_msg: String,
+}
+
+/// Admin rebalance waiting for the DEX swap
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingRebalance {
+    pub asset_in: AssetId,
+    pub asset_out: AssetId,
+    #[serde(with = "u128_dec_format")]
+    pub amount_in: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub min_amount_out: Balance,
+    /// Set once the DEX sends the output tokens
+    #[serde(with = "u128_dec_format")]
+    pub amount_out: Balance,
+    pub created_timestamp_ms: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetRebalanceView {
+    pub current_usd: U128,
+    pub target_usd: U128,
+    /// Value to add to the pool to reach the target
+    pub deficit_usd: U128,
+    /// Value to remove from the pool to reach the target
+    pub surplus_usd: U128,
+}
+
+impl Contract {
+    /// Current and target USD value of the asset in the pool
+    pub fn get_asset_rebalance_usd(
+        &self,
+        asset: &Asset,
+        total_usd: DollarBalance,
+    ) -> (DollarBalance, DollarBalance) {
+        let current_usd = asset.dollar_value_of(asset.pool_balance);
+        let target_usd = if self.total_weights == 0 {
+            0
+        } else {
+            ratio(total_usd, asset.token_weight, self.total_weights)
+        };
+        (current_usd, target_usd)
+    }
+
+    pub fn get_rebalance_info(&self) -> HashMap<String, AssetRebalanceView> {
+        let total_usd = self.get_total_value();
+        self.assets
+            .0
+            .iter()
+            .map(|(asset_id, asset)| {
+                let (current_usd, target_usd) = self.get_asset_rebalance_usd(asset, total_usd);
+                (
+                    asset_id.into_string(),
+                    AssetRebalanceView {
+                        current_usd: current_usd.into(),
+                        target_usd: target_usd.into(),
+                        deficit_usd: target_usd.saturating_sub(current_usd).into(),
+                        surplus_usd: current_usd.saturating_sub(target_usd).into(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Returns true if adding `amount_in` of `asset_in` and removing
+    /// `amount_out` of `asset_out` moves both assets towards their target
+    /// weights without overshooting them.
+    pub fn is_rebalancing_swap(
+        &self,
+        asset_in: &Asset,
+        asset_out: &Asset,
+        amount_in: Balance,
+        amount_out: Balance,
+    ) -> bool {
+        let total_usd = self.get_total_value();
+        if total_usd == 0 || self.total_weights == 0 {
+            return false;
+        }
+        let (in_usd, in_target_usd) = self.get_asset_rebalance_usd(asset_in, total_usd);
+        let (out_usd, out_target_usd) = self.get_asset_rebalance_usd(asset_out, total_usd);
+        in_usd + asset_in.dollar_value_of(amount_in) <= in_target_usd
+            && out_usd >= out_target_usd + asset_out.dollar_value_of(amount_out)
+    }
+
+    /// Swap fee discount for swaps which rebalance the pool
+    pub fn get_rebalance_discount_bps(
+        &self,
+        asset_in: &Asset,
+        asset_out: &Asset,
+        amount_in: Balance,
+        amount_out: Balance,
+    ) -> u16 {
+        let discount_bps = self.rebalance_parameters.swap_discount_bps;
+        if discount_bps > 0 && self.is_rebalancing_swap(asset_in, asset_out, amount_in, amount_out)
+        {
+            discount_bps
+        } else {
+            0
+        }
+    }
+
+    /// Value of `amount_in` in `asset_out` at index prices, minus the maximum
+    /// slippage of admin rebalances
+    fn get_min_rebalance_amount_out(
+        &self,
+        asset_in: &Asset,
+        asset_out: &Asset,
+        amount_in: Balance,
+    ) -> Balance {
+        let fair_amount_out = convert_assets(
+            amount_in,
+            asset_in.price,
+            asset_out.denomination(),
+            asset_out.price,
+            asset_in.denomination(),
+        );
+        ratio(
+            fair_amount_out,
+            BPS_DIVISOR - self.rebalance_parameters.max_slippage_bps as u128,
+            BPS_DIVISOR,
+        )
+    }
+
+    pub fn assert_no_pending_rebalance(&self) {
+        assert!(
+            self.pending_rebalance.is_none(),
+            "Pool rebalance in progress"
+        );
+    }
+
+    /// Take `amount_in` of a surplus asset out of the pool and send it to the
+    /// DEX to be swapped for a deficit asset. The minimum output can't be
+    /// lower than the index price value minus the maximum slippage.
+    pub fn rebalance_with_dex(
+        &mut self,
+        asset_in_id: &AssetId,
+        asset_out_id: &AssetId,
+        amount_in: Balance,
+        min_amount_out: Balance,
+    ) -> Promise {
+        let dex_id = self
+            .rebalance_parameters
+            .dex_id
+            .clone()
+            .unwrap_or_else(|| env::panic_str("Rebalance DEX is not set"));
+        self.assert_no_pending_rebalance();
+        assert!(amount_in > 0, "Amount should be positive");
+        let (token_in, token_out) = match (asset_in_id, asset_out_id) {
+            (AssetId::Ft(token_in), AssetId::Ft(token_out)) if token_in != token_out => {
+                (token_in.clone(), token_out.clone())
+            }
+            _ => env::panic_str("Can only rebalance between different fungible tokens"),
+        };
+        self.validate_asset_price(asset_in_id);
+        self.validate_asset_price(asset_out_id);
+
+        let mut asset_in = self.assets.unwrap(asset_in_id);
+        let asset_out = self.assets.unwrap(asset_out_id);
+        let fair_amount_out = convert_assets(
+            amount_in,
+            asset_in.price,
+            asset_out.denomination(),
+            asset_out.price,
+            asset_in.denomination(),
+        );
+        // The pool gives `asset_in` to the DEX and gets `asset_out` back
+        assert!(
+            self.is_rebalancing_swap(&asset_out, &asset_in, fair_amount_out, amount_in),
+            "Swap does not move the pool towards target weights"
+        );
+        assert!(
+            min_amount_out >= self.get_min_rebalance_amount_out(&asset_in, &asset_out, amount_in),
+            "Minimum output exceeds maximum slippage"
+        );
+        assert!(
+            amount_in <= asset_in.available_liquidity(),
+            "Not enough liquidity to rebalance"
+        );
+
+        asset_in.remove_liquidity(amount_in, &env::predecessor_account_id());
+        asset_in.check_available_liquidity();
+        self.set_asset(asset_in_id, asset_in);
+        self.pending_rebalance = Some(PendingRebalance {
+            asset_in: asset_in_id.clone(),
+            asset_out: asset_out_id.clone(),
+            amount_in,
+            min_amount_out,
+            amount_out: 0,
+            created_timestamp_ms: env::block_timestamp_ms(),
+        });
+
+        let msg = DexSwapMsg {
+            token_out,
+            min_amount_out: min_amount_out.into(),
+            output_msg: serde_json::to_string(&Action::RebalanceSwapOut).unwrap(),
+        };
+        ext_ft_core::ext(token_in)
+            .with_attached_deposit(ONE_YOCTO)
+            .with_static_gas(Gas::ONE_TERA * TGAS_FOR_REBALANCE_SWAP)
+            .ft_transfer_call(
+                dex_id,
+                amount_in.into(),
+                None,
+                serde_json::to_string(&msg).unwrap(),
+            )
+            .then(
+                VContract::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA * TGAS_FOR_REBALANCE_CALLBACK)
+                    .on_rebalance_transfer(),
+            )
+    }
+
+    /// Add the output of a DEX rebalance swap to the pool. Prices may have
+    /// moved while the swap was pending, so the output is checked against
+    /// the maximum slippage at the current index prices as well.
+    pub fn receive_rebalance_swap_out(
+        &mut self,
+        sender_id: &AccountId,
+        asset_id: &AssetId,
+        amount: Balance,
+    ) {
+        assert!(
+            self.rebalance_parameters.dex_id.as_ref() == Some(sender_id),
+            "Only the rebalance DEX can send rebalance output"
+        );
+        let mut pending = self
+            .pending_rebalance
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No pending rebalance"));
+        assert!(
+            pending.asset_out == *asset_id,
+            "Wrong rebalance output token"
+        );
+        assert!(pending.amount_out == 0, "Rebalance output already received");
+        let mut asset = self.assets.unwrap(asset_id);
+        let min_amount_out = self.get_min_rebalance_amount_out(
+            &self.assets.unwrap(&pending.asset_in),
+            &asset,
+            pending.amount_in,
+        );
+        assert!(
+            amount >= pending.min_amount_out.max(min_amount_out),
+            "Exceeded slippage tolerance"
+        );
+
+        asset.add_liquidity(amount, sender_id);
+        self.set_asset(asset_id, asset);
+        pending.amount_out = amount;
+        self.pending_rebalance = Some(pending);
+    }
+
+    /// Return unused input tokens to the pool and finish the rebalance.
+    /// `used_amount` is the amount of input tokens kept by the DEX.
+    pub fn resolve_rebalance(&mut self, used_amount: Balance) {
+        let pending = self
+            .pending_rebalance
+            .take()
+            .unwrap_or_else(|| env::panic_str("No pending rebalance"));
+        let refund = pending.amount_in.saturating_sub(used_amount);
+        if refund > 0 {
+            let mut asset = self.assets.unwrap(&pending.asset_in);
+            asset.add_liquidity(refund, &env::current_account_id());
+            self.set_asset(&pending.asset_in, asset);
+        }
+        if used_amount > 0 && pending.amount_out == 0 {
+            log!(
+                "Rebalance output not received for {} {}",
+                used_amount,
+                pending.asset_in.into_string()
+            );
+        }
+        log!(
+            "Rebalanced {} {} into {} {}",
+            pending.amount_in - refund,
+            pending.asset_in.into_string(),
+            pending.amount_out,
+            pending.asset_out.into_string()
+        );
+    }
+
+    /// Drop a rebalance whose callback never ran. The input tokens sent to
+    /// the DEX are written off, output already received stays in the pool.
+    pub fn clear_pending_rebalance(&mut self) {
+        let pending = self
+            .pending_rebalance
+            .take()
+            .unwrap_or_else(|| env::panic_str("No pending rebalance"));
+        let timeout_ms = Duration::from_secs(PENDING_REBALANCE_TIMEOUT_SEC).as_millis() as u64;
+        assert!(
+            env::block_timestamp_ms() >= pending.created_timestamp_ms + timeout_ms,
+            "Pending rebalance has not timed out"
+        );
+        log!(
+            "Cleared rebalance of {} {} into {} {}",
+            pending.amount_in,
+            pending.asset_in.into_string(),
+            pending.amount_out,
+            pending.asset_out.into_string()
+        );
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Per asset current and target value in the pool
+    pub fn get_rebalance_info(&self) -> HashMap<String, AssetRebalanceView> {
+        self.contract().get_rebalance_info()
+    }
+
+    pub fn get_pending_rebalance(&self) -> Option<PendingRebalance> {
+        self.contract().pending_rebalance.clone()
+    }
+
+    /// Swap a surplus asset for a deficit asset on the rebalance DEX. Only
+    /// callable by admins.
+    pub fn rebalance_with_dex(
+        &mut self,
+        asset_in: String,
+        asset_out: String,
+        amount_in: U128,
+        min_amount_out: U128,
+    ) -> Promise {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.rebalance_with_dex(
+            &asset_in.into(),
+            &asset_out.into(),
+            amount_in.0,
+            min_amount_out.0,
+        )
+    }
+
+    /// Clear a rebalance which wasn't resolved within
+    /// [PENDING_REBALANCE_TIMEOUT_SEC], so that LP tokens can be minted again.
+    /// Only callable by admins.
+    pub fn clear_pending_rebalance(&mut self) {
+        let contract = self.contract_mut();
+        contract.assert_admin();
+        contract.clear_pending_rebalance();
+    }
+
+    #[private]
+    pub fn on_rebalance_transfer(
+        &mut self,
+        #[callback_result] call_result: Result<U128, PromiseError>,
+    ) {
+        // If `ft_transfer_call` failed, the tokens never left the contract
+        let used_amount = call_result.map_or(0, |used| used.0);
+        self.contract_mut().resolve_rebalance(used_amount);
+    }
+}
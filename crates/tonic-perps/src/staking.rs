@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, IntoStorageKey};
+
+use crate::{
+    borsh, env, near_bindgen, ratio, AccountId, Asset, AssetId, Balance, BorshDeserialize,
+    BorshSerialize, Contract, DollarBalance, Serialize, TransferInfo, VContract, VContractExt,
+    BPS_DIVISOR, LP_TOKEN_DENOMINATION,
+};
+
+/// Precision of the reward per share accumulators
+const REWARD_PER_SHARE_PRECISION: u128 = LP_TOKEN_DENOMINATION;
+
+/// Minimum amount of staked LP tokens per account. Keeps the reward per share
+/// accumulators from overflowing. 0.001GIN
+const MIN_STAKE_AMOUNT: Balance = 1_000_000_000_000_000;
+
+const MS_PER_YEAR: u128 = 365 * 24 * 60 * 60 * 1000;
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct Staker {
+    pub staked: Balance,
+
+    /// Reward per share of each asset when the staker was last updated
+    pub reward_per_share_paid: HashMap<AssetId, u128>,
+
+    /// Rewards earned and not claimed yet, in native tokens
+    pub rewards: HashMap<AssetId, Balance>,
+}
+
+/// Staking fees of an asset paid out to stakers at a constant rate
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct RewardStream {
+    /// Fees left to stream, in native tokens
+    pub remaining: Balance,
+    pub last_update_ms: u64,
+    pub end_timestamp_ms: u64,
+}
+
+impl RewardStream {
+    /// Fees streamed since the last update
+    fn released(&self, now_ms: u64) -> Balance {
+        if now_ms >= self.end_timestamp_ms {
+            return self.remaining;
+        }
+        ratio(
+            self.remaining,
+            now_ms - self.last_update_ms,
+            self.end_timestamp_ms - self.last_update_ms,
+        )
+    }
+
+    /// Fees streamed per second, in native tokens
+    pub fn rate_per_sec(&self) -> Balance {
+        if self.end_timestamp_ms <= self.last_update_ms {
+            return 0;
+        }
+        ratio(
+            self.remaining,
+            Duration::from_secs(1).as_millis(),
+            self.end_timestamp_ms - self.last_update_ms,
+        )
+    }
+}
+
+/// LP tokens staked to earn a share of protocol fees. Staked tokens are held
+/// by the contract account.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingPool {
+    pub total_staked: Balance,
+
+    /// Accumulated rewards per staked LP token, scaled by [REWARD_PER_SHARE_PRECISION]
+    pub reward_per_share: HashMap<AssetId, u128>,
+
+    /// Rewards distributed to stakers and not claimed yet. These tokens are
+    /// not part of the asset balance anymore.
+    pub reward_balances: HashMap<AssetId, Balance>,
+
+    /// Staking fees of each asset not distributed yet
+    pub reward_streams: HashMap<AssetId, RewardStream>,
+
+    /// USD value of all distributed rewards, used to estimate APR
+    pub total_distributed_usd: DollarBalance,
+
+    /// Time of the first stake
+    pub start_timestamp_ms: u64,
+
+    pub stakers: LookupMap<AccountId, Staker>,
+}
+
+impl StakingPool {
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self {
+            total_staked: 0,
+            reward_per_share: HashMap::new(),
+            reward_balances: HashMap::new(),
+            reward_streams: HashMap::new(),
+            total_distributed_usd: 0,
+            start_timestamp_ms: 0,
+            stakers: LookupMap::new(prefix),
+        }
+    }
+
+    /// Move rewards earned since the last update to the staker's balance
+    fn update_staker(&self, staker: &mut Staker) {
+        for (asset_id, reward_per_share) in &self.reward_per_share {
+            let paid = staker
+                .reward_per_share_paid
+                .insert(asset_id.clone(), *reward_per_share)
+                .unwrap_or(0);
+            let earned = ratio(
+                staker.staked,
+                reward_per_share - paid,
+                REWARD_PER_SHARE_PRECISION,
+            );
+            if earned > 0 {
+                *staker.rewards.entry(asset_id.clone()).or_default() += earned;
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakerView {
+    pub staked: U128,
+    pub pending_rewards: HashMap<String, U128>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingPoolView {
+    pub total_staked: U128,
+    pub total_distributed_usd: U128,
+    /// Fees currently streamed to stakers per second, in native tokens
+    pub reward_rates: HashMap<String, U128>,
+    /// Annualized rewards relative to the value of staked LP tokens, in bps
+    pub apr_bps: U128,
+}
+
+impl Contract {
+    /// Advance the reward stream of an asset to now and return the fees
+    /// released to stakers. Staking fees collected since the last update
+    /// restart the stream over the staking reward duration.
+    fn update_reward_stream(&self, asset: &Asset, stream: &mut RewardStream) -> Balance {
+        let now_ms = env::block_timestamp_ms();
+        let mut released = stream.released(now_ms);
+        stream.remaining -= released;
+
+        let new_fees = asset.staking_fees - released - stream.remaining;
+        if new_fees > 0 {
+            stream.remaining += new_fees;
+            stream.end_timestamp_ms =
+                now_ms + Duration::from_secs(self.staking_reward_duration_sec).as_millis() as u64;
+        }
+        if stream.end_timestamp_ms <= now_ms {
+            released += stream.remaining;
+            stream.remaining = 0;
+        }
+        stream.last_update_ms = now_ms;
+        released
+    }
+
+    /// Distribute the fees streamed to stakers since the last update. While
+    /// nothing is staked the staking fees go to the treasury, so a late
+    /// staker can't capture them.
+    pub fn distribute_staking_fees(&mut self) {
+        for (asset_id, mut asset) in self.get_assets() {
+            if asset.staking_fees == 0 {
+                continue;
+            }
+            if self.staking.total_staked == 0 {
+                asset.release_staking_fees(asset.staking_fees, &env::current_account_id());
+                self.staking.reward_streams.remove(&asset_id);
+                self.set_asset(&asset_id, asset);
+                continue;
+            }
+
+            let mut stream = self
+                .staking
+                .reward_streams
+                .get(&asset_id)
+                .cloned()
+                .unwrap_or_default();
+            let amount = self.update_reward_stream(&asset, &mut stream);
+            self.staking.reward_streams.insert(asset_id.clone(), stream);
+            if amount == 0 {
+                continue;
+            }
+            asset.remove_staking_fees(amount, &env::current_account_id());
+
+            *self
+                .staking
+                .reward_per_share
+                .entry(asset_id.clone())
+                .or_default() += ratio(
+                amount,
+                REWARD_PER_SHARE_PRECISION,
+                self.staking.total_staked,
+            );
+            *self
+                .staking
+                .reward_balances
+                .entry(asset_id.clone())
+                .or_default() += amount;
+            if asset.price > 0 {
+                self.staking.total_distributed_usd += asset.to_min_usd_price(amount);
+            }
+            self.set_asset(&asset_id, asset);
+        }
+    }
+
+    pub fn stake_lp(&mut self, account_id: &AccountId, amount: Balance) {
+        assert!(amount > 0, "The amount should be a positive number");
+        self.assert_lp_tokens_unlocked(account_id, amount);
+        self.distribute_staking_fees();
+
+        let mut staker = self.staking.stakers.get(account_id).unwrap_or_default();
+        self.staking.update_staker(&mut staker);
+        staker.staked += amount;
+        assert!(
+            staker.staked >= MIN_STAKE_AMOUNT,
+            "Requires min 0.001GIN to stake"
+        );
+
+        self.lp_token.internal_transfer(
+            account_id,
+            &env::current_account_id(),
+            amount,
+            Some("stake".to_string()),
+        );
+        self.staking.total_staked += amount;
+        if self.staking.start_timestamp_ms == 0 {
+            self.staking.start_timestamp_ms = env::block_timestamp_ms();
+        }
+        self.staking.stakers.insert(account_id, &staker);
+    }
+
+    pub fn unstake_lp(&mut self, account_id: &AccountId, amount: Balance) {
+        assert!(amount > 0, "The amount should be a positive number");
+        self.distribute_staking_fees();
+
+        let mut staker = self
+            .staking
+            .stakers
+            .get(account_id)
+            .unwrap_or_else(|| env::panic_str("Account has no staked LP tokens"));
+        self.staking.update_staker(&mut staker);
+        assert!(staker.staked >= amount, "Not enough staked LP tokens");
+        staker.staked -= amount;
+        assert!(
+            staker.staked == 0 || staker.staked >= MIN_STAKE_AMOUNT,
+            "Requires min 0.001GIN to stay staked"
+        );
+
+        self.lp_token.internal_transfer(
+            &env::current_account_id(),
+            account_id,
+            amount,
+            Some("unstake".to_string()),
+        );
+        self.staking.total_staked -= amount;
+        self.staking.stakers.insert(account_id, &staker);
+    }
+
+    /// Claim staking rewards, optionally swapped to `reward_asset_id`. Returns
+    /// the transfers to make to the staker.
+    pub fn claim_staking_rewards(
+        &mut self,
+        account_id: &AccountId,
+        reward_asset_id: Option<AssetId>,
+        min_out: Option<Balance>,
+    ) -> Vec<TransferInfo> {
+        self.distribute_staking_fees();
+
+        let mut staker = self
+            .staking
+            .stakers
+            .get(account_id)
+            .unwrap_or_else(|| env::panic_str("Account has no staked LP tokens"));
+        self.staking.update_staker(&mut staker);
+
+        let mut amounts: HashMap<AssetId, Balance> = HashMap::new();
+        for (asset_id, amount) in staker.rewards.drain() {
+            if amount == 0 {
+                continue;
+            }
+            *self.staking.reward_balances.get_mut(&asset_id).unwrap() -= amount;
+
+            match &reward_asset_id {
+                Some(reward_asset_id) if *reward_asset_id != asset_id => {
+                    // Reward tokens are held by the contract, swap them
+                    // through the pool
+                    let amount_out = self.swap(&asset_id, reward_asset_id, amount, None);
+                    *amounts.entry(reward_asset_id.clone()).or_default() += amount_out;
+                }
+                _ => *amounts.entry(asset_id).or_default() += amount,
+            }
+        }
+        self.staking.stakers.insert(account_id, &staker);
+
+        if let (Some(reward_asset_id), Some(min_out)) = (&reward_asset_id, min_out) {
+            let amount_out = amounts.get(reward_asset_id).copied().unwrap_or(0);
+            assert!(amount_out >= min_out, "Exceeded slippage tolerance");
+        }
+
+        amounts
+            .into_iter()
+            .map(|(asset_id, amount)| TransferInfo::new(account_id, &asset_id, amount))
+            .collect()
+    }
+
+    /// Rewards of a staker, including fees which are not distributed yet
+    pub fn get_pending_staking_rewards(&self, account_id: &AccountId) -> HashMap<AssetId, Balance> {
+        let mut staker = match self.staking.stakers.get(account_id) {
+            Some(staker) => staker,
+            None => return HashMap::new(),
+        };
+        self.staking.update_staker(&mut staker);
+
+        if self.staking.total_staked > 0 {
+            for (asset_id, asset) in self.assets.0.iter() {
+                let mut stream = self
+                    .staking
+                    .reward_streams
+                    .get(asset_id)
+                    .cloned()
+                    .unwrap_or_default();
+                let released = self.update_reward_stream(asset, &mut stream);
+                let earned = ratio(staker.staked, released, self.staking.total_staked);
+                if earned > 0 {
+                    *staker.rewards.entry(asset_id.clone()).or_default() += earned;
+                }
+            }
+        }
+        staker.rewards
+    }
+
+    /// Estimated yearly rewards relative to the value of staked LP tokens, in bps
+    pub fn get_staking_apr_bps(&self) -> u128 {
+        let elapsed_ms = env::block_timestamp_ms().saturating_sub(self.staking.start_timestamp_ms);
+        if self.staking.total_staked == 0 || elapsed_ms == 0 {
+            return 0;
+        }
+        let staked_usd = ratio(
+            self.staking.total_staked,
+            self.lp_price(),
+            LP_TOKEN_DENOMINATION,
+        );
+        if staked_usd == 0 {
+            return 0;
+        }
+        ratio(
+            ratio(self.staking.total_distributed_usd, BPS_DIVISOR, staked_usd),
+            MS_PER_YEAR,
+            elapsed_ms,
+        )
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Stake LP tokens to earn a share of protocol fees
+    #[payable]
+    pub fn stake_lp(&mut self, amount: U128) {
+        assert_one_yocto();
+        let contract = self.contract_mut();
+        contract.assert_running();
+        contract.stake_lp(&env::predecessor_account_id(), amount.0);
+    }
+
+    #[payable]
+    pub fn unstake_lp(&mut self, amount: U128) {
+        assert_one_yocto();
+        let contract = self.contract_mut();
+        contract.assert_running();
+        contract.unstake_lp(&env::predecessor_account_id(), amount.0);
+    }
+
+    /// Claim staking rewards. Rewards are paid in the assets the fees were
+    /// collected in without any swap, unless `reward_asset_id` is set. Then
+    /// they are swapped to it and the output must be at least `min_out`.
+    pub fn claim_staking_rewards(
+        &mut self,
+        reward_asset_id: Option<String>,
+        min_out: Option<U128>,
+    ) {
+        let contract = self.contract_mut();
+        contract.assert_running();
+        let account_id = env::predecessor_account_id();
+        let transfers = contract.claim_staking_rewards(
+            &account_id,
+            reward_asset_id.map(AssetId::from),
+            min_out.map(|min_out| min_out.0),
+        );
+        for transfer_info in transfers {
+            contract.internal_send(transfer_info, "claim_staking_rewards");
+        }
+    }
+
+    pub fn get_staker(&self, account_id: AccountId) -> StakerView {
+        let contract = self.contract();
+        StakerView {
+            staked: contract
+                .staking
+                .stakers
+                .get(&account_id)
+                .map_or(0, |staker| staker.staked)
+                .into(),
+            pending_rewards: contract
+                .get_pending_staking_rewards(&account_id)
+                .into_iter()
+                .map(|(asset_id, amount)| (asset_id.into_string(), amount.into()))
+                .collect(),
+        }
+    }
+
+    pub fn get_staking_pool(&self) -> StakingPoolView {
+        let contract = self.contract();
+        StakingPoolView {
+            total_staked: contract.staking.total_staked.into(),
+            total_distributed_usd: contract.staking.total_distributed_usd.into(),
+            reward_rates: contract
+                .staking
+                .reward_streams
+                .iter()
+                .map(|(asset_id, stream)| (asset_id.into_string(), stream.rate_per_sec().into()))
+                .collect(),
+            apr_bps: contract.get_staking_apr_bps().into(),
+        }
+    }
+}
@@ -3,8 +3,8 @@ use near_sdk::{json_types::U128, PromiseOrValue};
 use tonic_perps_sdk::prelude::{emit_event, EventType, TokenDepositWithdrawEvent};
 
 use crate::{
-    env, near_bindgen, AccountId, Action, AddLimitOrderParams, AssetId, IncreasePositionRequest,
-    OrderType, VContract, VContractExt,
+    env, near_bindgen, split_execution_fee, AccountId, Action, AddLimitOrderParams, AssetId,
+    IncreasePositionRequest, OrderType, VContract, VContractExt,
 };
 
 #[near_bindgen]
@@ -17,8 +17,12 @@ impl FungibleTokenReceiver for VContract {
     ) -> PromiseOrValue<U128> {
         self.contract_mut().assert_running();
         let token_id = env::predecessor_account_id();
-        let asset_id = AssetId::Ft(token_id);
+        let contract = self.contract();
+        let is_wnear = contract.is_wnear(&AssetId::Ft(token_id.clone()));
+        let asset_id = contract.pool_asset_id(&AssetId::Ft(token_id));
         let action = serde_json::from_str::<Action>(&msg).expect("invalid message");
+        // Returned to the sender by the token contract
+        let mut unused_amount = 0;
 
         match action {
             Action::Swap(params) => {
@@ -26,13 +30,28 @@ impl FungibleTokenReceiver for VContract {
                     self.set_user_referral_code(referrer_id);
                 }
                 let contract = self.contract_mut();
-                contract.swap_and_send(
-                    &asset_id,
-                    &params.output_token_id.into(),
-                    amount.0,
-                    params.min_out.map(|x| x.0),
-                    &sender_id,
-                );
+                let output_id = params.output_token_id.into();
+                match params.amount_out {
+                    Some(amount_out) => {
+                        let amount_in = contract.swap_exact_out_and_send(
+                            &asset_id,
+                            &output_id,
+                            amount_out.0,
+                            amount.0,
+                            &sender_id,
+                        );
+                        unused_amount = amount.0 - amount_in;
+                    }
+                    None => {
+                        contract.swap_and_send(
+                            &asset_id,
+                            &output_id,
+                            amount.0,
+                            params.min_out.map(|x| x.0),
+                            &sender_id,
+                        );
+                    }
+                }
             }
             Action::MintLp(params) => {
                 if let Some(referrer_id) = params.referrer_id {
@@ -46,6 +65,21 @@ impl FungibleTokenReceiver for VContract {
                     params.min_out.map(|a| a.0),
                 );
             }
+            Action::ZapInLp(params) => {
+                if let Some(referrer_id) = params.referrer_id {
+                    self.set_user_referral_code(referrer_id);
+                }
+                let contract = self.contract_mut();
+                contract.zap_in_lp(&sender_id, &asset_id, amount.0, params.min_out.map(|a| a.0));
+            }
+            Action::RebalanceSwapOut => {
+                let contract = self.contract_mut();
+                contract.receive_rebalance_swap_out(&sender_id, &asset_id, amount.0);
+            }
+            Action::AddToLpBasket => {
+                let contract = self.contract_mut();
+                contract.add_to_lp_basket(&sender_id, &asset_id, amount.0);
+            }
             Action::IncreasePosition(params) => {
                 let IncreasePositionRequest {
                     is_long,
@@ -71,28 +105,63 @@ impl FungibleTokenReceiver for VContract {
                 let contract = self.contract_mut();
                 contract.assert_limit_order_state(matches!(params.order_type, OrderType::Increase));
 
-                assert!(
-                    matches!(params.order_type, OrderType::Increase)
-                        && params.collateral_delta.is_none(),
-                    "Collateral field is only applicable to sell orders"
-                );
+                // Tokens attached to sell orders pay their execution fee, so
+                // they must be in the collateral of the position.
+                match params.order_type {
+                    OrderType::Increase => assert!(
+                        params.collateral_delta.is_none(),
+                        "Collateral field is only applicable to sell orders"
+                    ),
+                    OrderType::Decrease => assert!(
+                        params.collateral_id.as_ref() == Some(&asset_id.into_string()),
+                        "The execution fee must be attached in the collateral of the order"
+                    ),
+                    OrderType::Swap => {
+                        env::panic_str("Swap orders are placed with PlaceSwapLimitOrder")
+                    }
+                }
+                let (attached_collateral, execution_fee) =
+                    split_execution_fee(params.order_type, amount.0, params.execution_fee);
                 contract.add_limit_order(AddLimitOrderParams {
                     owner: sender_id.clone(),
                     collateral_id: asset_id,
                     underlying_id: AssetId::from(params.underlying_id),
-                    collateral_delta_usd: 0,
-                    attached_collateral_native: amount.0,
+                    collateral_delta_usd: params.collateral_delta.map_or(0, |c| c.0),
+                    attached_collateral_native: attached_collateral,
                     size: params.size_delta.0,
                     price: params.price.0,
                     is_long: params.is_long,
                     order_type: params.order_type,
                     expiry: params.expiry.map(|e| e.0),
+                    output_id: None,
+                    reduce_only: params.reduce_only.unwrap_or(false),
+                    close_position: params.close_position.unwrap_or(false),
+                    execution_fee,
                 });
             }
+            Action::PlaceSwapLimitOrder(params) => {
+                let contract = self.contract_mut();
+                contract.add_swap_limit_order(&sender_id, &asset_id, amount.0, params);
+            }
+            Action::UpdateLimitOrder(params) => {
+                let contract = self.contract_mut();
+                let (_, transfer_info) =
+                    contract.update_limit_order(&sender_id, params, &asset_id, amount.0);
+                contract.internal_send(transfer_info, "update_limit_order");
+            }
+            Action::PlaceTwapOrder(params) => {
+                let contract = self.contract_mut();
+                contract.add_twap_order(&sender_id, &asset_id, amount.0, params);
+            }
         };
 
+        // Wrapped NEAR is kept as NEAR. Unused tokens are refunded as wNEAR.
+        if is_wnear && amount.0 > unused_amount {
+            self.contract().unwrap_near(amount.0 - unused_amount);
+        }
+
         emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
-            amount_native: amount,
+            amount_native: (amount.0 - unused_amount).into(),
             deposit: true,
             method: "ft_on_transfer".to_string(),
             receiver_id: env::current_account_id(),
@@ -100,6 +169,6 @@ impl FungibleTokenReceiver for VContract {
             asset_id: env::predecessor_account_id().to_string(),
         }));
 
-        PromiseOrValue::Value(U128(0))
+        PromiseOrValue::Value(U128(unused_amount))
     }
 }
@@ -115,6 +115,10 @@ impl TokenTransferHistory {
         current_timestamp_ms.saturating_sub(self.sliding_window_duration_ms)
     }
 
+    pub fn sliding_window_duration_ms(&self) -> u64 {
+        self.sliding_window_duration_ms
+    }
+
     pub fn update_sliding_window_duration(&mut self, sliding_window_duration: u64) {
         self.sliding_window_duration_ms = sliding_window_duration;
     }
@@ -4,8 +4,8 @@ use tonic_perps_sdk::prelude::{FeeType, TokenDepositWithdrawEvent};
 const GAS_SURPLUS: u64 = 7;
 
 use crate::{
-    convert_assets, emit_event, AssetId, Contract, EventType, SwapEvent, SwapState, TransferInfo,
-    VContract, VContractExt, TGAS_FOR_FT_TRANSFER,
+    apply_price_impact, convert_assets, emit_event, ratio, AssetId, Contract, EventType, SwapEvent,
+    SwapState, TransferInfo, VContract, VContractExt, BPS_DIVISOR, TGAS_FOR_FT_TRANSFER,
 };
 
 impl Contract {
@@ -45,6 +45,10 @@ impl Contract {
             );
         }
 
+        asset_in.assert_not_close_only();
+        asset_out.assert_not_close_only();
+        asset_in.assert_pegged();
+
         let amount_out = {
             convert_assets(
                 amount_in,
@@ -54,6 +58,9 @@ impl Contract {
                 asset_in.denomination(),
             )
         };
+        let impact_bps =
+            self.get_swap_price_impact_bps(&asset_in, &asset_out, amount_in, amount_out);
+        let amount_out = apply_price_impact(amount_out, impact_bps, false);
 
         if let Some(min_amount_out) = min_amount_out {
             assert!(amount_out >= min_amount_out, "Exceeded slippage tolerance");
@@ -74,7 +81,7 @@ impl Contract {
         self.update_cumulative_funding_rate(&mut asset_in);
 
         asset_out.remove_liquidity(amount_out, &account_id);
-        asset_out.add_fees(fees, FeeType::Swap, &account_id);
+        self.collect_fees(&mut asset_out, fees, FeeType::Swap, &account_id);
         asset_out.check_available_liquidity();
         self.update_cumulative_funding_rate(&mut asset_out);
 
@@ -112,21 +119,164 @@ impl Contract {
         min_amount_out: Option<Balance>,
         receiver_id: &AccountId,
     ) -> PromiseOrValue<()> {
-        let amount_out = self.swap(token_in, token_out, amount_in, min_amount_out);
+        // Wrapped NEAR output is swapped from the NEAR pool
+        let amount_out = self.swap(
+            token_in,
+            &self.pool_asset_id(token_out),
+            amount_in,
+            min_amount_out,
+        );
         let transfer_info = TransferInfo::new(receiver_id, token_out, amount_out);
 
         self.internal_send(transfer_info, "swap_and_send")
     }
+
+    /// Return the amount of `token_out` a swap of `amount_in` would give
+    /// after fees, or `None` if the swap is not possible now.
+    pub fn get_swap_amount_out(
+        &self,
+        token_in: &AssetId,
+        token_out: &AssetId,
+        amount_in: Balance,
+    ) -> Option<Balance> {
+        let asset_in = self.assets.get(token_in)?;
+        let asset_out = self.assets.get(token_out)?;
+        if !self.swap_enabled
+            || token_in == token_out
+            || !self.is_asset_price_valid(asset_in)
+            || !self.is_asset_price_valid(asset_out)
+            || !(asset_in.state.swap.check(SwapState::Enabled)
+                || asset_in.state.swap.check(SwapState::InOnly))
+            || !(asset_out.state.swap.check(SwapState::Enabled)
+                || asset_out.state.swap.check(SwapState::OutOnly))
+            || asset_in.is_close_only()
+            || asset_out.is_close_only()
+            || asset_in.is_depegged()
+        {
+            return None;
+        }
+
+        let amount_out = convert_assets(
+            amount_in,
+            asset_in.min_price(),
+            asset_out.denomination(),
+            asset_out.max_price(),
+            asset_in.denomination(),
+        );
+        let impact_bps = self.get_swap_price_impact_bps(asset_in, asset_out, amount_in, amount_out);
+        let amount_out = apply_price_impact(amount_out, impact_bps, false);
+        if amount_out > asset_out.available_liquidity() {
+            return None;
+        }
+        let swap_fee_bps = self.get_swap_fee_bps(token_in, token_out, amount_in, amount_out);
+        Some(self.withhold_fees(amount_out, swap_fee_bps).0)
+    }
+
+    /// Return the amount of `token_in` to swap to get at least `amount_out`
+    /// of `token_out` after fees.
+    pub fn get_swap_amount_in(
+        &self,
+        token_in: &AssetId,
+        token_out: &AssetId,
+        amount_out: Balance,
+    ) -> Balance {
+        let asset_in = self.assets.unwrap(token_in);
+        let asset_out = self.assets.unwrap(token_out);
+        // Rounded up so that the swap gives at least the requested amount
+        let amount_in_for = |gross_amount_out: Balance| {
+            convert_assets(
+                gross_amount_out,
+                asset_out.max_price(),
+                asset_in.denomination(),
+                asset_in.min_price(),
+                asset_out.denomination(),
+            ) + 1
+        };
+        let gross_amount_out_for =
+            |fee_bps: u128| ratio(amount_out, BPS_DIVISOR, BPS_DIVISOR - fee_bps) + 1;
+        // Fees and adverse price impact taken from the output. Favorable
+        // impact is ignored, so the output may be a bit higher. Dynamic fees
+        // can exceed the base fee, so the cost is capped below 100%.
+        let cost_bps = |amount_in: Balance, gross_amount_out: Balance| {
+            let fee_bps = self.get_swap_fee_bps(token_in, token_out, amount_in, gross_amount_out);
+            let impact_bps =
+                self.get_swap_price_impact_bps(&asset_in, &asset_out, amount_in, gross_amount_out);
+            (u128::from(fee_bps) + impact_bps.max(0) as u128).min(BPS_DIVISOR - 1)
+        };
+
+        // Costs depend on the swap amounts, estimate them from the net amounts
+        // first and adjust once if they're higher for the gross amounts.
+        let fee_bps = cost_bps(amount_in_for(amount_out), amount_out);
+        let gross_amount_out = gross_amount_out_for(fee_bps);
+        let amount_in = amount_in_for(gross_amount_out);
+        let next_fee_bps = cost_bps(amount_in, gross_amount_out);
+        if next_fee_bps > fee_bps {
+            amount_in_for(gross_amount_out_for(next_fee_bps))
+        } else {
+            amount_in
+        }
+    }
+
+    /// Swap the amount of `token_in` needed to get `amount_out` of
+    /// `token_out`. Return the amounts of input token used and of output
+    /// token. The output may be slightly higher than `amount_out` due to
+    /// rounding.
+    ///
+    /// It's the caller's responsibility to send the output token and the
+    /// unused input token back to user.
+    ///
+    /// Panics if more than `max_amount_in` is needed or if the output after
+    /// fees is below `amount_out`.
+    #[must_use]
+    pub fn swap_exact_out(
+        &mut self,
+        token_in: &AssetId,
+        token_out: &AssetId,
+        amount_out: Balance,
+        max_amount_in: Balance,
+    ) -> (Balance, Balance) {
+        assert!(amount_out > 0, "Amount out should be positive");
+        let amount_in = self.get_swap_amount_in(token_in, token_out, amount_out);
+        assert!(amount_in <= max_amount_in, "Exceeded slippage tolerance");
+        let net_amount_out = self.swap(token_in, token_out, amount_in, Some(amount_out));
+        // The amount in is estimated, fees at the actual amounts may be higher
+        assert!(net_amount_out >= amount_out, "Exceeded slippage tolerance");
+        (amount_in, net_amount_out)
+    }
+
+    /// Perform an exact output swap and send the output token to
+    /// `receiver_id`. Return the amount of input token used.
+    pub fn swap_exact_out_and_send(
+        &mut self,
+        token_in: &AssetId,
+        token_out: &AssetId,
+        amount_out: Balance,
+        max_amount_in: Balance,
+        receiver_id: &AccountId,
+    ) -> Balance {
+        let (amount_in, amount_out) = self.swap_exact_out(
+            token_in,
+            &self.pool_asset_id(token_out),
+            amount_out,
+            max_amount_in,
+        );
+        let transfer_info = TransferInfo::new(receiver_id, token_out, amount_out);
+        self.internal_send(transfer_info, "swap_exact_out_and_send");
+        amount_in
+    }
 }
 
 #[near_bindgen]
 impl VContract {
+    /// Swap attached NEAR. If `amount_out` is set, swap only the NEAR needed
+    /// to get `amount_out` and refund the rest; `min_out` is ignored then.
     #[payable]
     pub fn swap_near(
         &mut self,
         output_token_id: String,
         min_out: Option<U128>,
         referrer_id: Option<String>,
+        amount_out: Option<U128>,
     ) {
         self.contract_mut().assert_running();
         if let Some(referrer_id) = referrer_id {
@@ -135,16 +285,33 @@ impl VContract {
         let contract = self.contract_mut();
         let sender_id = env::predecessor_account_id();
         let amount = env::attached_deposit();
-        contract.swap_and_send(
-            &AssetId::NEAR,
-            &output_token_id.into(),
-            amount,
-            min_out.map(Into::into),
-            &sender_id,
-        );
+        let amount_in = match amount_out {
+            Some(amount_out) => {
+                let amount_in = contract.swap_exact_out_and_send(
+                    &AssetId::NEAR,
+                    &output_token_id.into(),
+                    amount_out.0,
+                    amount,
+                    &sender_id,
+                );
+                let refund = TransferInfo::new(&sender_id, &AssetId::NEAR, amount - amount_in);
+                contract.internal_send(refund, "swap_near");
+                amount_in
+            }
+            None => {
+                contract.swap_and_send(
+                    &AssetId::NEAR,
+                    &output_token_id.into(),
+                    amount,
+                    min_out.map(Into::into),
+                    &sender_id,
+                );
+                amount
+            }
+        };
 
         emit_event(EventType::TokenDepositWithdraw(TokenDepositWithdrawEvent {
-            amount_native: env::attached_deposit().into(),
+            amount_native: amount_in.into(),
             deposit: true,
             method: "swap_near".to_string(),
             receiver_id: env::current_account_id(),
@@ -153,11 +320,23 @@ impl VContract {
         }));
     }
 
+    /// Amount of `token_in` needed to get `amount_out` of `token_out`
+    pub fn get_swap_amount_in(
+        &self,
+        token_in: String,
+        token_out: String,
+        amount_out: U128,
+    ) -> U128 {
+        self.contract()
+            .get_swap_amount_in(&token_in.into(), &token_out.into(), amount_out.0)
+            .into()
+    }
+
     #[payable]
     pub fn withdraw_fees(&mut self, asset_ids: Option<Vec<String>>) -> U128 {
-        let receiver_id = env::predecessor_account_id();
         let contract = self.contract_mut();
         contract.assert_admin();
+        let receiver_id = contract.treasury_id.clone();
 
         let asset_ids: Vec<AssetId> = if let Some(asset_ids) = asset_ids {
             asset_ids.iter().map(|asset| asset.clone().into()).collect()
@@ -169,7 +348,7 @@ impl VContract {
         for asset_id in asset_ids {
             let asset_id = AssetId::from(asset_id);
             let mut asset = contract.assets.unwrap(&asset_id);
-            let fee_native = asset.accumulated_fees;
+            let fee_native = asset.withdrawable_fees();
 
             contract.update_cumulative_funding_rate(&mut asset);
 
@@ -1,6 +1,379 @@
-use near_sdk::{Gas, Promise};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::{env, near_bindgen, VContract, VContractExt};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::{log, Gas, Promise};
+
+use crate::{
+    borsh, env, near_bindgen, AccountId, AdminRole, Asset, AssetId, AssetPositionLimits,
+    AssetState, AssetsMap, Balance, BorshDeserialize, BorshSerialize, Contract, ContractState,
+    DollarBalance, FeeParameters, FeeSplitParameters, FungibleTokenFreeStorage, LimitOrder,
+    LimitOrderId, LimitOrderLimits, LimitOrders, LimitOrdersState, OpenInterestLimits, OrderType,
+    OutflowLimits, Position, PositionId, PriceImpactParameters, RebalanceParameters, ReferrerTier,
+    StakingPool, StoragePrefix, SwitchboardAddress, ThresholdType, TokenTransferHistory, VContract,
+    VContractExt, WithdrawalQueue, BPS_DIVISOR, DEFAULT_DEPEG_BAND_BPS,
+};
+
+/// Layout of [Contract] in [VContract::V1]. [VContract::V2] is the layout of
+/// the whole release, the layouts in between were never deployed. State added
+/// since V1 starts empty in V2, only the fields below are converted.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    owner_id: AccountId,
+    state: ContractState,
+    liquidators: UnorderedSet<AccountId>,
+    price_oracles: UnorderedSet<AccountId>,
+    admins: UnorderedMap<AccountId, AdminRole>,
+    goblins: UnorderedSet<AccountId>,
+    assets: AssetsMapV1,
+    lp_token: FungibleTokenV1,
+    position_ids_map: UnorderedMap<AccountId, HashSet<PositionId>>,
+    positions: UnorderedMap<PositionId, Position>,
+    limit_order_ids_map: UnorderedMap<AccountId, HashMap<LimitOrderId, AssetId>>,
+    limit_orders: UnorderedMap<AssetId, LimitOrdersV1>,
+    limit_order_sequence: u64,
+    referral_code_owners: UnorderedMap<String, (AccountId, ReferrerTier)>,
+    user_referral_code: UnorderedMap<AccountId, String>,
+    total_weights: u32,
+    min_leverage: u16,
+    max_leverage: u16,
+    liquidation_reward_usd: u128,
+    funding_interval_seconds: u32,
+    base_funding_rate: u32,
+    swap_enabled: bool,
+    leverage_enabled: bool,
+    limit_orders_state: LimitOrdersState,
+    fee_parameters: FeeParameters,
+    manager_mode: bool,
+    private_liquidation_only: bool,
+    min_profit_time_seconds: u64,
+    dynamic_swap_fees: bool,
+    dynamic_position_fees: bool,
+    default_stable_coin: Option<AssetId>,
+    max_staleness_duration_sec: u64,
+    max_limit_order_life_sec: u64,
+}
+
+/// Layout of [AssetsMap] in [VContract::V1]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AssetsMapV1(HashMap<AssetId, AssetV1>);
+
+/// Layout of [Asset] in [VContract::V1]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AssetV1 {
+    asset_id: AssetId,
+    decimals: u8,
+    stable: bool,
+    token_weight: u32,
+    min_profit_bps: Balance,
+    max_pool_amount: Balance,
+    shortable: bool,
+    balance: Balance,
+    pool_balance: Balance,
+    reserved_amount: Balance,
+    buffer_amount: Balance,
+    state: AssetState,
+    global_short_size: DollarBalance,
+    global_short_average_price: DollarBalance,
+    global_long_size: DollarBalance,
+    global_long_average_price: DollarBalance,
+    accumulated_fees: Balance,
+    price: DollarBalance,
+    spread_bps: u16,
+    last_funding_time: u64,
+    base_funding_rate: u64,
+    cumulative_funding_rate: u128,
+    guaranteed_usd: DollarBalance,
+    switchboard_aggregator_address: Option<SwitchboardAddress>,
+    max_price_change_bps: Option<u128>,
+    last_change_timestamp_ms: u64,
+    open_interest_limits: OpenInterestLimits,
+    position_limits: AssetPositionLimits,
+    token_transfer_history: TokenTransferHistory,
+    withdrawal_limit_bps: u128,
+}
+
+/// Layout of [FungibleTokenFreeStorage] in [VContract::V1]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FungibleTokenV1 {
+    accounts: LookupMap<AccountId, Balance>,
+    total_supply: Balance,
+}
+
+/// Layout of [LimitOrders] in [VContract::V1]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LimitOrdersV1(BTreeMap<LimitOrderId, LimitOrderV1>);
+
+/// Layout of [LimitOrder] in [VContract::V1]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LimitOrderV1 {
+    owner: AccountId,
+    collateral_delta: DollarBalance,
+    attached_collateral: Balance,
+    size_delta: DollarBalance,
+    collateral_id: AssetId,
+    underlying_id: AssetId,
+    price: DollarBalance,
+    is_long: bool,
+    expiry: u64,
+    order_type: OrderType,
+    threshold: ThresholdType,
+}
+
+impl From<AssetV1> for Asset {
+    fn from(asset: AssetV1) -> Self {
+        Self {
+            asset_id: asset.asset_id,
+            decimals: asset.decimals,
+            stable: asset.stable,
+            token_weight: asset.token_weight,
+            min_profit_bps: asset.min_profit_bps,
+            max_pool_amount: asset.max_pool_amount,
+            shortable: asset.shortable,
+            balance: asset.balance,
+            pool_balance: asset.pool_balance,
+            reserved_amount: asset.reserved_amount,
+            buffer_amount: asset.buffer_amount,
+            state: asset.state,
+            global_short_size: asset.global_short_size,
+            global_short_average_price: asset.global_short_average_price,
+            global_long_size: asset.global_long_size,
+            global_long_average_price: asset.global_long_average_price,
+            accumulated_fees: asset.accumulated_fees,
+            price: asset.price,
+            spread_bps: asset.spread_bps,
+            last_funding_time: asset.last_funding_time,
+            base_funding_rate: asset.base_funding_rate,
+            cumulative_funding_rate: asset.cumulative_funding_rate,
+            guaranteed_usd: asset.guaranteed_usd,
+            switchboard_aggregator_address: asset.switchboard_aggregator_address,
+            max_price_change_bps: asset.max_price_change_bps,
+            last_change_timestamp_ms: asset.last_change_timestamp_ms,
+            open_interest_limits: asset.open_interest_limits,
+            position_limits: asset.position_limits,
+            token_transfer_history: asset.token_transfer_history,
+            withdrawal_limit_bps: asset.withdrawal_limit_bps,
+            max_staleness_duration_sec: None,
+            depeg_band_bps: DEFAULT_DEPEG_BAND_BPS,
+            last_switchboard_query_ms: 0,
+            switchboard_failures: 0,
+            price_override: None,
+            // Start the mark price at the last index price
+            mark_price: asset.price,
+            mark_price_smoothing_bps: BPS_DIVISOR as u16,
+            staking_fees: 0,
+            // Seeded from the open positions when migrating the contract
+            collateral_position_size: 0,
+            collateral_entry_funding: 0,
+        }
+    }
+}
+
+impl From<LimitOrderV1> for LimitOrder {
+    fn from(limit_order: LimitOrderV1) -> Self {
+        Self {
+            owner: limit_order.owner,
+            collateral_delta: limit_order.collateral_delta,
+            attached_collateral: limit_order.attached_collateral,
+            size_delta: limit_order.size_delta,
+            collateral_id: limit_order.collateral_id,
+            underlying_id: limit_order.underlying_id,
+            price: limit_order.price,
+            is_long: limit_order.is_long,
+            expiry: limit_order.expiry,
+            order_type: limit_order.order_type,
+            threshold: limit_order.threshold,
+            output_id: None,
+            reduce_only: false,
+            close_position: false,
+            execution_fee: 0,
+            // Orders placed before order storage was charged are never
+            // credited storage
+            storage_usage: 0,
+        }
+    }
+}
+
+impl From<LimitOrdersV1> for LimitOrders {
+    fn from(limit_orders: LimitOrdersV1) -> Self {
+        let mut result = LimitOrders::new();
+        for (id, limit_order) in limit_orders.0 {
+            result.insert(id, limit_order.into());
+        }
+        result
+    }
+}
+
+impl From<FungibleTokenV1> for FungibleTokenFreeStorage {
+    fn from(token: FungibleTokenV1) -> Self {
+        let mut token = Self {
+            accounts: token.accounts,
+            total_supply: token.total_supply,
+            storage_deposits: LookupMap::new(StoragePrefix::LpTokenStorage),
+            account_storage_usage: 0,
+            free_storage: true,
+        };
+        token.measure_account_storage_usage();
+        token
+    }
+}
+
+/// Part of [VContract::V1] converted in batches by
+/// [VContract::migrate_batch], since its size is unbounded. The contract stays
+/// paused until it is done.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingMigration {
+    /// Order books in the V1 layout, removed as they are converted
+    limit_orders: UnorderedMap<AssetId, LimitOrdersV1>,
+    /// Number of positions tracked by their collateral so far
+    positions_migrated: u64,
+    /// State of the contract to restore once the migration is done
+    state: ContractState,
+}
+
+impl From<ContractV1> for Contract {
+    fn from(contract: ContractV1) -> Self {
+        let assets = AssetsMap(
+            contract
+                .assets
+                .0
+                .into_iter()
+                .map(|(asset_id, asset)| (asset_id, asset.into()))
+                .collect(),
+        );
+        Self {
+            owner_id: contract.owner_id.clone(),
+            state: ContractState::Paused,
+            liquidators: contract.liquidators,
+            price_oracles: contract.price_oracles,
+            admins: contract.admins,
+            goblins: contract.goblins,
+
+            assets,
+            total_weights: contract.total_weights,
+            fee_parameters: contract.fee_parameters,
+            fee_split_parameters: FeeSplitParameters::default(),
+            lp_token: contract.lp_token.into(),
+            lp_token_locks: LookupMap::new(StoragePrefix::LpTokenLocks),
+            staking: StakingPool::new(StoragePrefix::Stakers),
+            position_ids_map: contract.position_ids_map,
+            positions: contract.positions,
+
+            lp_cooldown_sec: 0,
+            lp_baskets: LookupMap::new(StoragePrefix::LpBaskets),
+            staking_reward_duration_sec: 60 * 60 * 24 * 7,
+            outflow_limits: OutflowLimits::default(),
+            outflow_history: TokenTransferHistory::default(),
+            account_outflow_history: LookupMap::new(StoragePrefix::AccountOutflows),
+            withdrawal_queue: WithdrawalQueue::new(StoragePrefix::WithdrawalQueue),
+            rebalance_parameters: RebalanceParameters::default(),
+            pending_rebalance: None,
+            price_impact_parameters: PriceImpactParameters::default(),
+            wnear_id: None,
+            pending_wnear_unwrap: 0,
+
+            limit_order_ids_map: contract.limit_order_ids_map,
+            limit_orders: UnorderedMap::new(StoragePrefix::LimitOrdersV2),
+            limit_order_sequence: contract.limit_order_sequence,
+            limit_order_limits: LimitOrderLimits {
+                max_orders_per_account: 100,
+                max_orders_per_asset: 20,
+            },
+            order_storage_balances: LookupMap::new(StoragePrefix::OrderStorageBalances),
+
+            twap_orders: UnorderedMap::new(StoragePrefix::TwapOrders),
+            twap_order_sequence: 0,
+
+            referral_code_owners: contract.referral_code_owners,
+            user_referral_code: contract.user_referral_code,
+
+            min_leverage: contract.min_leverage,
+            max_leverage: contract.max_leverage,
+            liquidation_reward_usd: contract.liquidation_reward_usd,
+            funding_interval_seconds: contract.funding_interval_seconds,
+            base_funding_rate: contract.base_funding_rate,
+            swap_enabled: contract.swap_enabled,
+            leverage_enabled: contract.leverage_enabled,
+            limit_orders_state: contract.limit_orders_state,
+            permissionless_limit_order_execution: false,
+            treasury_id: contract.owner_id,
+            manager_mode: contract.manager_mode,
+            private_liquidation_only: contract.private_liquidation_only,
+            min_profit_time_seconds: contract.min_profit_time_seconds,
+            dynamic_swap_fees: contract.dynamic_swap_fees,
+            dynamic_position_fees: contract.dynamic_position_fees,
+            default_stable_coin: contract.default_stable_coin,
+            max_staleness_duration_sec: contract.max_staleness_duration_sec,
+            max_limit_order_life_sec: contract.max_limit_order_life_sec,
+            switchboard_refresh_interval_sec: 30,
+            price_override_delay_sec: 60 * 60,
+
+            keeper_reward_parameters: Default::default(),
+            keeper_rewards: UnorderedMap::new(StoragePrefix::KeeperRewards),
+            keeper_reward_epoch: 0,
+            keeper_rewards_epoch_paid_usd: 0,
+
+            pending_migration: Some(PendingMigration {
+                limit_orders: contract.limit_orders,
+                positions_migrated: 0,
+                state: contract.state,
+            }),
+        }
+    }
+}
+
+impl Contract {
+    /// Convert up to `limit` positions or order books of the pending
+    /// migration. Positions whose collateral isn't listed anymore are
+    /// skipped. Restores the contract state and returns true once the
+    /// migration is done.
+    pub fn migrate_batch(&mut self, limit: u64) -> bool {
+        let mut migration = self
+            .pending_migration
+            .take()
+            .unwrap_or_else(|| env::panic_str("No contract migration in progress"));
+        let mut remaining = limit;
+
+        // Track the open positions by collateral
+        let positions = self.positions.values_as_vector();
+        while remaining > 0 && migration.positions_migrated < positions.len() {
+            let position = positions.get(migration.positions_migrated).unwrap();
+            let collateral_id = AssetId::from(position.collateral_id.clone());
+            match self.assets.0.get_mut(&collateral_id) {
+                Some(collateral) => {
+                    collateral.add_collateral_position(position.size, position.entry_funding_rate)
+                }
+                None => log!(
+                    "Skipping position {}, collateral {} is not listed",
+                    migration.positions_migrated,
+                    collateral_id.into_string()
+                ),
+            }
+            migration.positions_migrated += 1;
+            remaining -= 1;
+        }
+
+        // Rewrite the orders of each asset in the new layout
+        while remaining > 0 {
+            let asset_id = match migration.limit_orders.keys_as_vector().get(0) {
+                Some(asset_id) => asset_id,
+                None => break,
+            };
+            let orders = migration.limit_orders.remove(&asset_id).unwrap();
+            self.limit_orders
+                .insert(&asset_id, &LimitOrders::from(orders));
+            remaining -= 1;
+        }
+
+        let done =
+            migration.positions_migrated == positions.len() && migration.limit_orders.is_empty();
+        if done {
+            self.state = migration.state;
+        } else {
+            self.pending_migration = Some(migration);
+        }
+        done
+    }
+}
 
 #[near_bindgen]
 impl VContract {
@@ -12,7 +385,18 @@ impl VContract {
     #[init(ignore_state)]
     pub fn migrate() -> Self {
         let contract: VContract = env::state_read().expect("Contract is not initialized");
-        contract
+        match contract {
+            Self::V1(contract) => Self::V2(contract.into()),
+            Self::V2(contract) => Self::V2(contract),
+        }
+    }
+
+    /// Continue the migration from [VContract::V1], converting up to `limit`
+    /// items. Only callable by the owner. Returns true once it is done.
+    pub fn migrate_batch(&mut self, limit: Option<u64>) -> bool {
+        let contract = self.contract_mut();
+        contract.assert_owner();
+        contract.migrate_batch(limit.unwrap_or(100))
     }
 
     pub fn upgrade(&self) -> Promise {
@@ -33,3 +417,220 @@ impl VContract {
             .as_return()
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::DOLLAR_DENOMINATION;
+
+    fn asset_v1(asset_id: AssetId, stable: bool, price: DollarBalance) -> AssetV1 {
+        AssetV1 {
+            asset_id,
+            decimals: 24,
+            stable,
+            token_weight: 1,
+            min_profit_bps: 0,
+            max_pool_amount: 0,
+            shortable: !stable,
+            balance: 0,
+            pool_balance: 0,
+            reserved_amount: 0,
+            buffer_amount: 0,
+            state: Default::default(),
+            global_short_size: 0,
+            global_short_average_price: 0,
+            global_long_size: 0,
+            global_long_average_price: 0,
+            accumulated_fees: 0,
+            price,
+            spread_bps: 0,
+            last_funding_time: 0,
+            base_funding_rate: 0,
+            cumulative_funding_rate: 0,
+            guaranteed_usd: 0,
+            switchboard_aggregator_address: None,
+            max_price_change_bps: None,
+            last_change_timestamp_ms: 0,
+            open_interest_limits: Default::default(),
+            position_limits: Default::default(),
+            token_transfer_history: Default::default(),
+            withdrawal_limit_bps: 5000,
+        }
+    }
+
+    fn limit_order_v1() -> LimitOrderV1 {
+        LimitOrderV1 {
+            owner: accounts(1),
+            collateral_delta: 0,
+            attached_collateral: 10,
+            size_delta: 100 * DOLLAR_DENOMINATION,
+            collateral_id: AssetId::NEAR,
+            underlying_id: AssetId::NEAR,
+            price: 4 * DOLLAR_DENOMINATION,
+            is_long: true,
+            expiry: 1000,
+            order_type: OrderType::Increase,
+            threshold: ThresholdType::Below,
+        }
+    }
+
+    fn contract_v1() -> ContractV1 {
+        let usdc_id = AssetId::Ft(accounts(4));
+        let assets = AssetsMapV1(HashMap::from([
+            (
+                AssetId::NEAR,
+                asset_v1(AssetId::NEAR, false, 5 * DOLLAR_DENOMINATION),
+            ),
+            (
+                usdc_id.clone(),
+                asset_v1(usdc_id.clone(), true, DOLLAR_DENOMINATION),
+            ),
+        ]));
+
+        let mut lp_token = FungibleTokenV1 {
+            accounts: LookupMap::new(StoragePrefix::LpToken),
+            total_supply: 100,
+        };
+        lp_token.accounts.insert(&accounts(1), &100);
+
+        let mut positions = UnorderedMap::new(StoragePrefix::Positions);
+        positions.insert(
+            &PositionId::new(&accounts(1), &usdc_id, &AssetId::NEAR, true, 1),
+            &Position {
+                size: 50 * DOLLAR_DENOMINATION,
+                collateral: 10 * DOLLAR_DENOMINATION,
+                average_price: 5 * DOLLAR_DENOMINATION,
+                entry_funding_rate: 3,
+                reserve_amount: 0,
+                realized_pnl: 0,
+                last_increased_time: 0,
+                account_id: accounts(1),
+                collateral_id: usdc_id.into_string(),
+                underlying_id: AssetId::NEAR.into_string(),
+                is_long: true,
+            },
+        );
+
+        let mut limit_orders = UnorderedMap::new(StoragePrefix::LimitOrders);
+        let limit_order_id = LimitOrderId::new(&limit_order_v1().into(), 1);
+        limit_orders.insert(
+            &AssetId::NEAR,
+            &LimitOrdersV1(BTreeMap::from([(limit_order_id, limit_order_v1())])),
+        );
+
+        ContractV1 {
+            owner_id: accounts(0),
+            state: ContractState::Running,
+            liquidators: UnorderedSet::new(StoragePrefix::Liquidators),
+            price_oracles: UnorderedSet::new(StoragePrefix::PriceOracles),
+            admins: UnorderedMap::new(StoragePrefix::Admins),
+            goblins: UnorderedSet::new(StoragePrefix::Goblins),
+            assets,
+            lp_token,
+            position_ids_map: UnorderedMap::new(StoragePrefix::PositionIdsMap),
+            positions,
+            limit_order_ids_map: UnorderedMap::new(StoragePrefix::LimitOrderIdsMap),
+            limit_orders,
+            limit_order_sequence: 1,
+            referral_code_owners: UnorderedMap::new(StoragePrefix::ReferralCodeOwners),
+            user_referral_code: UnorderedMap::new(StoragePrefix::UserReferralCodes),
+            total_weights: 2,
+            min_leverage: 1000,
+            max_leverage: 11000,
+            liquidation_reward_usd: 0,
+            funding_interval_seconds: 3600,
+            base_funding_rate: 100,
+            swap_enabled: true,
+            leverage_enabled: true,
+            limit_orders_state: LimitOrdersState::Enabled,
+            fee_parameters: FeeParameters {
+                tax_bps: 50,
+                stable_tax_bps: 50,
+                mint_burn_fee_bps: 10,
+                swap_fee_bps: 10,
+                stable_swap_fee_bps: 4,
+                margin_fee_bps: 10,
+            },
+            manager_mode: false,
+            private_liquidation_only: true,
+            min_profit_time_seconds: 60,
+            dynamic_swap_fees: false,
+            dynamic_position_fees: false,
+            default_stable_coin: Some(usdc_id),
+            max_staleness_duration_sec: 90,
+            max_limit_order_life_sec: 60,
+        }
+    }
+
+    fn migrate_contract_v1(contract: ContractV1) -> VContract {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .build());
+        env::state_write(&VContract::V1(contract));
+        VContract::migrate()
+    }
+
+    #[test]
+    fn migrate_v1() {
+        let mut vcontract = migrate_contract_v1(contract_v1());
+        let contract = vcontract.contract();
+        assert_eq!(contract.owner_id, accounts(0));
+        assert_eq!(contract.treasury_id, accounts(0));
+        assert_eq!(contract.total_weights, 2);
+        assert_eq!(contract.limit_order_limits.max_orders_per_account, 100);
+        assert_eq!(contract.state, ContractState::Paused);
+
+        let near = contract.assets.unwrap(&AssetId::NEAR);
+        assert_eq!(near.mark_price, 5 * DOLLAR_DENOMINATION);
+        assert_eq!(near.depeg_band_bps, DEFAULT_DEPEG_BAND_BPS);
+
+        let lp_token = &contract.lp_token;
+        assert_eq!(lp_token.total_supply, 100);
+        assert_eq!(lp_token.internal_unwrap_balance_of(&accounts(1)), 100);
+        assert!(lp_token.free_storage);
+        assert!(lp_token.account_storage_usage > 0);
+
+        // The position, then the order book
+        assert!(!vcontract.migrate_batch(Some(1)));
+        assert!(vcontract.migrate_batch(Some(1)));
+        let contract = vcontract.contract();
+        assert_eq!(contract.state, ContractState::Running);
+        assert!(contract.pending_migration.is_none());
+
+        let near = contract.assets.unwrap(&AssetId::NEAR);
+        assert_eq!(near.collateral_position_size, 0);
+        let usdc = contract.assets.unwrap(&AssetId::Ft(accounts(4)));
+        assert_eq!(usdc.collateral_position_size, 50 * DOLLAR_DENOMINATION);
+        assert_eq!(usdc.collateral_entry_funding, 150 * DOLLAR_DENOMINATION);
+
+        let limit_orders = contract.limit_orders.get(&AssetId::NEAR).unwrap();
+        let limit_order = &limit_orders.to_vec()[0];
+        assert_eq!(limit_order.owner, accounts(1));
+        assert_eq!(limit_order.attached_collateral, 10);
+        assert_eq!(limit_order.execution_fee, 0);
+        assert_eq!(limit_order.storage_usage, 0);
+        assert!(!limit_order.reduce_only);
+    }
+
+    #[test]
+    fn migrate_v1_unlisted_collateral() {
+        let mut contract = contract_v1();
+        contract.assets.0.remove(&AssetId::Ft(accounts(4)));
+
+        let mut vcontract = migrate_contract_v1(contract);
+        assert!(vcontract.migrate_batch(None));
+        assert_eq!(vcontract.contract().state, ContractState::Running);
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract migration in progress")]
+    fn set_state_during_migration() {
+        let mut vcontract = migrate_contract_v1(contract_v1());
+        vcontract.add_admin(accounts(0), AdminRole::FullAdmin);
+        vcontract.set_state(ContractState::Running);
+    }
+}
@@ -3,14 +3,14 @@ use std::str::FromStr;
 use near_sdk::{json_types::U128, log};
 use tonic_perps_sdk::prelude::{
     emit_event, EditFeesEvent, EditGuaranteedUsdEvent, EditPoolBalanceEvent,
-    EditReservedAmountEvent, EventType, FeeType,
+    EditReservedAmountEvent, EventType, FeeDestination, FeeType,
 };
 
 use crate::{
-    borsh, env, ratio, round, u128_dec_format, AccountId, Balance, BorshDeserialize,
-    BorshSerialize, Contract, Deserialize, DollarBalance, HashMap, Serialize, SwitchboardAddress,
-    TokenTransfer, TokenTransferHistory, TransferType, BN, BPS_DIVISOR, DOLLAR_DENOMINATION,
-    FUNDING_RATE_PRECISION, U256,
+    borsh, env, ratio, round, u128_dec_format, AccountId, AumPrice, Balance, BorshDeserialize,
+    BorshSerialize, Contract, Deserialize, DollarBalance, HashMap, PriceOverride, Serialize,
+    SwitchboardAddress, TokenTransfer, TokenTransferHistory, TransferType, BN, BPS_DIVISOR,
+    DOLLAR_DENOMINATION, FUNDING_RATE_PRECISION, U256,
 };
 
 #[derive(
@@ -34,6 +34,9 @@ pub enum AssetId {
 const NEAR_TOKEN_NAME: &str = "near";
 const PERCENTAGE_MULTIPLIER: u128 = 100;
 
+/// Default allowed deviation of a stablecoin from its peg. 2%
+pub const DEFAULT_DEPEG_BAND_BPS: u16 = 200;
+
 impl From<String> for AssetId {
     fn from(s: String) -> Self {
         if s.to_lowercase() == *NEAR_TOKEN_NAME {
@@ -299,6 +302,43 @@ pub struct Asset {
 
     /// Maximium withdrawal percentage (in bps) that can be withdrawn in the window specified above
     pub withdrawal_limit_bps: u128,
+
+    /// Maximum staleness duration of the asset price. Falls back to the
+    /// contract-wide value if not set.
+    pub max_staleness_duration_sec: Option<u64>,
+
+    /// Maximum deviation of a stablecoin price from 1 USD, in bps. Outside of
+    /// this band the stablecoin is considered depegged.
+    pub depeg_band_bps: u16,
+
+    /// Last time the Switchboard aggregator was queried for this asset
+    pub last_switchboard_query_ms: u64,
+
+    /// Number of failed Switchboard price reads
+    pub switchboard_failures: u32,
+
+    /// Emergency admin price, see [PriceOverride]
+    pub price_override: Option<PriceOverride>,
+
+    /// Exponential moving average of the index price. Used instead of the
+    /// index price to determine liquidations, so a single price spike does
+    /// not liquidate positions.
+    pub mark_price: DollarBalance,
+
+    /// Weight of the index price in the mark price for each second elapsed
+    /// between price updates, in bps. With 10000 the mark price follows the
+    /// index price.
+    pub mark_price_smoothing_bps: u16,
+
+    /// Part of the accumulated fees which will be distributed to stakers
+    pub staking_fees: Balance,
+
+    /// Sum of the sizes of open positions using this asset as collateral
+    pub collateral_position_size: DollarBalance,
+
+    /// Sum of size * entry funding rate of open positions using this asset as
+    /// collateral. Used to compute pending funding fees.
+    pub collateral_entry_funding: u128,
 }
 
 #[derive(Serialize)]
@@ -315,6 +355,7 @@ pub struct AssetView {
     pub average_price: U128,
     pub entry_price: U128,
     pub exit_price: U128,
+    pub mark_price: U128,
     pub funding_rate: u64,
     pub funding_rate_percentage: String,
     pub accumulated_fees: U128,
@@ -322,6 +363,9 @@ pub struct AssetView {
     pub position_limits: AssetPositionLimits,
     pub open_interest_long: U128,
     pub open_interest_short: U128,
+    pub depegged: bool,
+    pub switchboard_failures: u32,
+    pub close_only: bool,
 }
 
 /// Use [Drop] to ensure balance integrity.
@@ -362,7 +406,9 @@ impl Asset {
         );
     }
 
-    pub fn to_view(&self) -> AssetView {
+    /// `funding_lp_bps` is the share of funding fees which goes to the pool,
+    /// see [Asset::aum]
+    pub fn to_view(&self, funding_lp_bps: u16) -> AssetView {
         let funding_rate = self.current_funding_rate();
         let funding_rate_percentage = (funding_rate as f64 * PERCENTAGE_MULTIPLIER as f64
             / FUNDING_RATE_PRECISION as f64)
@@ -379,14 +425,18 @@ impl Asset {
             average_price: self.price.into(),
             entry_price: self.max_price().into(),
             exit_price: self.min_price().into(),
+            mark_price: self.get_mark_price().into(),
             accumulated_fees: self.accumulated_fees.into(),
             funding_rate,
             funding_rate_percentage,
             maximum_pool_amount: 0.into(),
-            aum: self.aum().into(),
+            aum: self.aum(AumPrice::Index, funding_lp_bps).aum.into(),
             position_limits: self.position_limits.clone(),
             open_interest_long: self.global_long_size.into(),
             open_interest_short: self.global_short_size.into(),
+            depegged: self.is_depegged(),
+            switchboard_failures: self.switchboard_failures,
+            close_only: self.is_close_only(),
         }
     }
 
@@ -428,6 +478,16 @@ impl Asset {
             position_limits: Default::default(),
             token_transfer_history: Default::default(),
             withdrawal_limit_bps: 5000,
+            max_staleness_duration_sec: None,
+            depeg_band_bps: DEFAULT_DEPEG_BAND_BPS,
+            last_switchboard_query_ms: 0,
+            switchboard_failures: 0,
+            price_override: None,
+            mark_price: 0,
+            mark_price_smoothing_bps: BPS_DIVISOR as u16,
+            staking_fees: 0,
+            collateral_position_size: 0,
+            collateral_entry_funding: 0,
         }
     }
 
@@ -455,29 +515,67 @@ impl Asset {
         ratio(amount, self.price, self.denomination())
     }
 
-    /// Returns dollar value of all assets in the pool + profits/losses from shorts on the asset
-    /// with *any* collateral backing those shorts
-    pub fn aum(&self) -> DollarBalance {
-        if !self.stable {
-            let mut aum: DollarBalance = 0;
-            let short_size = self.global_short_size;
-            let average_price = self.global_short_average_price;
-            aum += self.guaranteed_usd;
-            aum += self.dollar_value_of(self.pool_balance);
-            aum -= self.dollar_value_of(self.reserved_amount);
-
-            if short_size > 0 {
-                let (has_profit, delta) = get_delta(average_price, self.price, short_size, false);
-                if has_profit {
-                    aum.saturating_sub(delta)
-                } else {
-                    aum + delta
-                }
-            } else {
-                aum
+    /// Returns true if the asset is a stablecoin trading outside of its peg band
+    pub fn is_depegged(&self) -> bool {
+        self.stable
+            && self.price.abs_diff(DOLLAR_DENOMINATION)
+                > ratio(DOLLAR_DENOMINATION, self.depeg_band_bps, BPS_DIVISOR)
+    }
+
+    /// Move the mark price towards the current index price, weighted by the
+    /// time elapsed since the previous price update. Updates within the same
+    /// second do not move a smoothed mark price.
+    pub fn update_mark_price(&mut self, elapsed_sec: u64) {
+        let smoothing_bps = self.mark_price_smoothing_bps as u128;
+        if self.mark_price == 0 || smoothing_bps >= BPS_DIVISOR {
+            self.mark_price = self.price;
+            return;
+        }
+
+        // Weight of the previous mark price, (1 - smoothing) ^ elapsed_sec
+        let mut kept_bps = BPS_DIVISOR;
+        let mut base_bps = BPS_DIVISOR - smoothing_bps;
+        let mut exp = elapsed_sec;
+        while exp > 0 && kept_bps > 0 {
+            if exp & 1 == 1 {
+                kept_bps = kept_bps * base_bps / BPS_DIVISOR;
             }
+            base_bps = base_bps * base_bps / BPS_DIVISOR;
+            exp >>= 1;
+        }
+
+        self.mark_price = ratio(self.price, BPS_DIVISOR - kept_bps, BPS_DIVISOR)
+            + ratio(self.mark_price, kept_bps, BPS_DIVISOR);
+    }
+
+    /// Mark price used for liquidations. Falls back to the index price if
+    /// the mark price was never set.
+    pub fn get_mark_price(&self) -> DollarBalance {
+        if self.mark_price == 0 {
+            self.price
         } else {
-            self.dollar_value_of(self.pool_balance)
+            self.mark_price
+        }
+    }
+
+    /// Returns true if the asset is priced by an active admin override. Only
+    /// closing positions is allowed in that case.
+    pub fn is_close_only(&self) -> bool {
+        self.price_override
+            .as_ref()
+            .is_some_and(|price_override| price_override.active)
+    }
+
+    pub fn assert_not_close_only(&self) {
+        if self.is_close_only() {
+            env::panic_str("Asset is in close-only mode");
+        }
+    }
+
+    /// Panics if the asset is a depegged stablecoin
+    pub fn assert_pegged(&self) {
+        if self.is_depegged() {
+            env::panic_str("Stablecoin is outside of its peg band");
         }
     }
 
@@ -526,30 +624,94 @@ impl Asset {
     pub fn add_fees(&mut self, fees: Balance, fee_type: FeeType, account_id: &AccountId) {
         self.balance += fees;
         self.accumulated_fees += fees;
-        emit_event(EventType::EditFees(EditFeesEvent {
-            fee_native: fees,
-            fee_usd: self.to_min_usd_price(fees),
-            fee_type,
-            new_accumulated_fees_native: self.accumulated_fees,
-            new_accumulated_fees_usd: self.to_min_usd_price(self.accumulated_fees),
+        self.emit_edit_fees(fees, fee_type, true, FeeDestination::Treasury, account_id);
+    }
+
+    /// Add fees reserved for stakers. They are counted as accumulated fees
+    /// until distributed.
+    pub fn add_staking_fees(&mut self, fees: Balance, fee_type: FeeType, account_id: &AccountId) {
+        self.balance += fees;
+        self.accumulated_fees += fees;
+        self.staking_fees += fees;
+        self.emit_edit_fees(fees, fee_type, true, FeeDestination::Staking, account_id);
+    }
+
+    /// Add fees to the pool balance, raising the LP token price
+    pub fn add_pool_fees(&mut self, fees: Balance, fee_type: FeeType, account_id: &AccountId) {
+        self.balance += fees;
+        self.pool_balance += fees;
+        self.emit_edit_fees(fees, fee_type, true, FeeDestination::Pool, account_id);
+        emit_event(EventType::EditPoolBalance(EditPoolBalanceEvent {
+            amount_native: fees,
+            new_pool_balance_native: self.pool_balance,
             increase: true,
             account_id: account_id.clone(),
             asset_id: self.asset_id.into_string(),
         }));
     }
 
+    /// Fees which are not set aside for stakers
+    pub fn withdrawable_fees(&self) -> Balance {
+        self.accumulated_fees - self.staking_fees
+    }
+
     pub fn remove_fees(&mut self, fees: Balance, fee_type: FeeType, account_id: &AccountId) {
         self.balance -= fees;
         self.accumulated_fees -= fees;
+        self.emit_edit_fees(fees, fee_type, false, FeeDestination::Treasury, account_id);
+    }
+
+    /// Remove fees reserved for stakers once they are distributed
+    pub fn remove_staking_fees(&mut self, fees: Balance, account_id: &AccountId) {
+        self.balance -= fees;
+        self.accumulated_fees -= fees;
+        self.staking_fees -= fees;
+        self.emit_edit_fees(
+            fees,
+            FeeType::WithrawFee,
+            false,
+            FeeDestination::Staking,
+            account_id,
+        );
+    }
+
+    /// Give fees reserved for stakers to the treasury instead
+    pub fn release_staking_fees(&mut self, fees: Balance, account_id: &AccountId) {
+        self.staking_fees -= fees;
+        self.emit_edit_fees(
+            fees,
+            FeeType::WithrawFee,
+            false,
+            FeeDestination::Staking,
+            account_id,
+        );
+        self.emit_edit_fees(
+            fees,
+            FeeType::WithrawFee,
+            true,
+            FeeDestination::Treasury,
+            account_id,
+        );
+    }
+
+    fn emit_edit_fees(
+        &self,
+        fees: Balance,
+        fee_type: FeeType,
+        increase: bool,
+        destination: FeeDestination,
+        account_id: &AccountId,
+    ) {
         emit_event(EventType::EditFees(EditFeesEvent {
             fee_native: fees,
             fee_usd: self.to_min_usd_price(fees),
             fee_type,
             new_accumulated_fees_native: self.accumulated_fees,
             new_accumulated_fees_usd: self.to_min_usd_price(self.accumulated_fees),
-            increase: false,
+            increase,
             account_id: account_id.clone(),
             asset_id: self.asset_id.into_string(),
+            destination,
         }));
     }
 
@@ -613,6 +775,29 @@ impl Asset {
         self.global_long_size -= amount;
     }
 
+    /// Track an open position using this asset as collateral
+    pub fn add_collateral_position(&mut self, size: DollarBalance, entry_funding_rate: u128) {
+        self.collateral_position_size += size;
+        self.collateral_entry_funding += size * entry_funding_rate;
+    }
+
+    /// Stop tracking an open position using this asset as collateral
+    pub fn remove_collateral_position(&mut self, size: DollarBalance, entry_funding_rate: u128) {
+        // Saturate for positions opened before the totals were tracked
+        self.collateral_position_size = self.collateral_position_size.saturating_sub(size);
+        self.collateral_entry_funding = self
+            .collateral_entry_funding
+            .saturating_sub(size * entry_funding_rate);
+    }
+
+    /// Funding fees accrued by open positions using this asset as collateral
+    /// which have not been charged yet, in USD
+    pub fn pending_funding_fees(&self) -> DollarBalance {
+        (self.collateral_position_size * self.cumulative_funding_rate)
+            .saturating_sub(self.collateral_entry_funding)
+            / FUNDING_RATE_PRECISION as u128
+    }
+
     pub fn update_cumulative_funding_rate(
         &mut self,
         block_timestamp_seconds: u64,
@@ -814,10 +999,6 @@ impl AssetsMap {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
-
-    pub fn get_total_aum(&self) -> DollarBalance {
-        self.0.values().map(Asset::aum).sum()
-    }
 }
 
 impl Contract {
@@ -911,6 +1092,16 @@ mod tests {
             position_limits: Default::default(),
             token_transfer_history: Default::default(),
             withdrawal_limit_bps: 10000,
+            max_staleness_duration_sec: None,
+            depeg_band_bps: 0,
+            last_switchboard_query_ms: 0,
+            switchboard_failures: 0,
+            price_override: None,
+            mark_price: 0,
+            mark_price_smoothing_bps: 0,
+            staking_fees: 0,
+            collateral_position_size: 0,
+            collateral_entry_funding: 0,
         };
     }
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::{I128, U128};
+
+use crate::{
+    get_delta, near_bindgen, ratio, Asset, Contract, DollarBalance, Serialize, VContract,
+    VContractExt, BPS_DIVISOR,
+};
+
+/// Price used to value pool assets
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AumPrice {
+    /// Index price, used for views
+    Index,
+    /// Maximum price, used when minting LP tokens
+    Max,
+    /// Minimum price, used when burning LP tokens
+    Min,
+}
+
+impl AumPrice {
+    pub fn from_maximise(maximise: Option<bool>) -> Self {
+        match maximise {
+            None => AumPrice::Index,
+            Some(true) => AumPrice::Max,
+            Some(false) => AumPrice::Min,
+        }
+    }
+}
+
+/// Components of the AUM of a single asset, in USD
+#[derive(Default)]
+pub struct AssetAum {
+    /// Value of the pool balance
+    pub pool_usd: DollarBalance,
+    /// Value of the pool balance reserved for open positions
+    pub reserved_usd: DollarBalance,
+    /// Sum of (size - collateral) of longs
+    pub guaranteed_usd: DollarBalance,
+    /// Unrealised PnL of longs, positive if traders are in profit
+    pub long_pnl: i128,
+    /// Unrealised PnL of shorts, positive if traders are in profit
+    pub short_pnl: i128,
+    /// Pool's share of funding fees which open positions haven't paid yet
+    pub pending_funding_usd: DollarBalance,
+    pub aum: DollarBalance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetAumView {
+    pub pool_usd: U128,
+    pub reserved_usd: U128,
+    pub guaranteed_usd: U128,
+    pub long_pnl: I128,
+    pub short_pnl: I128,
+    pub pending_funding_usd: U128,
+    pub aum: U128,
+}
+
+impl From<AssetAum> for AssetAumView {
+    fn from(aum: AssetAum) -> Self {
+        Self {
+            pool_usd: aum.pool_usd.into(),
+            reserved_usd: aum.reserved_usd.into(),
+            guaranteed_usd: aum.guaranteed_usd.into(),
+            long_pnl: aum.long_pnl.into(),
+            short_pnl: aum.short_pnl.into(),
+            pending_funding_usd: aum.pending_funding_usd.into(),
+            aum: aum.aum.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AumBreakdownView {
+    pub total_aum: U128,
+    pub assets: HashMap<String, AssetAumView>,
+}
+
+fn signed_pnl((has_profit, delta): (bool, DollarBalance)) -> i128 {
+    if has_profit {
+        delta as i128
+    } else {
+        -(delta as i128)
+    }
+}
+
+impl Asset {
+    pub fn aum_price(&self, price: AumPrice) -> DollarBalance {
+        match price {
+            AumPrice::Index => self.price,
+            AumPrice::Max => self.max_price(),
+            AumPrice::Min => self.min_price(),
+        }
+    }
+
+    /// Returns dollar value of all assets in the pool + profits/losses from
+    /// shorts on the asset with *any* collateral backing those shorts + the
+    /// pool's share of pending funding fees of positions using this asset as
+    /// collateral. `funding_lp_bps` is the share of funding fees added to the
+    /// pool.
+    ///
+    /// Long PnL is accounted for through the reserved amount: the pool owns
+    /// its balance minus the tokens reserved for longs, plus the
+    /// `guaranteed_usd` owed by them. `long_pnl` is reported for auditing
+    /// and is not added again.
+    pub fn aum(&self, price: AumPrice, funding_lp_bps: u16) -> AssetAum {
+        let pending_funding_usd = ratio(self.pending_funding_fees(), funding_lp_bps, BPS_DIVISOR);
+        let mut aum = AssetAum {
+            pending_funding_usd,
+            ..Default::default()
+        };
+
+        if self.stable {
+            aum.pool_usd = ratio(
+                self.pool_balance,
+                self.aum_price(price),
+                self.denomination(),
+            );
+            aum.aum = aum.pool_usd + pending_funding_usd;
+            return aum;
+        }
+
+        let price = self.aum_price(price);
+        aum.pool_usd = ratio(self.pool_balance, price, self.denomination());
+        aum.reserved_usd = ratio(self.reserved_amount, price, self.denomination());
+        aum.guaranteed_usd = self.guaranteed_usd;
+
+        if self.global_long_size > 0 && self.global_long_average_price > 0 {
+            aum.long_pnl = signed_pnl(get_delta(
+                self.global_long_average_price,
+                price,
+                self.global_long_size,
+                true,
+            ));
+        }
+        if self.global_short_size > 0 {
+            aum.short_pnl = signed_pnl(get_delta(
+                self.global_short_average_price,
+                price,
+                self.global_short_size,
+                false,
+            ));
+        }
+
+        let value = aum.guaranteed_usd + aum.pool_usd - aum.reserved_usd;
+        // Profits of shorts are paid by the pool, losses are paid to it
+        let value = if aum.short_pnl > 0 {
+            value.saturating_sub(aum.short_pnl.unsigned_abs())
+        } else {
+            value + aum.short_pnl.unsigned_abs()
+        };
+        aum.aum = value + pending_funding_usd;
+        aum
+    }
+}
+
+impl Contract {
+    pub fn get_asset_aum(&self, asset: &Asset, price: AumPrice) -> AssetAum {
+        asset.aum(price, self.fee_split_parameters.funding.lp_bps)
+    }
+
+    /// Dollar value of all assets under management, valued at `price`
+    pub fn get_aum(&self, price: AumPrice) -> DollarBalance {
+        self.assets
+            .0
+            .values()
+            .map(|asset| self.get_asset_aum(asset, price).aum)
+            .sum()
+    }
+
+    pub fn get_aum_breakdown(&self, price: AumPrice) -> AumBreakdownView {
+        let assets: HashMap<_, _> = self
+            .assets
+            .0
+            .iter()
+            .map(|(asset_id, asset)| {
+                (
+                    asset_id.into_string(),
+                    AssetAumView::from(self.get_asset_aum(asset, price)),
+                )
+            })
+            .collect();
+        AumBreakdownView {
+            total_aum: assets.values().map(|aum| aum.aum.0).sum::<u128>().into(),
+            assets,
+        }
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// AUM per component and per asset. `maximise` selects the max (mint) or
+    /// min (burn) prices; the index price is used if not set.
+    pub fn get_aum_breakdown(&self, maximise: Option<bool>) -> AumBreakdownView {
+        self.contract()
+            .get_aum_breakdown(AumPrice::from_maximise(maximise))
+    }
+}
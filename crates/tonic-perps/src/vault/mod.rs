@@ -9,8 +9,10 @@ use near_sdk::{Gas, Promise, PromiseOrValue, ONE_YOCTO};
 use std::time::Duration;
 
 mod asset;
+mod aum;
 
 pub use asset::*;
+pub use aum::*;
 
 pub const TGAS_FOR_FT_TRANSFER: u64 = 20;
 
@@ -46,7 +48,7 @@ impl TransferInfo {
 
 impl Contract {
     pub(crate) fn get_total_aum(&self) -> DollarBalance {
-        self.assets.get_total_aum()
+        self.get_aum(AumPrice::Index)
     }
 
     pub fn update_cumulative_funding_rate(&self, asset: &mut Asset) -> u128 {
@@ -74,6 +76,9 @@ impl Contract {
             }));
             match transfer_info.asset_id {
                 AssetId::NEAR => Promise::new(receiver_id.clone()).transfer(amount).into(),
+                AssetId::Ft(_) if self.is_wnear(&asset_id) => {
+                    self.internal_send_wnear(transfer_info).into()
+                }
                 AssetId::Ft(_) => self.internal_send_ft(transfer_info).into(),
             }
         } else {
@@ -1,9 +1,10 @@
 use near_sdk::json_types::{U128, U64};
 
 use crate::{
-    get_funding_fee, near_bindgen, AccountId, AdminRole, Asset, AssetId, AssetView, Balance,
-    Base58VecU8, ContractState, LimitOrder, LimitOrderId, LiquidationStatus, LiquidationView,
-    OrderType, PositionId, PositionView, Serialize, ThresholdType, VContract, VContractExt,
+    env, get_funding_fee, near_bindgen, AccountId, AdminRole, AssetId, AssetView, AumPrice,
+    Balance, Base58VecU8, ContractState, LimitOrder, LimitOrderId, LiquidationStatus,
+    LiquidationView, OrderType, PositionId, PositionView, Serialize, ThresholdType, TwapOrder,
+    TwapOrderId, VContract, VContractExt,
 };
 
 #[derive(Serialize)]
@@ -47,6 +48,10 @@ pub struct LimitOrderView {
     pub size_delta: U128,
     pub threshold: ThresholdType,
     pub underlying_id: String,
+    pub output_id: Option<String>,
+    pub reduce_only: bool,
+    pub close_position: bool,
+    pub execution_fee: U128,
 }
 
 impl LimitOrderView {
@@ -64,6 +69,59 @@ impl LimitOrderView {
             size_delta: lo.size_delta.into(),
             threshold: lo.threshold,
             underlying_id: lo.underlying_id.into_string(),
+            output_id: lo.output_id.as_ref().map(|id| id.into_string()),
+            reduce_only: lo.reduce_only,
+            close_position: lo.close_position,
+            execution_fee: lo.execution_fee.into(),
+        }
+    }
+}
+
+/// Resting limit orders of one side and threshold in a price bucket
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LimitOrderDepthLevel {
+    /// Lowest price of the bucket
+    pub price: U128,
+    pub is_long: bool,
+    pub threshold: ThresholdType,
+    /// Total size of increase orders, in USD
+    pub increase_size: U128,
+    /// Total size of decrease orders, in USD
+    pub decrease_size: U128,
+    pub num_orders: u32,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TwapOrderView {
+    pub id: U64,
+    pub owner: String,
+    pub collateral_id: String,
+    pub underlying_id: String,
+    pub is_long: bool,
+    pub remaining_collateral: U128,
+    pub slice_size: U128,
+    pub num_slices: u16,
+    pub remaining_slices: u16,
+    pub interval_sec: u64,
+    pub next_slice_time: U64,
+}
+
+impl TwapOrderView {
+    pub fn new(order: &TwapOrder, id: TwapOrderId) -> Self {
+        Self {
+            id: id.into(),
+            owner: order.owner.to_string(),
+            collateral_id: order.collateral_id.into_string(),
+            underlying_id: order.underlying_id.into_string(),
+            is_long: order.is_long,
+            remaining_collateral: order.remaining_collateral.into(),
+            slice_size: order.slice_size.into(),
+            num_slices: order.num_slices,
+            remaining_slices: order.remaining_slices,
+            interval_sec: order.interval_sec,
+            next_slice_time: order.next_slice_time.into(),
         }
     }
 }
@@ -78,12 +136,21 @@ pub struct PositionAccountView {
 #[near_bindgen]
 impl VContract {
     pub fn get_asset_info(&self, asset: String) -> AssetView {
-        self.contract().assets.unwrap(&asset.into()).to_view()
+        let contract = self.contract();
+        contract
+            .assets
+            .unwrap(&asset.into())
+            .to_view(contract.fee_split_parameters.funding.lp_bps)
     }
 
     pub fn get_assets(&self) -> Vec<AssetView> {
-        let assets = self.contract().get_assets();
-        assets.values().map(Asset::to_view).collect()
+        let contract = self.contract();
+        let funding_lp_bps = contract.fee_split_parameters.funding.lp_bps;
+        contract
+            .get_assets()
+            .values()
+            .map(|asset| asset.to_view(funding_lp_bps))
+            .collect()
     }
 
     pub fn get_oracles(&self) -> Vec<AccountId> {
@@ -136,6 +203,16 @@ impl VContract {
         self.contract().lp_price().into()
     }
 
+    /// LP token price used to mint, with assets valued at their max price
+    pub fn get_lp_mint_price(&self) -> U128 {
+        self.contract().lp_price_at(AumPrice::Max).into()
+    }
+
+    /// LP token price used to burn, with assets valued at their min price
+    pub fn get_lp_burn_price(&self) -> U128 {
+        self.contract().lp_price_at(AumPrice::Min).into()
+    }
+
     pub fn get_positions(&self, account_id: AccountId) -> Vec<PositionView> {
         let mut positions: Vec<PositionView> = vec![];
         if let Some(position_ids) = self.contract().position_ids_map.get(&account_id) {
@@ -307,6 +384,41 @@ impl VContract {
         self.contract().get_user_limit_orders(account_id)
     }
 
+    /// NEAR deposited for order storage and not used by open orders
+    pub fn get_order_storage_balance(&self, account_id: AccountId) -> U128 {
+        self.contract()
+            .get_order_storage_balance(&account_id)
+            .into()
+    }
+
+    pub fn get_twap_order(&self, twap_order_id: U64) -> Option<TwapOrderView> {
+        self.contract()
+            .twap_orders
+            .get(&twap_order_id.0)
+            .map(|order| TwapOrderView::new(&order, twap_order_id.0))
+    }
+
+    pub fn get_user_twap_orders(&self, account_id: AccountId) -> Vec<TwapOrderView> {
+        self.contract()
+            .twap_orders
+            .iter()
+            .filter(|(_, order)| order.owner == account_id)
+            .map(|(id, order)| TwapOrderView::new(&order, id))
+            .collect()
+    }
+
+    /// IDs of TWAP orders whose next slice can be executed
+    pub fn get_executable_twap_orders(&self, max: Option<u64>) -> Vec<U64> {
+        let now = env::block_timestamp_ms();
+        self.contract()
+            .twap_orders
+            .iter()
+            .filter(|(_, order)| order.next_slice_time <= now)
+            .take(max.map_or(usize::MAX, |max| max as usize))
+            .map(|(id, _)| id.into())
+            .collect()
+    }
+
     fn get_limit_order_range_vec(
         &self,
         asset_id: &AssetId,
@@ -393,6 +505,23 @@ impl VContract {
         }
     }
 
+    /// Order book depth of an underlying asset: resting orders aggregated
+    /// in price buckets of `bucket_size`
+    pub fn get_limit_order_depth(
+        &self,
+        asset_id: String,
+        bucket_size: U128,
+        price_min: Option<U128>,
+        price_max: Option<U128>,
+    ) -> Vec<LimitOrderDepthLevel> {
+        self.contract().get_limit_order_depth(
+            &AssetId::from(asset_id),
+            bucket_size.0,
+            price_min.map(|p| p.0),
+            price_max.map(|p| p.0),
+        )
+    }
+
     pub fn get_eligible_orders(&self, asset_id: String, max: Option<u64>) -> Vec<LimitOrderId> {
         self.contract()
             .get_eligible_orders(&AssetId::from(asset_id), max)
@@ -0,0 +1,132 @@
+use near_sdk::json_types::U128;
+use near_sdk::{is_promise_success, log, Gas, Promise, ONE_YOCTO};
+
+use crate::{
+    env, near_bindgen, AccountId, AssetId, Balance, Contract, TransferInfo, VContract,
+    VContractExt, TGAS_FOR_FT_TRANSFER,
+};
+
+pub const TGAS_FOR_NEAR_DEPOSIT: u64 = 10;
+pub const TGAS_FOR_NEAR_WITHDRAW: u64 = 10;
+pub const TGAS_FOR_WNEAR_CALLBACK: u64 = 10;
+pub const TGAS_FOR_WNEAR_DEPOSIT_CALLBACK: u64 = TGAS_FOR_FT_TRANSFER + 2 * TGAS_FOR_WNEAR_CALLBACK;
+
+impl Contract {
+    pub fn is_wnear(&self, asset_id: &AssetId) -> bool {
+        match (asset_id, &self.wnear_id) {
+            (AssetId::Ft(token_id), Some(wnear_id)) => token_id == wnear_id,
+            _ => false,
+        }
+    }
+
+    /// Asset of the pool backing `asset_id`. Wrapped NEAR is backed by NEAR.
+    pub fn pool_asset_id(&self, asset_id: &AssetId) -> AssetId {
+        if self.is_wnear(asset_id) {
+            AssetId::NEAR
+        } else {
+            asset_id.clone()
+        }
+    }
+
+    fn get_wnear_id(&self) -> AccountId {
+        self.wnear_id
+            .clone()
+            .unwrap_or_else(|| env::panic_str("Wrapped NEAR is not set"))
+    }
+
+    /// Unwrap wrapped NEAR held by the contract into NEAR. If the unwrap
+    /// fails, the amount is kept in `pending_wnear_unwrap` to be retried with
+    /// [VContract::retry_unwrap_near].
+    pub fn unwrap_near(&self, amount: Balance) -> Promise {
+        Promise::new(self.get_wnear_id())
+            .function_call(
+                "near_withdraw".to_string(),
+                serde_json::json!({ "amount": U128(amount) })
+                    .to_string()
+                    .into_bytes(),
+                ONE_YOCTO,
+                Gas::ONE_TERA * TGAS_FOR_NEAR_WITHDRAW,
+            )
+            .then(
+                VContract::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA * TGAS_FOR_WNEAR_CALLBACK)
+                    .on_unwrap_near(amount.into()),
+            )
+    }
+
+    /// Wrap NEAR and send it to the receiver as a regular FT payout. The
+    /// contract must be registered with the wrapped NEAR contract.
+    pub fn internal_send_wnear(&self, transfer_info: TransferInfo) -> Promise {
+        Promise::new(self.get_wnear_id())
+            .function_call(
+                "near_deposit".to_string(),
+                vec![],
+                transfer_info.amount(),
+                Gas::ONE_TERA * TGAS_FOR_NEAR_DEPOSIT,
+            )
+            .then(
+                VContract::ext(env::current_account_id())
+                    .with_static_gas(Gas::ONE_TERA * TGAS_FOR_WNEAR_DEPOSIT_CALLBACK)
+                    .on_wrap_near(transfer_info),
+            )
+    }
+}
+
+#[near_bindgen]
+impl VContract {
+    /// Unwrap wrapped NEAR left over by failed unwraps
+    pub fn retry_unwrap_near(&mut self) -> Promise {
+        let contract = self.contract_mut();
+        let amount = std::mem::take(&mut contract.pending_wnear_unwrap);
+        assert!(amount > 0, "Nothing to unwrap");
+        contract.unwrap_near(amount)
+    }
+
+    pub fn get_pending_wnear_unwrap(&self) -> U128 {
+        self.contract().pending_wnear_unwrap.into()
+    }
+
+    #[private]
+    pub fn on_unwrap_near(&mut self, amount: U128) {
+        if !is_promise_success() {
+            log!("Failed to unwrap {} wNEAR", amount.0);
+            self.contract_mut().pending_wnear_unwrap += amount.0;
+        }
+    }
+
+    /// Send the wrapped NEAR, or the NEAR back if it couldn't be wrapped
+    #[private]
+    pub fn on_wrap_near(&mut self, transfer_info: TransferInfo) -> Promise {
+        if is_promise_success() {
+            self.contract()
+                .internal_send_ft(transfer_info.clone())
+                .then(
+                    VContract::ext(env::current_account_id())
+                        .with_static_gas(Gas::ONE_TERA * TGAS_FOR_WNEAR_CALLBACK)
+                        .on_send_wnear(transfer_info),
+                )
+        } else {
+            log!(
+                "Failed to wrap {} NEAR, sending NEAR",
+                transfer_info.amount()
+            );
+            Promise::new(transfer_info.receiver_id()).transfer(transfer_info.amount())
+        }
+    }
+
+    /// If the receiver can't get wrapped NEAR, e.g. because it isn't
+    /// registered with the wrapped NEAR contract, pay it in NEAR and unwrap
+    /// the wrapped NEAR later
+    #[private]
+    pub fn on_send_wnear(&mut self, transfer_info: TransferInfo) {
+        if !is_promise_success() {
+            log!(
+                "Failed to send {} wNEAR to {}, sending NEAR",
+                transfer_info.amount(),
+                transfer_info.receiver_id()
+            );
+            self.contract_mut().pending_wnear_unwrap += transfer_info.amount();
+            Promise::new(transfer_info.receiver_id()).transfer(transfer_info.amount());
+        }
+    }
+}
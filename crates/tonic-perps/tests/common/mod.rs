@@ -48,6 +48,18 @@ pub fn near(amount: u64) -> Balance {
     amount as u128 * NEAR_DENOMINATION
 }
 
+pub fn usdc(amount: u64) -> Balance {
+    amount as u128 * 10u128.pow(6)
+}
+
+/// Move the block timestamp forward by `seconds`
+pub fn advance_time(context: &mut VMContextBuilder, seconds: u64) {
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(seconds).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+}
+
 pub fn lp_tokens(amount: f64) -> Balance {
     (amount * (LP_TOKEN_DENOMINATION as f64)) as u128
 }
@@ -129,5 +141,12 @@ pub fn setup() -> (VMContextBuilder, VContract) {
 
     vcontract.set_shortable(near_id(), true);
 
+    // Prepay the storage of limit orders
+    set_deposit(&mut context, near(1));
+    for account in [Alice, Bob, Admin] {
+        vcontract.deposit_order_storage(Some(get_account(account)));
+    }
+    set_deposit(&mut context, 0);
+
     (context, vcontract)
 }
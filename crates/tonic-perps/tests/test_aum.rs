@@ -365,6 +365,8 @@ fn test_complex_aum() {
         collateral_delta: dollars(250).into(),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
     set_predecessor(&mut context, Admin);
     update_near_price(&mut vcontract, dollars(5));
@@ -375,6 +377,121 @@ fn test_complex_aum() {
     assert_eq!(near_asset.aum.0, 4958333333)
 }
 
+#[test]
+fn test_aum_mint_burn_prices() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.update_index_price(vec![UpdateIndexPriceRequest {
+        asset_id: near_id(),
+        price: dollars(5).into(),
+        spread: Some(100),
+    }]);
+
+    // The deposit is valued at the min price of $4.95
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(1000));
+    let minted = vcontract.mint_lp_near(None, None);
+    assert_eq!(minted.0, 4950 * LP_TOKEN_DENOMINATION);
+
+    assert_eq!(
+        vcontract.get_aum_breakdown(Some(true)).total_aum.0,
+        dollars(5050)
+    );
+    assert_eq!(vcontract.get_aum_breakdown(None).total_aum.0, dollars(5000));
+    assert_eq!(
+        vcontract.get_aum_breakdown(Some(false)).total_aum.0,
+        dollars(4950)
+    );
+    assert!(vcontract.get_lp_mint_price().0 > vcontract.get_lp_price().0);
+    assert!(vcontract.get_lp_burn_price().0 < vcontract.get_lp_price().0);
+
+    // Minting and burning right away returns less than the deposit
+    set_deposit(&mut context, near(100));
+    let minted = vcontract.mint_lp_near(None, None);
+    set_deposit(&mut context, 1);
+    let amount_out = vcontract.burn_lp_token(minted, near_id(), None, None);
+    assert!(amount_out.0 < near(100));
+}
+
+#[test]
+fn test_aum_breakdown() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_fee_split_parameters(FeeSplitParameters {
+        funding: FeeSplit {
+            treasury_bps: 0,
+            lp_bps: 10000,
+            staking_bps: 0,
+        },
+        ..Default::default()
+    });
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    let position_id = vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(1000).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+
+    // Funding accrues for a day
+    context.block_timestamp(
+        context.context.block_timestamp
+            + std::time::Duration::from_secs(60 * 60 * 24).as_nanos() as u64,
+    );
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(6));
+
+    let position = vcontract.get_position(&position_id).unwrap();
+    assert!(position.funding_fee.0 > 0);
+
+    let breakdown = vcontract.get_aum_breakdown(None);
+    let near_aum = breakdown.assets.get(&near_id()).unwrap();
+    assert_eq!(near_aum.pool_usd.0, dollars(6600));
+    assert_eq!(near_aum.reserved_usd.0, dollars(1200));
+    assert_eq!(near_aum.guaranteed_usd.0, dollars(500));
+    // Long PnL is already covered by the reserved amount
+    assert_eq!(near_aum.long_pnl.0, dollars(200) as i128);
+    assert_eq!(near_aum.short_pnl.0, 0);
+    assert_eq!(near_aum.pending_funding_usd.0, position.funding_fee.0);
+    assert_eq!(
+        near_aum.aum.0,
+        dollars(6600) - dollars(1200) + dollars(500) + position.funding_fee.0
+    );
+    assert_eq!(vcontract.get_asset_info(near_id()).aum.0, near_aum.aum.0);
+    assert_eq!(breakdown.total_aum.0, vcontract.get_total_aum().0);
+
+    // Pending funding is cleared once the position pays it
+    context.account_balance(near(1000));
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.decrease_position(DecreasePositionRequest {
+        position_id,
+        collateral_delta: 0.into(),
+        size_delta: dollars(1000).into(),
+        referrer_id: None,
+        output_token_id: None,
+        reduce_only: None,
+        close_position: None,
+    });
+    let breakdown = vcontract.get_aum_breakdown(None);
+    assert_eq!(
+        breakdown
+            .assets
+            .get(&near_id())
+            .unwrap()
+            .pending_funding_usd
+            .0,
+        0
+    );
+}
+
 proptest! {
     #[test]
     fn test_aum(
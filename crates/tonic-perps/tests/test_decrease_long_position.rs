@@ -41,6 +41,8 @@ fn test_close_position_with_profit() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -88,6 +90,8 @@ fn test_decrease_position_with_profit() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -136,6 +140,8 @@ fn test_close_position_with_loss() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -185,6 +191,8 @@ fn test_decrease_position_with_loss() {
         size_delta: U128(dollars(60)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -237,6 +245,8 @@ fn test_close_long_position_collateral_loss() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -281,6 +291,8 @@ fn test_close_long_position_with_fees() {
         size_delta: U128(dollars(60)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let asset_after = vcontract.get_asset_info("near".to_string());
@@ -341,6 +353,8 @@ fn test_reduce_only_state() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -376,6 +390,8 @@ fn test_decrease_position_wrong_account() {
         size_delta: U128(dollars(50)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -411,6 +427,8 @@ fn test_decrease_position_disabled_asset() {
         size_delta: U128(dollars(50)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -446,6 +464,8 @@ fn test_decrease_position_zero_delta() {
         size_delta: U128(dollars(0)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions_after = vcontract.get_positions(get_account(Admin));
@@ -489,6 +509,8 @@ fn test_decrease_position_min_margin_level() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -524,6 +546,8 @@ fn test_decrease_position_paused_contract_state() {
         size_delta: U128(dollars(90)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -557,6 +581,8 @@ fn test_decrease_position_below_min_leverage() {
         size_delta: U128(dollars(10)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -738,6 +764,8 @@ fn test_close_position_exceeded_collateral() {
         size_delta: U128(dollars(80)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -781,6 +809,8 @@ proptest! {
             size_delta: U128(decrease_position_size),
             referrer_id: None, output_token_id: None,
 
+            reduce_only: None,
+            close_position: None,
         });
 
         let user_positions_after = vcontract.get_positions(get_account(Admin));
@@ -819,6 +849,8 @@ proptest! {
             size_delta: U128(user_positions_after[0].size.0),
             referrer_id: None, output_token_id: None,
 
+            reduce_only: None,
+            close_position: None,
         });
 
         vcontract.remove_admin(get_account(Admin));
@@ -894,3 +926,76 @@ proptest! {
         vcontract.remove_admin(get_account(Admin));
     }
 }
+
+fn open_long_position(
+    context: &mut near_sdk::test_utils::VMContextBuilder,
+    vcontract: &mut VContract,
+) -> PositionId {
+    set_predecessor(context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    update_near_price(vcontract, dollars(5));
+
+    set_deposit(context, near(10));
+    vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(200).into(),
+        is_long: true,
+        referrer_id: None,
+    })
+}
+
+#[test]
+fn test_close_entire_long_position() {
+    let (mut context, mut vcontract) = setup();
+    let position_id = open_long_position(&mut context, &mut vcontract);
+
+    set_deposit(&mut context, 1);
+    vcontract.decrease_position(DecreasePositionRequest {
+        position_id,
+        collateral_delta: 0.into(),
+        size_delta: 0.into(),
+        referrer_id: None,
+        output_token_id: None,
+        reduce_only: None,
+        close_position: Some(true),
+    });
+    assert!(vcontract.get_positions(get_account(Admin)).is_empty());
+}
+
+#[test]
+fn test_reduce_only_decrease_long_position() {
+    let (mut context, mut vcontract) = setup();
+    let position_id = open_long_position(&mut context, &mut vcontract);
+
+    set_deposit(&mut context, 1);
+    vcontract.decrease_position(DecreasePositionRequest {
+        position_id,
+        collateral_delta: 0.into(),
+        size_delta: dollars(1000).into(),
+        referrer_id: None,
+        output_token_id: None,
+        reduce_only: Some(true),
+        close_position: None,
+    });
+    assert!(vcontract.get_positions(get_account(Admin)).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Can not decrease position by more than size")]
+fn test_decrease_long_position_more_than_size() {
+    let (mut context, mut vcontract) = setup();
+    let position_id = open_long_position(&mut context, &mut vcontract);
+
+    set_deposit(&mut context, 1);
+    vcontract.decrease_position(DecreasePositionRequest {
+        position_id,
+        collateral_delta: 0.into(),
+        size_delta: dollars(1000).into(),
+        referrer_id: None,
+        output_token_id: None,
+        reduce_only: None,
+        close_position: None,
+    });
+}
@@ -41,6 +41,8 @@ fn test_close_position_with_profit() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -95,6 +97,8 @@ fn test_decrease_position_with_profit() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -146,6 +150,8 @@ fn test_check_short_open_interest() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     vcontract.set_open_interest_limits(
@@ -202,6 +208,8 @@ fn test_close_position_with_loss() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -255,6 +263,8 @@ fn test_decrease_position_with_loss() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -308,6 +318,8 @@ fn test_decrease_position_no_usd_out() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let user_positions = vcontract.get_positions(get_account(Admin));
@@ -368,6 +380,8 @@ fn test_close_position_no_usd_out() {
         size_delta: U128(dollars(90)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -408,6 +422,8 @@ fn test_decrease_position_wrong_account() {
         size_delta: U128(dollars(50)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -445,6 +461,8 @@ fn test_decrease_position_zero_delta() {
         size_delta: U128(dollars(0)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -483,6 +501,8 @@ fn test_decrease_position_min_margin_level() {
         size_delta: U128(dollars(20)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 }
 
@@ -585,6 +605,8 @@ proptest! {
             size_delta: U128(decrease_position_size),
             referrer_id: None, output_token_id: None,
 
+            reduce_only: None,
+            close_position: None,
         });
 
         let user_positions = vcontract.get_positions(get_account(Admin));
@@ -619,6 +641,8 @@ proptest! {
             size_delta: U128(user_positions[0].size.0),
             referrer_id: None, output_token_id: None,
 
+            reduce_only: None,
+            close_position: None,
         });
 
         vcontract.remove_admin(get_account(Admin));
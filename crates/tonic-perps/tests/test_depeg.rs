@@ -0,0 +1,150 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn test_stable_aum_at_peg() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+
+    // 1% off peg is within the default band. The band only gates trading,
+    // the pool is still valued at the oracle price.
+    update_asset_price(&mut vcontract, usdc_id(), 990_000);
+    assert_eq!(vcontract.get_total_aum().0, dollars(990));
+    assert!(!vcontract.get_asset_info(usdc_id()).depegged);
+}
+
+#[test]
+fn test_stable_aum_depegged() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+
+    // 5% off peg is outside of the default band
+    update_asset_price(&mut vcontract, usdc_id(), 950_000);
+    assert_eq!(vcontract.get_total_aum().0, dollars(950));
+    assert!(vcontract.get_asset_info(usdc_id()).depegged);
+
+    // Widening the band allows trading again without changing the valuation
+    vcontract.set_depeg_band_bps(usdc_id(), 1000);
+    assert!(!vcontract.get_asset_info(usdc_id()).depegged);
+    assert_eq!(vcontract.get_total_aum().0, dollars(950));
+}
+
+#[test]
+#[should_panic(expected = "Stablecoin is outside of its peg band")]
+fn test_depegged_stable_mint_lp() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_asset_price(&mut vcontract, usdc_id(), 950_000);
+
+    vcontract.contract_mut().mint_lp_token(
+        &get_account(Alice),
+        &AssetId::from(usdc_id()),
+        usdc(100),
+        None,
+    );
+}
+
+#[test]
+#[should_panic(expected = "Stablecoin is outside of its peg band")]
+fn test_depegged_stable_swap_in() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, usdc_id(), 1_050_000);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(100));
+
+    let _ =
+        vcontract
+            .contract_mut()
+            .swap(&AssetId::from(usdc_id()), &AssetId::NEAR, usdc(100), None);
+}
+
+#[test]
+fn test_depegged_stable_swap_out() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, usdc_id(), 950_000);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+
+    // Swapping out of a depegged stablecoin is still allowed
+    let amount_out =
+        vcontract
+            .contract_mut()
+            .swap(&AssetId::NEAR, &AssetId::from(usdc_id()), near(1), None);
+    assert!(amount_out > usdc(5));
+}
+
+#[test]
+#[should_panic(expected = "Stablecoin is outside of its peg band")]
+fn test_depegged_stable_short_collateral() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, usdc_id(), 950_000);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+
+    let _ = vcontract.contract_mut().increase_position(
+        &get_account(Alice),
+        &AssetId::from(usdc_id()),
+        &AssetId::NEAR,
+        usdc(10),
+        dollars(20),
+        false,
+        None,
+    );
+}
+
+#[test]
+#[should_panic(expected = "Asset price is too stale")]
+fn test_stable_price_staleness() {
+    let (mut context, vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(91).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+
+    vcontract
+        .contract()
+        .validate_asset_price(&AssetId::from(usdc_id()));
+}
+
+#[test]
+#[should_panic(expected = "Asset price is too stale")]
+fn test_per_asset_staleness() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_asset_max_staleness_duration_sec(near_id(), Some(10));
+
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(11).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+
+    // Contract-wide threshold still applies to usdc
+    vcontract
+        .contract()
+        .validate_asset_price(&AssetId::from(usdc_id()));
+    vcontract.contract().validate_asset_price(&AssetId::NEAR);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::*;
+use near_sdk::test_utils::get_logs;
+
+fn setup_fee_split(split: FeeSplit) -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 100,
+        swap_fee_bps: 0,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+    vcontract.set_fee_split_parameters(FeeSplitParameters {
+        mint: split,
+        ..Default::default()
+    });
+
+    // 1 NEAR of fees
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    set_predecessor(&mut context, Admin);
+    (context, vcontract)
+}
+
+#[test]
+fn test_fee_split_treasury() {
+    let (_context, vcontract) = setup_fee_split(FeeSplit::default());
+
+    let asset = vcontract.get_asset_info(near_id());
+    assert_eq!(asset.accumulated_fees.0, near(1));
+    assert_eq!(asset.pool_amount.0, near(99));
+    assert_eq!(vcontract.get_lp_price().0, dollars(1));
+}
+
+#[test]
+fn test_fee_split_lp() {
+    let (_context, vcontract) = setup_fee_split(FeeSplit {
+        treasury_bps: 5000,
+        lp_bps: 5000,
+        staking_bps: 0,
+    });
+
+    let asset = vcontract.get_asset_info(near_id());
+    assert_eq!(asset.accumulated_fees.0, near(1) / 2);
+    assert_eq!(asset.pool_amount.0, near(99) + near(1) / 2);
+    // Fees added to the pool raise the LP token price
+    assert!(vcontract.get_lp_price().0 > dollars(1));
+}
+
+#[test]
+fn test_fee_split_events() {
+    let (mut context, mut vcontract) = setup_fee_split(FeeSplit {
+        treasury_bps: 2000,
+        lp_bps: 3000,
+        staking_bps: 5000,
+    });
+
+    // Nothing is staked yet, the staking share goes to the treasury
+    let asset = vcontract.get_asset_info(near_id());
+    assert_eq!(asset.accumulated_fees.0, near(1) * 7 / 10);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.stake_lp(lp_tokens(100.).into());
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    let logs = get_logs();
+    for destination in ["treasury", "pool", "staking"] {
+        assert!(logs.iter().any(|log| log.contains("EditFees")
+            && log.contains(&format!("\"destination\":\"{}\"", destination))));
+    }
+}
+
+#[test]
+fn test_withdraw_fees_to_treasury() {
+    let (mut context, mut vcontract) = setup_fee_split(FeeSplit::default());
+    assert_eq!(vcontract.get_treasury_id(), get_account(Admin));
+    vcontract.set_treasury_id(get_account(Bob));
+    vcontract.add_admin(get_account(Alice), AdminRole::FullAdmin);
+
+    set_predecessor(&mut context, Alice);
+    vcontract.withdraw_fees(Some(vec![near_id()]));
+    assert_eq!(vcontract.get_asset_info(near_id()).accumulated_fees.0, 0);
+
+    let bob = format!("\"account_id\":\"{}\"", get_account(Bob));
+    assert!(get_logs()
+        .iter()
+        .any(|log| log.contains("EditFees") && log.contains(&bob)));
+}
+
+#[test]
+#[should_panic(expected = "caller must be owner")]
+fn test_set_treasury_id_not_owner() {
+    let (mut context, mut vcontract) = setup_fee_split(FeeSplit::default());
+    vcontract.add_admin(get_account(Bob), AdminRole::FullAdmin);
+
+    set_predecessor(&mut context, Bob);
+    vcontract.set_treasury_id(get_account(Bob));
+}
+
+#[test]
+#[should_panic(expected = "validator_result")]
+fn test_invalid_fee_split() {
+    setup_fee_split(FeeSplit {
+        treasury_bps: 5000,
+        lp_bps: 5000,
+        staking_bps: 5000,
+    });
+}
@@ -4,6 +4,7 @@ use common::*;
 use near_contract_standards::fungible_token::{
     core::FungibleTokenCore, receiver::FungibleTokenReceiver,
 };
+use near_contract_standards::storage_management::StorageManagement;
 
 #[test]
 fn test_mint_burn_tokens() {
@@ -108,3 +109,112 @@ fn test_transfer_to_not_registered_account() {
     set_deposit(&mut context, 1);
     vcontract.ft_transfer(get_account(Bob), U128(10000000), None);
 }
+
+fn setup_paid_storage() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_lp_free_storage(false);
+
+    let min = vcontract.storage_balance_bounds().min.0;
+    set_deposit(&mut context, min);
+    vcontract.storage_deposit(None, None);
+    (context, vcontract)
+}
+
+#[test]
+fn test_storage_deposit() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    let bounds = vcontract.storage_balance_bounds();
+    assert!(bounds.min.0 > 0);
+    assert_eq!(bounds.max.unwrap(), bounds.min);
+    assert!(vcontract.storage_balance_of(get_account(Bob)).is_none());
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, bounds.min.0 + 1000);
+    let storage_balance = vcontract.storage_deposit(None, None);
+    assert_eq!(storage_balance.total, bounds.min);
+    assert_eq!(storage_balance.available.0, 0);
+    assert_eq!(
+        vcontract
+            .storage_balance_of(get_account(Bob))
+            .unwrap()
+            .total,
+        bounds.min
+    );
+
+    // Nothing to withdraw above the minimum balance
+    set_deposit(&mut context, 1);
+    assert_eq!(vcontract.storage_withdraw(None).total, bounds.min);
+
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+    assert_eq!(vcontract.ft_balance_of(get_account(Bob)).0, lp_tokens(50.0));
+}
+
+#[test]
+#[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+fn test_storage_deposit_too_low() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    let min = vcontract.storage_balance_bounds().min.0;
+    set_deposit(&mut context, min - 1);
+    vcontract.storage_deposit(Some(get_account(Bob)), None);
+}
+
+#[test]
+#[should_panic(expected = "The account bob is not registered")]
+fn test_mint_not_registered() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+}
+
+#[test]
+#[should_panic(expected = "The account bob is not registered")]
+fn test_transfer_not_registered() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+
+    // Existing holders are registered, but new receivers have to pay
+    set_deposit(&mut context, 1);
+    vcontract.ft_transfer(get_account(Bob), U128(lp_tokens(1.0)), None);
+}
+
+#[test]
+fn test_storage_unregister() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    let min = vcontract.storage_balance_bounds().min.0;
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, min);
+    vcontract.storage_deposit(None, None);
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, 1);
+    vcontract.ft_transfer(get_account(Bob), U128(lp_tokens(1.0)), None);
+
+    // Dust is burned with force
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    assert!(vcontract.storage_unregister(Some(true)));
+    assert!(vcontract.storage_balance_of(get_account(Bob)).is_none());
+    assert_eq!(vcontract.ft_balance_of(get_account(Bob)).0, 0);
+    assert_eq!(vcontract.ft_total_supply().0, lp_tokens(49.0));
+
+    // Not registered anymore
+    assert!(!vcontract.storage_unregister(None));
+}
+
+#[test]
+#[should_panic(expected = "Can't unregister the account with the positive balance without force")]
+fn test_storage_unregister_with_balance() {
+    let (mut context, mut vcontract) = setup_paid_storage();
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+
+    set_deposit(&mut context, 1);
+    vcontract.storage_unregister(None);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::*;
+
+fn setup_keeper_rewards() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 100,
+        swap_fee_bps: 0,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+    vcontract.set_keeper_reward_parameters(KeeperRewardParameters {
+        reward_usd: dollars(1),
+        min_staleness_sec: 60,
+        epoch_sec: 60 * 60,
+        epoch_cap_usd: dollars(2),
+    });
+    vcontract.add_price_oracle(get_account(Bob));
+
+    // 1 NEAR of fees
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    set_predecessor(&mut context, Bob);
+    (context, vcontract)
+}
+
+#[test]
+fn test_keeper_reward() {
+    let (mut context, mut vcontract) = setup_keeper_rewards();
+
+    // Price is not stale enough
+    advance_time(&mut context, 30);
+    update_near_price(&mut vcontract, dollars(5));
+    assert_eq!(
+        vcontract
+            .get_keeper_rewards(get_account(Bob))
+            .total_earned_usd
+            .0,
+        0
+    );
+
+    advance_time(&mut context, 61);
+    update_near_price(&mut vcontract, dollars(5));
+
+    let rewards = vcontract.get_keeper_rewards(get_account(Bob));
+    assert_eq!(rewards.total_earned_usd.0, dollars(1));
+    assert_eq!(rewards.claimable.get(&near_id()).unwrap().0, near(1) / 5);
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).accumulated_fees.0,
+        near(1) - near(1) / 5
+    );
+}
+
+#[test]
+fn test_keeper_reward_epoch_cap() {
+    let (mut context, mut vcontract) = setup_keeper_rewards();
+
+    for _ in 0..3 {
+        advance_time(&mut context, 61);
+        update_near_price(&mut vcontract, dollars(5));
+    }
+    assert_eq!(
+        vcontract
+            .get_keeper_rewards(get_account(Bob))
+            .total_earned_usd
+            .0,
+        dollars(2)
+    );
+
+    // Cap resets in the next epoch
+    advance_time(&mut context, 60 * 60);
+    update_near_price(&mut vcontract, dollars(5));
+    assert_eq!(
+        vcontract
+            .get_keeper_rewards(get_account(Bob))
+            .total_earned_usd
+            .0,
+        dollars(3)
+    );
+}
+
+#[test]
+fn test_claim_keeper_rewards() {
+    let (mut context, mut vcontract) = setup_keeper_rewards();
+
+    advance_time(&mut context, 61);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.claim_keeper_rewards();
+
+    let rewards = vcontract.get_keeper_rewards(get_account(Bob));
+    assert!(rewards.claimable.is_empty());
+    assert_eq!(rewards.total_earned_usd.0, dollars(1));
+}
+
+#[test]
+fn test_no_keeper_reward_for_switchboard() {
+    let (mut context, mut vcontract) = setup_keeper_rewards();
+
+    advance_time(&mut context, 61);
+    set_predecessor(&mut context, Alice);
+    context.signer_account_id(get_account(Alice));
+    testing_env!(context.build());
+    vcontract.query_switchboard_callback(
+        Ok(sbv2_near::AggregatorRound {
+            result: sbv2_near::SwitchboardDecimal {
+                mantissa: 5,
+                scale: 0,
+            },
+            round_open_timestamp: near_sdk::env::block_timestamp_ms() / 1000,
+            ..Default::default()
+        }),
+        &AssetId::NEAR,
+    );
+
+    assert_eq!(
+        vcontract
+            .get_keeper_rewards(get_account(Alice))
+            .total_earned_usd
+            .0,
+        0
+    );
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).accumulated_fees.0,
+        near(1)
+    );
+}
@@ -4,6 +4,7 @@ use common::*;
 
 use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 use near_sdk::env;
+use tonic_perps_sdk::prelude::RemoveOrderReason;
 
 #[test]
 fn test_limit_orders_get() {
@@ -26,6 +27,11 @@ fn test_limit_orders_get() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     let id = LimitOrderId::new(&order, 1);
 
@@ -41,6 +47,11 @@ fn test_limit_orders_get() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -56,6 +67,11 @@ fn test_limit_orders_get() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -90,6 +106,11 @@ fn test_limit_orders_get_range() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -105,6 +126,11 @@ fn test_limit_orders_get_range() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -120,6 +146,11 @@ fn test_limit_orders_get_range() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -152,6 +183,11 @@ fn test_limit_orders_get_higher() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -167,6 +203,11 @@ fn test_limit_orders_get_higher() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -182,6 +223,11 @@ fn test_limit_orders_get_higher() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -212,6 +258,11 @@ fn test_limit_orders_get_lower() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -227,6 +278,11 @@ fn test_limit_orders_get_lower() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -242,6 +298,11 @@ fn test_limit_orders_get_lower() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     limit_orders.insert(get_next(&limit_order), limit_order);
 
@@ -272,6 +333,9 @@ fn test_limit_orders_execute() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     let limit_order_id = vcontract.add_limit_order(params.clone());
 
@@ -312,6 +376,9 @@ fn test_limit_orders_same_order_type() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     vcontract.add_limit_order(params.clone());
 
@@ -324,6 +391,9 @@ fn test_limit_orders_same_order_type() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     vcontract.add_limit_order(params.clone());
 }
@@ -350,6 +420,9 @@ fn test_limit_orders_same_order_type_different_threshold() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     vcontract.add_limit_order(params.clone());
 
@@ -362,6 +435,9 @@ fn test_limit_orders_same_order_type_different_threshold() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     vcontract.add_limit_order(params.clone());
 }
@@ -389,6 +465,9 @@ fn test_limit_orders_execute_not_ready_below() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     let limit_order_id = vcontract.add_limit_order(params.clone());
 
@@ -420,6 +499,9 @@ fn test_limit_orders_execute_not_ready_above() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     };
     let limit_order_id = vcontract.add_limit_order(params.clone());
 
@@ -450,6 +532,9 @@ fn test_limit_orders_remove_outdated() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     context.block_timestamp(u64::MAX);
@@ -485,6 +570,9 @@ fn test_limit_orders_remove_not_outdated() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, 1);
@@ -514,6 +602,9 @@ fn test_remove_limit_orders() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, 1);
@@ -545,6 +636,9 @@ fn test_remove_another_limit_orders() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Alice);
@@ -576,6 +670,9 @@ fn test_remove_another_limit_orders_2() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Alice);
@@ -588,6 +685,9 @@ fn test_remove_another_limit_orders_2() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, 1);
@@ -621,6 +721,9 @@ fn test_limit_orders_ft_transfer() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -660,6 +763,9 @@ fn test_limit_orders_merge_buy() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, near(10));
@@ -672,6 +778,9 @@ fn test_limit_orders_merge_buy() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     let limit_orders = vcontract.get_limit_orders(near_id());
@@ -712,6 +821,9 @@ fn test_limit_orders_merge_sell() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(10).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     vcontract.add_limit_order(LimitOrderParameters {
@@ -723,6 +835,9 @@ fn test_limit_orders_merge_sell() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(10).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     let limit_orders = vcontract.get_limit_orders(near_id());
@@ -765,6 +880,9 @@ fn test_limit_order_with_no_changes() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(0).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -799,6 +917,9 @@ fn test_limit_orders_zero_deltas() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Zero collateral delta
@@ -812,6 +933,9 @@ fn test_limit_orders_zero_deltas() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -855,6 +979,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, 0);
@@ -867,6 +994,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, near(10));
@@ -879,6 +1009,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, 0);
@@ -891,6 +1024,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Add two positions with above/below threshold type
@@ -906,6 +1042,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, near(10));
@@ -918,6 +1057,9 @@ fn test_limit_orders_get_eligible() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Admin);
@@ -960,6 +1102,9 @@ fn test_update_limit_orders_after_decrease() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Order to increase position if price goes up.
@@ -974,6 +1119,9 @@ fn test_update_limit_orders_after_decrease() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Order to create short position. Should remain.
@@ -990,6 +1138,9 @@ fn test_update_limit_orders_after_decrease() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1040,6 +1191,9 @@ fn test_update_limit_orders_after_increase() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, near(0));
@@ -1055,6 +1209,9 @@ fn test_update_limit_orders_after_increase() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(10).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Order to create short position. Should remain.
@@ -1071,6 +1228,9 @@ fn test_update_limit_orders_after_increase() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1123,6 +1283,9 @@ fn test_update_limit_orders_after_liquidate() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Order to increase position if price goes up.
@@ -1137,6 +1300,9 @@ fn test_update_limit_orders_after_liquidate() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // Order to create short position. Should remain.
@@ -1153,6 +1319,9 @@ fn test_update_limit_orders_after_liquidate() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1207,6 +1376,9 @@ fn test_add_decrease_limit_order_decrease_state() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -1235,6 +1407,9 @@ fn test_add_increase_limit_order_decrease_state() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -1271,6 +1446,9 @@ fn test_add_decrease_limit_order_disabled_state() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -1303,6 +1481,9 @@ fn test_ft_transfer_order_in_decrease_state() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1332,6 +1513,11 @@ fn test_is_eligible() {
         expiry: env::block_timestamp_ms() + 1000,
         order_type: OrderType::Increase,
         threshold: ThresholdType::Below,
+        output_id: None,
+        reduce_only: false,
+        close_position: false,
+        execution_fee: 0,
+        storage_usage: 0,
     };
     assert!(vcontract.contract_mut().limit_order_is_eligible(&order));
     order.price = dollars(4);
@@ -1376,6 +1562,9 @@ fn test_remove_invalid_limit_order_losses_exceed() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     let user_orders = vcontract.get_user_limit_orders(&get_account(Admin));
@@ -1434,6 +1623,9 @@ fn test_remove_invalid_limit_order_max_leverage_exceed() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     let user_orders = vcontract.get_user_limit_orders(&get_account(Admin));
@@ -1494,6 +1686,9 @@ fn test_order_to_close_position_with_huge_losses() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -1531,6 +1726,9 @@ fn test_order_to_decrease_position_with_huge_losses() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(20).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 }
 
@@ -1560,6 +1758,9 @@ fn test_do_not_merge_different_limit_orders() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1582,6 +1783,9 @@ fn test_do_not_merge_different_limit_orders() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1618,6 +1822,9 @@ fn test_stable_token_for_long_position() {
             expiry: None,
             order_type: OrderType::Increase,
             collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
         }))
         .unwrap(),
     );
@@ -1658,6 +1865,9 @@ fn test_increase_with_zero_collateral_after_closure() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(dollars(50).into()),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // This one should increase size delta, so limit order to close the position would
@@ -1671,6 +1881,9 @@ fn test_increase_with_zero_collateral_after_closure() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Admin);
@@ -1720,6 +1933,9 @@ fn test_close_order_after_increase_collateral() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_deposit(&mut context, near(2));
@@ -1733,6 +1949,9 @@ fn test_close_order_after_increase_collateral() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Admin);
@@ -1793,6 +2012,9 @@ fn test_close_order_after_decrease_collateral() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     // This one should decrease collateral
@@ -1805,6 +2027,9 @@ fn test_close_order_after_decrease_collateral() {
         expiry: None,
         order_type: OrderType::Decrease,
         collateral_delta: Some(U128(10)),
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     set_predecessor(&mut context, Admin);
@@ -1864,6 +2089,9 @@ fn test_get_collateral_after_removing_order_in_increase() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     let (_, transfer_info) = vcontract.contract_mut().increase_position(
@@ -1913,6 +2141,9 @@ fn test_get_collateral_after_removing_order_in_decrease() {
         expiry: None,
         order_type: OrderType::Increase,
         collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
     });
 
     update_near_price(&mut vcontract, dollars(6));
@@ -1931,3 +2162,783 @@ fn test_get_collateral_after_removing_order_in_decrease() {
     assert_eq!(transfer_info.receiver_id(), get_account(Admin));
     assert_eq!(transfer_info.asset_id(), AssetId::from(near_id()));
 }
+
+fn setup_swap_orders() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+    update_near_price(&mut vcontract, dollars(5));
+    (context, vcontract)
+}
+
+#[test]
+fn test_swap_limit_order_execute() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    // Sell 10 NEAR once NEAR is worth 6 USDC
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(10));
+    let limit_order_id = vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+    let order = &vcontract.get_user_limit_orders(&get_account(Bob))[0];
+    assert_eq!(order.order_type, OrderType::Swap);
+    assert_eq!(order.threshold, ThresholdType::Above);
+    assert_eq!(order.output_id, Some(usdc_id()));
+    assert!(vcontract.get_eligible_orders(near_id(), None).is_empty());
+
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(6));
+    assert_eq!(
+        vcontract.get_eligible_orders(near_id(), None),
+        vec![limit_order_id]
+    );
+
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), limit_order_id);
+    assert!(vcontract.get_limit_orders(near_id()).is_empty());
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1010)
+    );
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(5000) - dollars(60)
+    );
+}
+
+#[test]
+#[should_panic(expected = "Swap order can not be filled at its price")]
+fn test_swap_limit_order_below_price_after_fees() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 0,
+        swap_fee_bps: 30,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(10));
+    let limit_order_id = vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+
+    // 10 NEAR give less than 60 USDC after fees, the order keeps resting
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(6));
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), limit_order_id);
+}
+
+#[test]
+#[should_panic(expected = "Position is not ready to be executed")]
+fn test_swap_limit_order_not_ready() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    set_deposit(&mut context, near(10));
+    let limit_order_id = vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+
+    update_near_price(&mut vcontract, dollars(5) + dollars(1) / 2);
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), limit_order_id);
+}
+
+#[test]
+fn test_swap_limit_order_ft_transfer() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    // Buy NEAR with 100 USDC once NEAR drops to $4, i.e. 0.25 NEAR per USDC
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        dollars(100).into(),
+        serde_json::to_string(&Action::PlaceSwapLimitOrder(SwapLimitOrderParameters {
+            output_token_id: near_id(),
+            price: (dollars(1) / 4).into(),
+            expiry: None,
+            execution_fee: None,
+        }))
+        .unwrap(),
+    );
+
+    let orders = vcontract.get_limit_orders(usdc_id());
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].attached_collateral.0, dollars(100));
+    assert_eq!(orders[0].collateral_id, usdc_id());
+
+    // Perps orders on NEAR are not affected by swap orders from USDC
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(4));
+    assert!(vcontract.get_eligible_orders(near_id(), None).is_empty());
+    let eligible = vcontract.get_eligible_orders(usdc_id(), None);
+    assert_eq!(eligible.len(), 1);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    let limit_order_id = eligible[0];
+    vcontract.remove_limit_order(limit_order_id);
+    assert!(vcontract.get_limit_orders(usdc_id()).is_empty());
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(5000)
+    );
+}
+
+#[test]
+fn test_swap_limit_order_expired() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    set_deposit(&mut context, near(10));
+    let limit_order_id = vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+
+    context.block_timestamp(u64::MAX);
+    testing_env!(context.build());
+    set_deposit(&mut context, 1);
+    vcontract.remove_outdated_limit_order(near_id(), limit_order_id);
+    assert!(vcontract.get_limit_orders(near_id()).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Swap tokens should be different")]
+fn test_swap_limit_order_same_token() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    set_deposit(&mut context, near(10));
+    vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: near_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+}
+
+fn setup_update_limit_order() -> (
+    near_sdk::test_utils::VMContextBuilder,
+    VContract,
+    LimitOrderId,
+) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), dollars(1000));
+    update_near_price(&mut vcontract, dollars(5));
+
+    set_deposit(&mut context, near(10));
+    let limit_order_id = vcontract.add_limit_order(LimitOrderParameters {
+        price: dollars(4).into(),
+        size_delta: dollars(80).into(),
+        underlying_id: near_id(),
+        collateral_id: None,
+        is_long: true,
+        expiry: None,
+        order_type: OrderType::Increase,
+        collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: None,
+    });
+    (context, vcontract, limit_order_id)
+}
+
+#[test]
+fn test_update_limit_order() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_deposit(&mut context, 1);
+    let new_id = vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: Some((dollars(9) / 2).into()),
+        size_delta: Some(dollars(45).into()),
+        collateral_delta: None,
+        top_up: None,
+        withdraw: Some(near(2).into()),
+        expiry: None,
+        execution_fee: None,
+    });
+    assert_ne!(new_id, limit_order_id);
+
+    let limit_orders = vcontract.get_user_limit_orders(&get_account(Admin));
+    assert_eq!(limit_orders.len(), 1);
+    let order = &limit_orders[0];
+    assert_eq!(order.id, new_id.to_string());
+    assert_eq!(order.price.0, dollars(9) / 2);
+    assert_eq!(order.size_delta.0, dollars(45));
+    assert_eq!(order.attached_collateral.0, near(8));
+    assert_eq!(order.threshold, ThresholdType::Below);
+
+    update_near_price(&mut vcontract, dollars(9) / 2);
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), new_id);
+    let position = vcontract.get_positions(get_account(Admin)).pop().unwrap();
+    assert_eq!(position.size.0, dollars(45));
+    assert_eq!(position.collateral.0, dollars(36));
+}
+
+#[test]
+fn test_update_limit_order_top_up() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_deposit(&mut context, near(5));
+    vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: None,
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: None,
+        expiry: None,
+        execution_fee: None,
+    });
+    let limit_orders = vcontract.get_limit_orders(near_id());
+    assert_eq!(limit_orders.len(), 1);
+    assert_eq!(limit_orders[0].attached_collateral.0, near(15));
+    assert_eq!(limit_orders[0].price.0, dollars(4));
+}
+
+#[test]
+fn test_update_limit_order_threshold() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_deposit(&mut context, 1);
+    let new_id = vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: Some(dollars(6).into()),
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: None,
+        expiry: None,
+        execution_fee: None,
+    });
+    let order = vcontract
+        .get_user_limit_orders(&get_account(Admin))
+        .pop()
+        .unwrap();
+    assert_eq!(order.threshold, ThresholdType::Above);
+    assert!(vcontract.get_eligible_orders(near_id(), None).is_empty());
+
+    update_near_price(&mut vcontract, dollars(6));
+    assert_eq!(vcontract.get_eligible_orders(near_id(), None), vec![new_id]);
+}
+
+#[test]
+#[should_panic(expected = "Not enough collateral attached")]
+fn test_update_limit_order_collateral_too_high() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_deposit(&mut context, near(5));
+    vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: None,
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: Some(near(16).into()),
+        expiry: None,
+        execution_fee: None,
+    });
+}
+
+#[test]
+#[should_panic(expected = "You do not have any orders")]
+fn test_update_another_limit_order() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: Some(dollars(3).into()),
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: None,
+        expiry: None,
+        execution_fee: None,
+    });
+}
+
+#[test]
+#[should_panic(expected = "Tokens must be attached in the collateral of the order")]
+fn test_update_limit_order_wrong_token() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Admin),
+        dollars(10).into(),
+        serde_json::to_string(&Action::UpdateLimitOrder(UpdateLimitOrderParameters {
+            limit_order_id,
+            price: None,
+            size_delta: None,
+            collateral_delta: None,
+            top_up: None,
+            withdraw: None,
+            expiry: None,
+            execution_fee: None,
+        }))
+        .unwrap(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+fn test_update_limit_order_requires_deposit() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    set_deposit(&mut context, 0);
+    vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: None,
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: Some(near(10).into()),
+        expiry: None,
+        execution_fee: None,
+    });
+}
+
+#[test]
+fn test_update_limit_order_partial_top_up() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+
+    // 2 NEAR top up the collateral, 1 NEAR raises the execution fee and the
+    // rest is refunded
+    set_deposit(&mut context, near(5));
+    let (_, transfer_info) = vcontract.contract_mut().update_limit_order(
+        &get_account(Admin),
+        UpdateLimitOrderParameters {
+            limit_order_id,
+            price: None,
+            size_delta: None,
+            collateral_delta: None,
+            top_up: Some(near(2).into()),
+            withdraw: None,
+            expiry: None,
+            execution_fee: Some(near(1).into()),
+        },
+        &AssetId::NEAR,
+        near(5),
+    );
+    assert_eq!(transfer_info.amount(), near(2));
+    let limit_orders = vcontract.get_limit_orders(near_id());
+    assert_eq!(limit_orders[0].attached_collateral.0, near(12));
+    assert_eq!(limit_orders[0].execution_fee.0, near(1));
+}
+
+#[test]
+fn test_update_limit_order_lower_execution_fee() {
+    let (mut context, mut vcontract, limit_order_id) = setup_update_limit_order();
+    set_deposit(&mut context, near(1));
+    let (limit_order_id, _) = vcontract.contract_mut().update_limit_order(
+        &get_account(Admin),
+        UpdateLimitOrderParameters {
+            limit_order_id,
+            price: None,
+            size_delta: None,
+            collateral_delta: None,
+            top_up: None,
+            withdraw: None,
+            expiry: None,
+            execution_fee: Some(near(1).into()),
+        },
+        &AssetId::NEAR,
+        near(1),
+    );
+
+    let (_, transfer_info) = vcontract.contract_mut().update_limit_order(
+        &get_account(Admin),
+        UpdateLimitOrderParameters {
+            limit_order_id,
+            price: None,
+            size_delta: None,
+            collateral_delta: None,
+            top_up: None,
+            withdraw: None,
+            expiry: None,
+            execution_fee: Some(0.into()),
+        },
+        &AssetId::NEAR,
+        0,
+    );
+    assert_eq!(transfer_info.amount(), near(1));
+    let limit_orders = vcontract.get_limit_orders(near_id());
+    assert_eq!(limit_orders[0].attached_collateral.0, near(10));
+    assert_eq!(limit_orders[0].execution_fee.0, 0);
+}
+
+fn setup_decrease_order(
+    reduce_only: bool,
+    close_position: bool,
+    size_delta: DollarBalance,
+) -> (
+    near_sdk::test_utils::VMContextBuilder,
+    VContract,
+    PositionId,
+) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    update_near_price(&mut vcontract, dollars(5));
+
+    // $200 long with $50 of collateral
+    set_deposit(&mut context, near(10));
+    let position_id = vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(200).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+
+    set_deposit(&mut context, 0);
+    vcontract.add_limit_order(LimitOrderParameters {
+        price: dollars(4).into(),
+        size_delta: size_delta.into(),
+        underlying_id: near_id(),
+        collateral_id: Some(near_id()),
+        is_long: true,
+        expiry: None,
+        order_type: OrderType::Decrease,
+        collateral_delta: None,
+        reduce_only: Some(reduce_only),
+        close_position: Some(close_position),
+        execution_fee: None,
+    });
+    (context, vcontract, position_id)
+}
+
+#[test]
+fn test_reduce_only_order_is_capped() {
+    let (mut context, mut vcontract, position_id) = setup_decrease_order(true, false, dollars(150));
+
+    // A plain order would be invalidated by this decrease
+    set_deposit(&mut context, 1);
+    vcontract.decrease_position(DecreasePositionRequest {
+        position_id,
+        collateral_delta: 0.into(),
+        size_delta: dollars(100).into(),
+        referrer_id: None,
+        output_token_id: None,
+        reduce_only: None,
+        close_position: None,
+    });
+    let user_orders = vcontract.get_user_limit_orders(&get_account(Admin));
+    assert_eq!(user_orders.len(), 1);
+    assert!(user_orders[0].reduce_only);
+
+    update_near_price(&mut vcontract, dollars(4));
+    vcontract.execute_limit_order(near_id(), vcontract.get_eligible_orders(near_id(), None)[0]);
+    assert!(vcontract.get_positions(get_account(Admin)).is_empty());
+    assert!(vcontract
+        .get_user_limit_orders(&get_account(Admin))
+        .is_empty());
+}
+
+#[test]
+fn test_close_position_order() {
+    let (mut context, mut vcontract, _) = setup_decrease_order(false, true, 0);
+
+    // The order closes the position whatever its size
+    set_deposit(&mut context, near(10));
+    vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(100).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+    assert_eq!(
+        vcontract.get_user_limit_orders(&get_account(Admin)).len(),
+        1
+    );
+
+    update_near_price(&mut vcontract, dollars(4));
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), vcontract.get_eligible_orders(near_id(), None)[0]);
+    assert!(vcontract.get_positions(get_account(Admin)).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Reduce-only and close-position flags are only valid on sell orders")]
+fn test_reduce_only_increase_order() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    update_near_price(&mut vcontract, dollars(5));
+
+    set_deposit(&mut context, near(10));
+    vcontract.add_limit_order(LimitOrderParameters {
+        price: dollars(4).into(),
+        size_delta: dollars(100).into(),
+        underlying_id: near_id(),
+        collateral_id: None,
+        is_long: true,
+        expiry: None,
+        order_type: OrderType::Increase,
+        collateral_delta: None,
+        reduce_only: Some(true),
+        close_position: None,
+        execution_fee: None,
+    });
+}
+
+fn setup_execution_fee_order(
+    order_type: OrderType,
+    deposit: near_sdk::Balance,
+) -> (
+    near_sdk::test_utils::VMContextBuilder,
+    VContract,
+    LimitOrderId,
+) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    update_near_price(&mut vcontract, dollars(5));
+
+    let is_decrease = matches!(order_type, OrderType::Decrease);
+    if is_decrease {
+        set_deposit(&mut context, near(10));
+        vcontract.increase_position(IncreasePositionRequest {
+            underlying_id: near_id(),
+            size_delta: dollars(200).into(),
+            is_long: true,
+            referrer_id: None,
+        });
+    }
+
+    // 1 NEAR of the deposit pays the execution fee. Take profit on sell
+    // orders, buy the dip on buy orders.
+    set_deposit(&mut context, deposit);
+    let limit_order_id = vcontract.add_limit_order(LimitOrderParameters {
+        price: dollars(if is_decrease { 6 } else { 4 }).into(),
+        size_delta: dollars(80).into(),
+        underlying_id: near_id(),
+        collateral_id: is_decrease.then(near_id),
+        is_long: true,
+        expiry: None,
+        order_type,
+        collateral_delta: None,
+        reduce_only: None,
+        close_position: None,
+        execution_fee: Some(near(1).into()),
+    });
+    (context, vcontract, limit_order_id)
+}
+
+#[test]
+fn test_limit_order_execution_fee_paid_to_keeper() {
+    let (mut context, mut vcontract, _) = setup_execution_fee_order(OrderType::Increase, near(11));
+    let order = &vcontract.get_user_limit_orders(&get_account(Admin))[0];
+    assert_eq!(order.attached_collateral.0, near(10));
+    assert_eq!(order.execution_fee.0, near(1));
+
+    update_near_price(&mut vcontract, dollars(4));
+    let limit_order_id = vcontract.get_eligible_orders(near_id(), None)[0];
+    set_predecessor(&mut context, Alice);
+    let transfers = vcontract
+        .contract_mut()
+        .execute_limit_order(&AssetId::NEAR, &limit_order_id);
+    let keeper_fee = transfers.last().unwrap();
+    assert_eq!(keeper_fee.receiver_id(), get_account(Alice));
+    assert_eq!(keeper_fee.asset_id(), AssetId::NEAR);
+    assert_eq!(keeper_fee.amount(), near(1));
+    assert_eq!(vcontract.get_positions(get_account(Admin)).len(), 1);
+}
+
+#[test]
+fn test_remove_limit_order_refunds_execution_fee() {
+    let (_, mut vcontract, limit_order_id) =
+        setup_execution_fee_order(OrderType::Increase, near(11));
+
+    let transfer_info = vcontract.contract_mut().remove_limit_order(
+        &get_account(Admin),
+        &limit_order_id,
+        RemoveOrderReason::Removed,
+    );
+    assert_eq!(transfer_info.receiver_id(), get_account(Admin));
+    assert_eq!(transfer_info.amount(), near(11));
+}
+
+#[test]
+fn test_outdated_limit_order_pays_execution_fee() {
+    let (mut context, mut vcontract, limit_order_id) =
+        setup_execution_fee_order(OrderType::Increase, near(11));
+
+    context.block_timestamp(u64::MAX);
+    set_predecessor(&mut context, Alice);
+    let transfers = vcontract
+        .contract_mut()
+        .remove_outdated_limit_order(&AssetId::NEAR, &limit_order_id);
+    assert_eq!(transfers[0].receiver_id(), get_account(Admin));
+    assert_eq!(transfers[0].amount(), near(10));
+    assert_eq!(transfers[1].receiver_id(), get_account(Alice));
+    assert_eq!(transfers[1].amount(), near(1));
+}
+
+#[test]
+fn test_permissionless_decrease_order_execution() {
+    let (mut context, mut vcontract, _) = setup_execution_fee_order(OrderType::Decrease, near(1));
+    vcontract.set_permissionless_limit_order_execution(true);
+    let order = &vcontract.get_user_limit_orders(&get_account(Admin))[0];
+    assert_eq!(order.attached_collateral.0, 0);
+    assert_eq!(order.execution_fee.0, near(1));
+
+    update_near_price(&mut vcontract, dollars(6));
+    let limit_order_id = vcontract.get_eligible_orders(near_id(), None)[0];
+    set_predecessor(&mut context, Bob);
+    let transfers = vcontract
+        .contract_mut()
+        .execute_limit_order(&AssetId::NEAR, &limit_order_id);
+    let keeper_fee = transfers.last().unwrap();
+    assert_eq!(keeper_fee.receiver_id(), get_account(Bob));
+    assert_eq!(keeper_fee.amount(), near(1));
+    assert_eq!(
+        vcontract.get_positions(get_account(Admin))[0].size.0,
+        dollars(120)
+    );
+}
+
+#[test]
+#[should_panic(expected = "caller must be have a Liquidator role")]
+fn test_decrease_order_execution_requires_liquidator() {
+    let (mut context, mut vcontract, _) = setup_execution_fee_order(OrderType::Decrease, near(1));
+
+    update_near_price(&mut vcontract, dollars(6));
+    let limit_order_id = vcontract.get_eligible_orders(near_id(), None)[0];
+    set_predecessor(&mut context, Bob);
+    let _ = vcontract
+        .contract_mut()
+        .execute_limit_order(&AssetId::NEAR, &limit_order_id);
+}
+
+#[test]
+#[should_panic(expected = "Execution fee exceeds the attached tokens")]
+fn test_execution_fee_exceeds_deposit() {
+    setup_execution_fee_order(OrderType::Increase, near(1) / 2);
+}
+
+#[test]
+fn test_limit_order_depth() {
+    let (mut context, mut vcontract) = setup_swap_orders();
+
+    // Admin has a $200 long and an order closing it at $6
+    set_deposit(&mut context, near(10));
+    vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(200).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+    set_deposit(&mut context, 0);
+    vcontract.add_limit_order(LimitOrderParameters {
+        price: dollars(6).into(),
+        size_delta: 0.into(),
+        underlying_id: near_id(),
+        collateral_id: Some(near_id()),
+        is_long: true,
+        expiry: None,
+        order_type: OrderType::Decrease,
+        collateral_delta: None,
+        reduce_only: None,
+        close_position: Some(true),
+        execution_fee: None,
+    });
+
+    // Swap orders are not part of the depth
+    set_deposit(&mut context, near(10));
+    vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(6).into(),
+        expiry: None,
+        execution_fee: None,
+    });
+
+    for (account, price) in [(Alice, dollars(41) / 10), (Bob, dollars(44) / 10)] {
+        set_predecessor(&mut context, account);
+        vcontract.add_limit_order(LimitOrderParameters {
+            price: price.into(),
+            size_delta: dollars(80).into(),
+            underlying_id: near_id(),
+            collateral_id: None,
+            is_long: true,
+            expiry: None,
+            order_type: OrderType::Increase,
+            collateral_delta: None,
+            reduce_only: None,
+            close_position: None,
+            execution_fee: None,
+        });
+    }
+
+    let depth = vcontract.get_limit_order_depth(near_id(), dollars(1).into(), None, None);
+    let levels: Vec<_> = depth
+        .iter()
+        .map(|level| {
+            (
+                level.price.0,
+                level.threshold,
+                level.increase_size.0,
+                level.decrease_size.0,
+                level.num_orders,
+            )
+        })
+        .collect();
+    assert_eq!(
+        levels,
+        vec![
+            (dollars(4), ThresholdType::Below, dollars(160), 0, 2),
+            (dollars(6), ThresholdType::Above, 0, dollars(200), 1),
+        ]
+    );
+    assert!(depth.iter().all(|level| level.is_long));
+
+    let depth = vcontract.get_limit_order_depth(
+        near_id(),
+        dollars(1).into(),
+        Some(dollars(4).into()),
+        Some(dollars(5).into()),
+    );
+    assert_eq!(depth.len(), 1);
+    assert_eq!(depth[0].num_orders, 2);
+}
+
+#[test]
+#[should_panic(expected = "Bucket size must be greater than 0")]
+fn test_limit_order_depth_zero_bucket() {
+    let (_, vcontract) = setup_swap_orders();
+    vcontract.get_limit_order_depth(near_id(), 0.into(), None, None);
+}
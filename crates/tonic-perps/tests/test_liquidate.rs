@@ -423,3 +423,60 @@ fn test_liquidate_position_fees_exceed_collateral() {
         dollars(40) - reward
     );
 }
+
+#[test]
+fn test_mark_price_ema() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.set_mark_price_smoothing_bps(near_id(), 5000);
+
+    update_near_price(&mut vcontract, dollars(5));
+    assert_eq!(vcontract.get_asset_info(near_id()).mark_price.0, dollars(5));
+
+    advance_time(&mut context, 1);
+    update_near_price(&mut vcontract, dollars(3));
+    let near = vcontract.get_asset_info(near_id());
+    assert_eq!(near.average_price.0, dollars(3));
+    assert_eq!(near.mark_price.0, dollars(4));
+
+    // Updates within the same second do not move the mark price
+    update_near_price(&mut vcontract, dollars(3));
+    assert_eq!(vcontract.get_asset_info(near_id()).mark_price.0, dollars(4));
+
+    // Each elapsed second is weighted
+    advance_time(&mut context, 2);
+    update_near_price(&mut vcontract, dollars(3));
+    assert_eq!(vcontract.get_asset_info(near_id()).mark_price.0, 3_250_000);
+}
+
+#[test]
+#[should_panic(expected = "Position not eligible for liquidation")]
+fn test_liquidate_ignores_price_spike() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.set_mark_price_smoothing_bps(near_id(), 1000);
+
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(100));
+    update_near_price(&mut vcontract, dollars(5));
+
+    // Open a 4x leveraged position long NEAR
+    set_deposit(&mut context, near(5));
+    let position_id = vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: U128(dollars(100)),
+        is_long: true,
+        referrer_id: None,
+    });
+
+    // Index price spikes down, mark price only moves to $4.89
+    advance_time(&mut context, 1);
+    update_near_price(&mut vcontract, 3_900_000);
+    assert_eq!(vcontract.get_asset_info(near_id()).mark_price.0, 4_890_000);
+
+    set_predecessor(&mut context, Alice);
+    set_deposit(&mut context, 1);
+
+    vcontract.liquidate_position(LiquidatePositionRequest { position_id });
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::{
+    core::FungibleTokenCore, receiver::FungibleTokenReceiver,
+};
+
+fn add_usdc_to_basket(
+    context: &mut near_sdk::test_utils::VMContextBuilder,
+    vcontract: &mut VContract,
+    amount: u128,
+) {
+    set_predecessor_token(context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        amount.into(),
+        serde_json::to_string(&Action::AddToLpBasket).unwrap(),
+    );
+    set_predecessor(context, Bob);
+}
+
+fn setup_basket() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    add_usdc_to_basket(&mut context, &mut vcontract, dollars(200));
+    set_deposit(&mut context, near(1000));
+    vcontract.mint_lp_basket(None, None);
+
+    (context, vcontract)
+}
+
+#[test]
+fn test_mint_lp_basket() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    add_usdc_to_basket(&mut context, &mut vcontract, dollars(200));
+    let basket = vcontract.get_lp_basket(get_account(Bob));
+    assert_eq!(basket.get(&usdc_id()).unwrap().0, dollars(200));
+
+    // $5000 of NEAR + $200 of USDC
+    set_deposit(&mut context, near(1000));
+    let minted = vcontract.mint_lp_basket(None, None);
+    assert_eq!(minted.0, lp_tokens(5200.0));
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(5200.0)
+    );
+    assert!(vcontract.get_lp_basket(get_account(Bob)).is_empty());
+
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1000)
+    );
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(200)
+    );
+}
+
+#[test]
+#[should_panic(expected = "exceeded slippage tolerance")]
+fn test_mint_lp_basket_slippage() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    add_usdc_to_basket(&mut context, &mut vcontract, dollars(200));
+    set_deposit(&mut context, near(1000));
+    vcontract.mint_lp_basket(Some(lp_tokens(5201.0).into()), None);
+}
+
+#[test]
+fn test_withdraw_lp_basket() {
+    let (mut context, mut vcontract) = setup();
+    add_usdc_to_basket(&mut context, &mut vcontract, dollars(200));
+
+    vcontract.withdraw_lp_basket();
+    assert!(vcontract.get_lp_basket(get_account(Bob)).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "LP basket is empty")]
+fn test_mint_empty_lp_basket() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Bob);
+    vcontract.mint_lp_basket(None, None);
+}
+
+#[test]
+fn test_burn_lp_token_in_kind() {
+    let (_context, mut vcontract) = setup_basket();
+
+    let amounts = vcontract.burn_lp_token_in_kind(lp_tokens(2600.0).into(), None, None);
+    assert_eq!(amounts.get(&near_id()).unwrap().0, near(500));
+    assert_eq!(amounts.get(&usdc_id()).unwrap().0, dollars(100));
+
+    assert_eq!(vcontract.get_asset_info(near_id()).pool_amount.0, near(500));
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(100)
+    );
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(2600.0)
+    );
+}
+
+#[test]
+fn test_burn_lp_token_in_kind_no_tax() {
+    let (mut context, mut vcontract) = setup_basket();
+
+    set_predecessor(&mut context, Admin);
+    vcontract.set_dynamic_swap_fees(true);
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 50,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 10,
+        swap_fee_bps: 0,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+
+    // Only the base fee is charged even though the pool is unbalanced
+    set_predecessor(&mut context, Bob);
+    let amounts = vcontract.burn_lp_token_in_kind(lp_tokens(2600.0).into(), None, None);
+    assert_eq!(
+        amounts.get(&near_id()).unwrap().0,
+        near(500) - near(500) / 1000
+    );
+    assert_eq!(
+        amounts.get(&usdc_id()).unwrap().0,
+        dollars(100) - dollars(100) / 1000
+    );
+}
+
+#[test]
+#[should_panic(expected = "exceeded slippage tolerance")]
+fn test_burn_lp_token_in_kind_slippage() {
+    let (_context, mut vcontract) = setup_basket();
+    let _ =
+        vcontract.burn_lp_token_in_kind(lp_tokens(2600.0).into(), Some(dollars(2601).into()), None);
+}
+
+#[test]
+fn test_burn_lp_token_in_kind_skips_depegged() {
+    let (mut context, mut vcontract) = setup_basket();
+
+    set_predecessor(&mut context, Admin);
+    update_asset_price(&mut vcontract, usdc_id(), 950_000);
+    update_near_price(&mut vcontract, dollars(5));
+
+    // Only half of the NEAR is paid out, Bob's share of the depegged USDC
+    // isn't taken out of the NEAR
+    set_predecessor(&mut context, Bob);
+    let amounts = vcontract.burn_lp_token_in_kind(lp_tokens(2600.0).into(), None, None);
+    assert_eq!(amounts.len(), 1);
+    assert_eq!(amounts.get(&near_id()).unwrap().0, near(500));
+    assert_eq!(vcontract.get_asset_info(near_id()).pool_amount.0, near(500));
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(200)
+    );
+}
+
+#[test]
+fn test_burn_lp_token_in_kind_skips_close_only() {
+    let (mut context, mut vcontract) = setup_basket();
+
+    set_predecessor(&mut context, Admin);
+    vcontract.schedule_price_override(usdc_id(), dollars(1).into());
+    advance_time(&mut context, 60 * 60);
+    vcontract.apply_price_override(usdc_id());
+    update_near_price(&mut vcontract, dollars(5));
+    assert!(vcontract.get_asset_info(usdc_id()).close_only);
+
+    set_predecessor(&mut context, Bob);
+    let amounts = vcontract.burn_lp_token_in_kind(lp_tokens(2600.0).into(), None, None);
+    assert_eq!(amounts.len(), 1);
+    assert_eq!(amounts.get(&near_id()).unwrap().0, near(500));
+}
+
+#[test]
+fn test_lp_basket_storage() {
+    let (mut context, mut vcontract) = setup();
+    let initial_balance = vcontract.get_order_storage_balance(get_account(Bob)).0;
+
+    add_usdc_to_basket(&mut context, &mut vcontract, dollars(200));
+    assert!(vcontract.get_order_storage_balance(get_account(Bob)).0 < initial_balance);
+
+    vcontract.withdraw_lp_basket();
+    assert_eq!(
+        vcontract.get_order_storage_balance(get_account(Bob)).0,
+        initial_balance
+    );
+}
+
+#[test]
+#[should_panic(expected = "Not enough order storage balance")]
+fn test_lp_basket_storage_required() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        accounts(3),
+        dollars(200).into(),
+        serde_json::to_string(&Action::AddToLpBasket).unwrap(),
+    );
+}
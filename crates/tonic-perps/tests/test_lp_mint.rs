@@ -514,3 +514,84 @@ fn test_mint_zero_deposit() {
     set_deposit(&mut context, near(0));
     vcontract.mint_lp_near(None, None);
 }
+
+fn setup_lp_cooldown() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_lp_cooldown_sec(60);
+
+    set_predecessor(&mut context, Alice);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+    (context, vcontract)
+}
+
+#[test]
+#[should_panic(expected = "LP tokens are locked until")]
+fn test_burn_lp_token_cooldown() {
+    let (_, mut vcontract) = setup_lp_cooldown();
+    vcontract.burn_lp_token(U128(lp_tokens(1.0)), near_id(), None, None);
+}
+
+#[test]
+#[should_panic(expected = "LP tokens are locked until")]
+fn test_transfer_lp_token_cooldown() {
+    let (mut context, mut vcontract) = setup_lp_cooldown();
+    set_deposit(&mut context, 1);
+    vcontract.ft_transfer(get_account(Bob), U128(lp_tokens(1.0)), None);
+}
+
+#[test]
+fn test_lp_token_cooldown_expires() {
+    let (mut context, mut vcontract) = setup_lp_cooldown();
+
+    let lock = vcontract.get_lp_token_lock(get_account(Alice)).unwrap();
+    assert_eq!(lock.amount, lp_tokens(500.0));
+    assert_eq!(
+        lock.unlock_timestamp_ms,
+        near_sdk::env::block_timestamp_ms() + 60_000
+    );
+
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(60).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    set_predecessor(&mut context, Alice);
+
+    assert!(vcontract.get_lp_token_lock(get_account(Alice)).is_none());
+    vcontract.burn_lp_token(U128(lp_tokens(100.0)), near_id(), None, None);
+}
+
+#[test]
+fn test_lp_token_cooldown_only_locks_minted_amount() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_lp_cooldown_sec(60);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(60).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    set_predecessor(&mut context, Alice);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    // Tokens received from others are not locked
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.ft_transfer(get_account(Alice), U128(lp_tokens(100.0)), None);
+
+    set_predecessor(&mut context, Alice);
+    vcontract.burn_lp_token(U128(lp_tokens(100.0)), near_id(), None, None);
+}
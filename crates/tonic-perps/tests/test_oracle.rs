@@ -28,3 +28,203 @@ fn test_oracle_max_change() {
 
     assert!(dollars(6) > near.unwrap().average_price.0);
 }
+
+fn switchboard_round(mantissa: i128, scale: u32, timestamp_sec: u64) -> sbv2_near::AggregatorRound {
+    sbv2_near::AggregatorRound {
+        result: sbv2_near::SwitchboardDecimal { mantissa, scale },
+        round_open_timestamp: timestamp_sec,
+        ..Default::default()
+    }
+}
+
+fn now_sec() -> u64 {
+    near_sdk::env::block_timestamp_ms() / 1000
+}
+
+#[test]
+fn test_switchboard_price_scaling() {
+    let price =
+        |mantissa, scale| switchboard_price(&sbv2_near::SwitchboardDecimal { mantissa, scale });
+    assert_eq!(price(5, 0), Ok(dollars(5)));
+    assert_eq!(price(512_345_678, 8), Ok(5_123_456));
+    assert_eq!(price(5 * 10i128.pow(30), 30), Ok(dollars(5)));
+    assert!(price(-5, 0).is_err());
+    assert!(price(0, 0).is_err());
+    assert!(price(5, 50).is_err());
+    assert!(price(i128::MAX, 0).is_err());
+}
+
+#[test]
+fn test_switchboard_callback() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+
+    vcontract.query_switchboard_callback(
+        Ok(switchboard_round(512_345_678, 8, now_sec())),
+        &AssetId::NEAR,
+    );
+    let near = vcontract.get_asset_info(near_id());
+    assert_eq!(near.average_price.0, 5_123_456);
+    assert_eq!(near.switchboard_failures, 0);
+}
+
+#[test]
+fn test_switchboard_callback_failures() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    // Negative price does not panic
+    vcontract.query_switchboard_callback(Ok(switchboard_round(-5, 0, now_sec())), &AssetId::NEAR);
+    // Round is older than the staleness threshold
+    vcontract
+        .query_switchboard_callback(Ok(switchboard_round(6, 0, now_sec() - 91)), &AssetId::NEAR);
+    vcontract.query_switchboard_callback(Err(near_sdk::PromiseError::Failed), &AssetId::NEAR);
+
+    let near = vcontract.get_asset_info(near_id());
+    assert_eq!(near.average_price.0, dollars(5));
+    assert_eq!(near.switchboard_failures, 3);
+}
+
+#[test]
+fn test_switchboard_outdated_round_ignored() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    advance_time(&mut context, 60);
+    update_near_price(&mut vcontract, dollars(5));
+
+    // Round opened before the last price update
+    vcontract
+        .query_switchboard_callback(Ok(switchboard_round(6, 0, now_sec() - 30)), &AssetId::NEAR);
+
+    let near = vcontract.get_asset_info(near_id());
+    assert_eq!(near.average_price.0, dollars(5));
+    assert_eq!(near.switchboard_failures, 0);
+}
+
+#[test]
+#[should_panic(expected = "Switchboard was queried too recently")]
+fn test_switchboard_rate_limit() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.set_switchboard_aggregator_address(near_id(), Some([1; 32]));
+
+    set_predecessor(&mut context, Alice);
+    vcontract.query_switchboard_asset(near_id());
+
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(31).as_nanos() as u64,
+    );
+    testing_env!(context.build());
+    vcontract.query_switchboard_asset(near_id());
+    vcontract.query_switchboard_asset(near_id());
+}
+
+#[test]
+fn test_price_override() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    assert!(!vcontract.get_asset_info(near_id()).close_only);
+
+    advance_time(&mut context, 60 * 60);
+    vcontract.apply_price_override(near_id());
+
+    let near = vcontract.get_asset_info(near_id());
+    assert_eq!(near.average_price.0, dollars(4));
+    assert!(near.close_only);
+    assert!(vcontract.get_price_override(near_id()).unwrap().active);
+
+    vcontract.contract().validate_asset_price(&AssetId::NEAR);
+
+    // A fresh oracle price expires the override
+    update_near_price(&mut vcontract, dollars(5));
+    let near = vcontract.get_asset_info(near_id());
+    assert!(!near.close_only);
+    assert!(vcontract.get_price_override(near_id()).is_none());
+}
+
+#[test]
+fn test_cancel_price_override() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    vcontract.cancel_price_override(near_id());
+    assert!(vcontract.get_price_override(near_id()).is_none());
+
+    let logs = near_sdk::test_utils::get_logs();
+    for action in ["schedule", "cancel"] {
+        assert!(logs.iter().any(|log| log.contains("PriceOverride")
+            && log.contains(&format!("\"action\":\"{}\"", action))));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Price override is not effective yet")]
+fn test_price_override_delay() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    advance_time(&mut context, 60 * 60 - 1);
+    vcontract.apply_price_override(near_id());
+}
+
+#[test]
+#[should_panic(expected = "No price override scheduled")]
+fn test_price_override_expired_by_oracle() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    advance_time(&mut context, 60);
+    update_near_price(&mut vcontract, dollars(5));
+
+    advance_time(&mut context, 60 * 60);
+    vcontract.apply_price_override(near_id());
+}
+
+#[test]
+#[should_panic(expected = "Asset is in close-only mode")]
+fn test_price_override_close_only() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    advance_time(&mut context, 60 * 60);
+    vcontract.apply_price_override(near_id());
+
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+}
+
+#[test]
+#[should_panic(expected = "Asset price is too stale")]
+fn test_price_override_goes_stale() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.schedule_price_override(near_id(), U128(dollars(4)));
+    advance_time(&mut context, 60 * 60);
+    vcontract.apply_price_override(near_id());
+
+    // Close-only exits still need a fresh price
+    advance_time(&mut context, 91);
+    vcontract.contract().validate_asset_price(&AssetId::NEAR);
+}
+
+#[test]
+#[should_panic]
+fn test_price_override_delay_below_min() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.set_price_override_delay_sec(MIN_PRICE_OVERRIDE_DELAY_SEC - 1);
+}
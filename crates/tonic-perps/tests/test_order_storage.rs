@@ -0,0 +1,173 @@
+mod common;
+
+use common::*;
+use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+
+fn setup_order_storage() -> (VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::NEAR, near(1000));
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+    update_near_price(&mut vcontract, dollars(5));
+
+    // The account creating the order book of an asset pays for it
+    place_order(&mut context, &mut vcontract, Admin, 10);
+    (context, vcontract)
+}
+
+/// Swap 10 NEAR into USDC once NEAR is worth `price`
+fn place_order(
+    context: &mut VMContextBuilder,
+    vcontract: &mut VContract,
+    account: TestAccount,
+    price: u64,
+) -> LimitOrderId {
+    set_predecessor(context, account);
+    set_deposit(context, near(10));
+    vcontract.add_swap_limit_order(SwapLimitOrderParameters {
+        output_token_id: usdc_id(),
+        price: dollars(price).into(),
+        expiry: None,
+        execution_fee: None,
+    })
+}
+
+fn get_storage_balance(vcontract: &VContract) -> u128 {
+    vcontract.get_order_storage_balance(get_account(Bob)).0
+}
+
+#[test]
+fn test_order_storage_charged_and_refunded() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    let initial_balance = get_storage_balance(&vcontract);
+
+    let limit_order_id = place_order(&mut context, &mut vcontract, Bob, 7);
+    let balance = get_storage_balance(&vcontract);
+    assert!(balance < initial_balance);
+
+    // Merging into the order uses no more storage
+    place_order(&mut context, &mut vcontract, Bob, 7);
+    assert_eq!(get_storage_balance(&vcontract), balance);
+
+    set_deposit(&mut context, 1);
+    vcontract.remove_limit_order(limit_order_id);
+    assert_eq!(get_storage_balance(&vcontract), initial_balance);
+}
+
+#[test]
+fn test_order_storage_refunded_on_execution() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    let initial_balance = get_storage_balance(&vcontract);
+    let limit_order_id = place_order(&mut context, &mut vcontract, Bob, 6);
+
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(6));
+    set_deposit(&mut context, 1);
+    vcontract.execute_limit_order(near_id(), limit_order_id);
+    assert_eq!(get_storage_balance(&vcontract), initial_balance);
+}
+
+#[test]
+#[should_panic(expected = "Not enough order storage balance")]
+fn test_order_storage_required() {
+    let (mut context, mut vcontract) = setup_order_storage();
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.withdraw_order_storage(None);
+    assert_eq!(get_storage_balance(&vcontract), 0);
+    place_order(&mut context, &mut vcontract, Bob, 7);
+}
+
+#[test]
+fn test_order_storage_update_keeps_paid_storage() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    let initial_balance = get_storage_balance(&vcontract);
+    let limit_order_id = place_order(&mut context, &mut vcontract, Bob, 7);
+    let balance = get_storage_balance(&vcontract);
+
+    set_deposit(&mut context, 1);
+    let limit_order_id = vcontract.update_limit_order(UpdateLimitOrderParameters {
+        limit_order_id,
+        price: Some(dollars(8).into()),
+        size_delta: None,
+        collateral_delta: None,
+        top_up: None,
+        withdraw: None,
+        expiry: None,
+        execution_fee: None,
+    });
+    assert_eq!(get_storage_balance(&vcontract), balance);
+
+    vcontract.remove_limit_order(limit_order_id);
+    assert_eq!(get_storage_balance(&vcontract), initial_balance);
+}
+
+#[test]
+fn test_deposit_order_storage_pays_for_balance() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    let account_id = accounts(3);
+
+    set_deposit(&mut context, near(1));
+    let balance = vcontract.deposit_order_storage(Some(account_id.clone())).0;
+    assert!(balance < near(1));
+
+    // Later deposits add to the existing balance
+    assert_eq!(
+        vcontract.deposit_order_storage(Some(account_id)).0,
+        balance + near(1)
+    );
+}
+
+#[test]
+fn test_deposit_order_storage_event_for_credited_account() {
+    let (mut context, mut vcontract) = setup_order_storage();
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(1));
+    vcontract.deposit_order_storage(Some(accounts(3)));
+    assert!(get_logs()
+        .iter()
+        .any(|log| log.contains("deposit_order_storage")
+            && log.contains(&format!("\"account_id\":\"{}\"", accounts(3)))));
+}
+
+#[test]
+#[should_panic(expected = "Not enough order storage balance")]
+fn test_deposit_order_storage_too_small_for_balance() {
+    let (mut context, mut vcontract) = setup_order_storage();
+
+    set_deposit(&mut context, 1);
+    vcontract.deposit_order_storage(Some(accounts(3)));
+}
+
+#[test]
+#[should_panic(expected = "Too many open limit orders")]
+fn test_max_orders_per_account() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    vcontract.set_limit_order_limits(LimitOrderLimits {
+        max_orders_per_account: 1,
+        max_orders_per_asset: 0,
+    });
+
+    place_order(&mut context, &mut vcontract, Bob, 7);
+    place_order(&mut context, &mut vcontract, Bob, 8);
+}
+
+#[test]
+#[should_panic(expected = "Too many open limit orders for this asset")]
+fn test_max_orders_per_asset() {
+    let (mut context, mut vcontract) = setup_order_storage();
+    vcontract.set_limit_order_limits(LimitOrderLimits {
+        max_orders_per_account: 0,
+        max_orders_per_asset: 2,
+    });
+
+    place_order(&mut context, &mut vcontract, Bob, 7);
+    place_order(&mut context, &mut vcontract, Bob, 8);
+    place_order(&mut context, &mut vcontract, Bob, 9);
+}
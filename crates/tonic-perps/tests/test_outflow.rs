@@ -0,0 +1,297 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+
+fn setup_outflow(limits: OutflowLimits) -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    context.account_balance(near(10000));
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_outflow_limits(limits);
+
+    // $5000 of LP tokens for Bob and Admin each
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(1000));
+    vcontract.mint_lp_near(None, None);
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, near(1000));
+    vcontract.mint_lp_near(None, None);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    (context, vcontract)
+}
+
+fn pass_window(context: &mut near_sdk::test_utils::VMContextBuilder, vcontract: &mut VContract) {
+    context.block_timestamp(
+        context.context.block_timestamp
+            + std::time::Duration::from_secs(60 * 60 + 1).as_nanos() as u64,
+    );
+    set_predecessor(context, Admin);
+    update_near_price(vcontract, dollars(5));
+}
+
+#[test]
+fn test_global_outflow_limit_queues_withdrawal() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let amount_out = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, near(100));
+    assert_eq!(vcontract.get_outflow_usd(None).0, dollars(500));
+
+    // $600 more would exceed the $1000 limit
+    let amount_out = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, 0);
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(3900.0)
+    );
+    let queue = vcontract.get_withdrawal_queue(None, None);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].account_id, get_account(Bob));
+    assert_eq!(queue[0].amount.0, lp_tokens(600.0));
+    assert_eq!(queue[0].output_token_id, Some(near_id()));
+
+    // Still within the window
+    assert_eq!(vcontract.process_withdrawal_queue(None), 0);
+
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.get_outflow_usd(None).0, 0);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 1);
+    assert!(vcontract.get_withdrawal_queue(None, None).is_empty());
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(2000) - near(100) - near(120)
+    );
+}
+
+#[test]
+fn test_account_outflow_limit() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: 0,
+        account_limit_usd: dollars(1000),
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(800.0).into(), near_id(), None, None);
+    let amount_out = vcontract.burn_lp_token(lp_tokens(800.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, 0);
+
+    // Other accounts aren't limited by Bob's withdrawals
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, 1);
+    let amount_out = vcontract.burn_lp_token(lp_tokens(800.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, near(160));
+
+    // Bob's withdrawal is skipped until his window passes
+    assert_eq!(vcontract.process_withdrawal_queue(None), 0);
+    assert_eq!(vcontract.get_withdrawal_queue(None, None).len(), 1);
+
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 1);
+}
+
+#[test]
+fn test_cancel_queued_withdrawal() {
+    let (_context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let _ = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+    let id = vcontract.get_withdrawal_queue(None, None)[0].id;
+
+    vcontract.cancel_queued_withdrawal(id);
+    assert!(vcontract.get_withdrawal_queue(None, None).is_empty());
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(4500.0)
+    );
+}
+
+#[test]
+#[should_panic(expected = "Can not cancel other account's withdrawal")]
+fn test_cancel_other_account_withdrawal() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let _ = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, 1);
+    vcontract.cancel_queued_withdrawal(0);
+}
+
+#[test]
+fn test_outflow_limit_in_kind() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let amounts = vcontract.burn_lp_token_in_kind(lp_tokens(600.0).into(), None, None);
+    assert!(amounts.is_empty());
+    let queue = vcontract.get_withdrawal_queue(None, None);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].output_token_id, None);
+
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 1);
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(2000) - near(100) - near(120)
+    );
+}
+
+#[test]
+fn test_outflow_limit_zap_out() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let amount_out = vcontract.zap_out_lp(lp_tokens(600.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, 0);
+    let queue = vcontract.get_withdrawal_queue(None, None);
+    assert_eq!(queue.len(), 1);
+    assert!(queue[0].zap_out);
+
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 1);
+    assert!(vcontract.get_withdrawal_queue(None, None).is_empty());
+}
+
+#[test]
+fn test_queued_withdrawal_min_out() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let _ = vcontract.burn_lp_token(
+        lp_tokens(600.0).into(),
+        near_id(),
+        Some(near(121).into()),
+        None,
+    );
+    assert_eq!(
+        vcontract.get_withdrawal_queue(None, None)[0].min_out,
+        Some(near(121).into())
+    );
+
+    // The burn redeems 120 NEAR, the withdrawal is cancelled
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 0);
+    assert!(vcontract.get_withdrawal_queue(None, None).is_empty());
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(4500.0)
+    );
+}
+
+#[test]
+fn test_queued_withdrawal_stale_price() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let _ = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+
+    // Stays queued instead of panicking
+    advance_time(&mut context, 60 * 60 + 1);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 0);
+    assert_eq!(vcontract.get_withdrawal_queue(None, None).len(), 1);
+
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    assert_eq!(vcontract.process_withdrawal_queue(None), 1);
+}
+
+#[test]
+fn test_queued_withdrawal_storage() {
+    let (_context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let storage_balance = vcontract.get_order_storage_balance(get_account(Bob)).0;
+    let _ = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+    assert!(vcontract.get_order_storage_balance(get_account(Bob)).0 < storage_balance);
+
+    vcontract.cancel_queued_withdrawal(0);
+    assert_eq!(
+        vcontract.get_order_storage_balance(get_account(Bob)).0,
+        storage_balance
+    );
+}
+
+#[test]
+fn test_burn_without_order_storage() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    set_predecessor_token(&mut context, accounts(3).to_string());
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+    assert_eq!(vcontract.get_order_storage_balance(accounts(3)).0, 0);
+
+    set_deposit(&mut context, 1);
+    let amount_out = vcontract.burn_lp_token(lp_tokens(250.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, near(50));
+    assert_eq!(vcontract.get_outflow_usd(None).0, dollars(250));
+    // No account history is kept without an account limit
+    assert_eq!(vcontract.get_outflow_usd(Some(accounts(3))).0, 0);
+}
+
+#[test]
+#[should_panic(expected = "Withdrawal exceeds outflow limit")]
+fn test_first_withdrawal_over_outflow_limit() {
+    let (_context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    // Nothing was withdrawn in the window, the burn is still over the limit
+    let _ = vcontract.burn_lp_token(lp_tokens(1500.0).into(), near_id(), None, None);
+}
+
+#[test]
+fn test_queued_withdrawal_over_lowered_limit() {
+    let (mut context, mut vcontract) = setup_outflow(OutflowLimits {
+        global_limit_usd: dollars(1000),
+        account_limit_usd: 0,
+    });
+
+    let _ = vcontract.burn_lp_token(lp_tokens(500.0).into(), near_id(), None, None);
+    let _ = vcontract.burn_lp_token(lp_tokens(600.0).into(), near_id(), None, None);
+
+    set_predecessor(&mut context, Admin);
+    vcontract.set_outflow_limits(OutflowLimits {
+        global_limit_usd: dollars(500),
+        account_limit_usd: 0,
+    });
+
+    // The withdrawal can never fit in the limit, it's cancelled
+    pass_window(&mut context, &mut vcontract);
+    assert_eq!(vcontract.process_withdrawal_queue(None), 0);
+    assert!(vcontract.get_withdrawal_queue(None, None).is_empty());
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(4500.0)
+    );
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use common::*;
+
+fn setup_price_impact(
+    params: PriceImpactParameters,
+) -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    // $5000 of NEAR and $5000 of USDC available
+    let contract = vcontract.contract_mut();
+    contract.add_liquidity(&AssetId::NEAR, near(1000));
+    contract.add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+
+    vcontract.set_price_impact_parameters(params);
+    (context, vcontract)
+}
+
+#[test]
+fn test_long_price_impact() {
+    let (mut context, mut vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 100,
+        imbalance_factor_bps: 0,
+        max_impact_bps: 100,
+    });
+
+    // $500 is 10% of the available liquidity: 10 bps
+    assert_eq!(
+        vcontract.get_position_price_impact_bps(
+            near_id(),
+            near_id(),
+            dollars(500).into(),
+            true,
+            true
+        ),
+        10
+    );
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(50));
+    let position_id = vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(500).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+    let position = vcontract.get_position(&position_id).unwrap();
+    assert_eq!(position.average_price.0, dollars(5) + dollars(5) / 1000);
+}
+
+#[test]
+fn test_imbalance_reducing_short_is_rewarded() {
+    let (mut context, mut vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 0,
+        imbalance_factor_bps: 100,
+        max_impact_bps: 100,
+    });
+
+    // $1000 of long open interest
+    set_deposit(&mut context, near(100));
+    vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(1000).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+
+    // Shorting $500 halves the imbalance: -10 bps, sold at a higher price
+    assert_eq!(
+        vcontract.get_position_price_impact_bps(
+            usdc_id(),
+            near_id(),
+            dollars(500).into(),
+            false,
+            true
+        ),
+        -10
+    );
+    let (position_id, _) = vcontract.contract_mut().increase_position(
+        &get_account(Bob),
+        &AssetId::from(usdc_id()),
+        &AssetId::NEAR,
+        dollars(100),
+        dollars(500),
+        false,
+        None,
+    );
+    let position = vcontract.get_position(&position_id).unwrap();
+    assert_eq!(position.average_price.0, dollars(5) + dollars(5) / 1000);
+}
+
+#[test]
+fn test_price_impact_is_capped() {
+    let (_context, vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 1000,
+        imbalance_factor_bps: 1000,
+        max_impact_bps: 50,
+    });
+
+    assert_eq!(
+        vcontract.get_position_price_impact_bps(
+            near_id(),
+            near_id(),
+            dollars(2500).into(),
+            true,
+            true
+        ),
+        50
+    );
+}
+
+#[test]
+fn test_swap_price_impact() {
+    let (mut context, mut vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 100,
+        imbalance_factor_bps: 0,
+        max_impact_bps: 100,
+    });
+    assert_eq!(
+        vcontract.get_swap_price_impact_bps(usdc_id(), near_id(), dollars(500).into()),
+        10
+    );
+
+    set_signer(&mut context, Bob);
+    let amount_out = vcontract.contract_mut().swap(
+        &AssetId::from(usdc_id()),
+        &AssetId::NEAR,
+        dollars(500),
+        None,
+    );
+    assert_eq!(amount_out, near(100) - near(100) / 1000);
+}
+
+#[test]
+fn test_swap_exact_out_with_price_impact() {
+    let (mut context, mut vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 100,
+        imbalance_factor_bps: 0,
+        max_impact_bps: 100,
+    });
+
+    set_signer(&mut context, Bob);
+    let (amount_in, amount_out) = vcontract.contract_mut().swap_exact_out(
+        &AssetId::from(usdc_id()),
+        &AssetId::NEAR,
+        near(100),
+        dollars(600),
+    );
+    assert!(amount_out >= near(100));
+    assert!(amount_in > dollars(500));
+}
+
+#[test]
+fn test_imbalance_reducing_swap_is_rewarded() {
+    let (mut context, mut vcontract) = setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 0,
+        imbalance_factor_bps: 100,
+        max_impact_bps: 100,
+    });
+    // $10000 of USDC and $5000 of NEAR, targets are $7500 each
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+
+    // Moves both assets $500 towards their targets
+    assert_eq!(
+        vcontract.get_swap_price_impact_bps(near_id(), usdc_id(), near(100).into()),
+        -10
+    );
+    set_signer(&mut context, Bob);
+    let amount_out =
+        vcontract
+            .contract_mut()
+            .swap(&AssetId::NEAR, &AssetId::from(usdc_id()), near(100), None);
+    assert_eq!(amount_out, dollars(500) + dollars(1) / 2);
+}
+
+#[test]
+#[should_panic]
+fn test_set_invalid_price_impact_parameters() {
+    setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 100,
+        imbalance_factor_bps: 100,
+        max_impact_bps: 10000,
+    });
+}
+
+#[test]
+#[should_panic]
+fn test_set_price_impact_over_max_fee() {
+    // Swap fees and price impact could take the whole output
+    setup_price_impact(PriceImpactParameters {
+        liquidity_factor_bps: 100,
+        imbalance_factor_bps: 100,
+        max_impact_bps: 9500,
+    });
+}
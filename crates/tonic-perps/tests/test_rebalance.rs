@@ -0,0 +1,224 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::AccountId;
+
+fn eth(amount: u64) -> u128 {
+    amount as u128 * 10u128.pow(18)
+}
+
+fn eth_id() -> String {
+    "eth".to_string()
+}
+
+fn dex_id() -> AccountId {
+    "dex".parse().unwrap()
+}
+
+fn setup_rebalance() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.add_asset(eth_id(), 18, false, 50);
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, eth_id(), dollars(1000));
+
+    // $1000 of NEAR, $1000 of USDC and $5000 of ETH. Targets are $2333 each.
+    let contract = vcontract.contract_mut();
+    contract.add_liquidity(&AssetId::NEAR, near(200));
+    contract.add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+    contract.add_liquidity(&AssetId::from(eth_id()), eth(5));
+
+    vcontract.set_rebalance_parameters(RebalanceParameters {
+        swap_discount_bps: 20,
+        dex_id: Some(dex_id()),
+        max_slippage_bps: 100,
+    });
+    (context, vcontract)
+}
+
+#[test]
+fn test_rebalance_info() {
+    let (_context, vcontract) = setup_rebalance();
+    let info = vcontract.get_rebalance_info();
+
+    let eth_info = &info[&eth_id()];
+    assert_eq!(eth_info.current_usd.0, dollars(5000));
+    assert_eq!(eth_info.target_usd.0, dollars(7000) / 3);
+    assert_eq!(eth_info.deficit_usd.0, 0);
+    assert_eq!(eth_info.surplus_usd.0, dollars(5000) - dollars(7000) / 3);
+
+    let usdc_info = &info[&usdc_id()];
+    assert_eq!(usdc_info.deficit_usd.0, dollars(7000) / 3 - dollars(1000));
+    assert_eq!(usdc_info.surplus_usd.0, 0);
+}
+
+#[test]
+fn test_rebalance_swap_discount() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 0,
+        swap_fee_bps: 30,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+    set_signer(&mut context, Bob);
+
+    // NEAR is under its target and ETH over it: 30 - 20 bps
+    let amount_out =
+        vcontract
+            .contract_mut()
+            .swap(&AssetId::NEAR, &AssetId::from(eth_id()), near(20), None);
+    assert_eq!(amount_out, eth(1) / 10 - eth(1) / 10 * 10 / 10_000);
+
+    // Other direction pays the full fee
+    let amount_out =
+        vcontract
+            .contract_mut()
+            .swap(&AssetId::from(eth_id()), &AssetId::NEAR, eth(1) / 10, None);
+    assert_eq!(amount_out, near(20) - near(20) * 30 / 10_000);
+}
+
+#[test]
+fn test_rebalance_with_dex() {
+    let (mut context, mut vcontract) = setup_rebalance();
+
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+    assert_eq!(vcontract.get_asset_info(eth_id()).pool_amount.0, eth(4));
+    let pending = vcontract.get_pending_rebalance().unwrap();
+    assert_eq!(pending.amount_in, eth(1));
+    assert_eq!(pending.min_amount_out, usdc(990));
+
+    // The DEX sends the output back
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        dex_id(),
+        usdc(995).into(),
+        serde_json::to_string(&Action::RebalanceSwapOut).unwrap(),
+    );
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        usdc(1995)
+    );
+
+    set_predecessor(&mut context, Alice);
+    vcontract.on_rebalance_transfer(Ok(eth(1).into()));
+    assert!(vcontract.get_pending_rebalance().is_none());
+    assert_eq!(vcontract.get_asset_info(eth_id()).pool_amount.0, eth(4));
+}
+
+#[test]
+fn test_rebalance_with_dex_refund() {
+    let (mut context, mut vcontract) = setup_rebalance();
+
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    // The swap failed and the DEX refunded everything
+    set_predecessor(&mut context, Alice);
+    vcontract.on_rebalance_transfer(Ok(0.into()));
+    assert!(vcontract.get_pending_rebalance().is_none());
+    assert_eq!(vcontract.get_asset_info(eth_id()).pool_amount.0, eth(5));
+}
+
+#[test]
+#[should_panic(expected = "Pool rebalance in progress")]
+fn test_mint_during_rebalance() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    // LP tokens can't be minted while the pool is missing the tokens
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+}
+
+#[test]
+#[should_panic(expected = "Minimum output exceeds maximum slippage")]
+fn test_rebalance_with_dex_slippage() {
+    let (_context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(989).into());
+}
+
+#[test]
+#[should_panic(expected = "Swap does not move the pool towards target weights")]
+fn test_rebalance_with_dex_wrong_direction() {
+    let (_context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(usdc_id(), eth_id(), usdc(100).into(), eth(0).into());
+}
+
+#[test]
+#[should_panic(expected = "Only the rebalance DEX can send rebalance output")]
+fn test_rebalance_output_from_other_account() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        usdc(995).into(),
+        serde_json::to_string(&Action::RebalanceSwapOut).unwrap(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Exceeded slippage tolerance")]
+fn test_rebalance_output_after_price_move() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    // ETH went up while the swap was pending, 995 USDC is now below the
+    // maximum slippage
+    update_asset_price(&mut vcontract, eth_id(), dollars(1100));
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        dex_id(),
+        usdc(995).into(),
+        serde_json::to_string(&Action::RebalanceSwapOut).unwrap(),
+    );
+}
+
+#[test]
+fn test_clear_pending_rebalance() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    // The callback never ran
+    advance_time(&mut context, 60 * 60);
+    vcontract.clear_pending_rebalance();
+    assert!(vcontract.get_pending_rebalance().is_none());
+    assert_eq!(vcontract.get_asset_info(eth_id()).pool_amount.0, eth(4));
+
+    // LP tokens can be minted again
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, usdc_id(), dollars(1));
+    update_asset_price(&mut vcontract, eth_id(), dollars(1000));
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+}
+
+#[test]
+#[should_panic(expected = "No pending rebalance")]
+fn test_rebalance_output_after_clear() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+    advance_time(&mut context, 60 * 60);
+    vcontract.clear_pending_rebalance();
+
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        dex_id(),
+        usdc(995).into(),
+        serde_json::to_string(&Action::RebalanceSwapOut).unwrap(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Pending rebalance has not timed out")]
+fn test_clear_pending_rebalance_too_early() {
+    let (mut context, mut vcontract) = setup_rebalance();
+    let _ = vcontract.rebalance_with_dex(eth_id(), usdc_id(), eth(1).into(), usdc(990).into());
+
+    advance_time(&mut context, 60 * 60 - 1);
+    vcontract.clear_pending_rebalance();
+}
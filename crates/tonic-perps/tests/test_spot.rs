@@ -53,7 +53,7 @@ fn test_swap_near() {
     let near_before = vcontract.get_asset_info(near_id());
     let usdc_before = vcontract.get_asset_info(usdc_id());
 
-    vcontract.swap_near(usdc_id(), None, None);
+    vcontract.swap_near(usdc_id(), None, None, None);
 
     let near_after = vcontract.get_asset_info(near_id());
     let usdc_after = vcontract.get_asset_info(usdc_id());
@@ -99,7 +99,7 @@ fn test_swap_near_with_fees() {
         margin_fee_bps: 0,
     });
 
-    vcontract.swap_near(usdc_id(), None, None);
+    vcontract.swap_near(usdc_id(), None, None, None);
 
     let near_after = vcontract.get_asset_info(near_id());
     let usdc_after = vcontract.get_asset_info(usdc_id());
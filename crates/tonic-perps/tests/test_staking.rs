@@ -0,0 +1,269 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+
+fn setup_staking() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 100,
+        swap_fee_bps: 0,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+    vcontract.set_fee_split_parameters(FeeSplitParameters {
+        mint: FeeSplit {
+            treasury_bps: 5000,
+            lp_bps: 0,
+            staking_bps: 5000,
+        },
+        ..Default::default()
+    });
+    vcontract.set_staking_reward_duration_sec(1000);
+
+    // 1 NEAR of fees, all for the treasury as nothing is staked
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    set_deposit(&mut context, 1);
+    (context, vcontract)
+}
+
+/// Alice mints LP tokens paying 1 NEAR of fees, half of it for stakers
+fn collect_staking_fees(
+    context: &mut near_sdk::test_utils::VMContextBuilder,
+    vcontract: &mut VContract,
+) {
+    set_predecessor(context, Alice);
+    set_deposit(context, near(100));
+    vcontract.mint_lp_near(None, None);
+    refresh_price(context, vcontract);
+}
+
+/// Oracle updates keep streaming staking fees
+fn refresh_price(context: &mut near_sdk::test_utils::VMContextBuilder, vcontract: &mut VContract) {
+    set_predecessor(context, Admin);
+    update_near_price(vcontract, dollars(5));
+    set_predecessor(context, Bob);
+    set_deposit(context, 1);
+}
+
+fn pending_rewards(vcontract: &VContract, account: TestAccount) -> u128 {
+    vcontract
+        .get_staker(get_account(account))
+        .pending_rewards
+        .get(&near_id())
+        .map_or(0, |r| r.0)
+}
+
+#[test]
+fn test_stake_lp() {
+    let (_context, mut vcontract) = setup_staking();
+    let balance = vcontract.ft_balance_of(get_account(Bob)).0;
+
+    vcontract.stake_lp(lp_tokens(100.).into());
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        balance - lp_tokens(100.)
+    );
+
+    let staker = vcontract.get_staker(get_account(Bob));
+    assert_eq!(staker.staked.0, lp_tokens(100.));
+    // Fees collected before anyone staked went to the treasury
+    assert!(staker.pending_rewards.is_empty());
+    assert_eq!(vcontract.get_staking_pool().total_staked.0, lp_tokens(100.));
+
+    vcontract.unstake_lp(lp_tokens(100.).into());
+    assert_eq!(vcontract.ft_balance_of(get_account(Bob)).0, balance);
+}
+
+#[test]
+fn test_staking_rewards_streamed() {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+
+    collect_staking_fees(&mut context, &mut vcontract);
+    assert_eq!(pending_rewards(&vcontract, Bob), 0);
+    assert_eq!(
+        vcontract
+            .get_staking_pool()
+            .reward_rates
+            .get(&near_id())
+            .unwrap()
+            .0,
+        near(1) / 2 / 1000
+    );
+
+    advance_time(&mut context, 500);
+    assert_eq!(pending_rewards(&vcontract, Bob), near(1) / 4);
+
+    advance_time(&mut context, 500);
+    refresh_price(&mut context, &mut vcontract);
+    assert_eq!(pending_rewards(&vcontract, Bob), near(1) / 2);
+}
+
+#[test]
+fn test_staking_rewards_split() {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+
+    // Admin's mint fees are streamed once Admin stakes too
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+    set_deposit(&mut context, 1);
+    vcontract.stake_lp(lp_tokens(300.).into());
+    assert_eq!(pending_rewards(&vcontract, Bob), 0);
+
+    advance_time(&mut context, 1000);
+    refresh_price(&mut context, &mut vcontract);
+    assert_eq!(pending_rewards(&vcontract, Bob), near(1) / 8);
+    assert_eq!(pending_rewards(&vcontract, Admin), near(1) * 3 / 8);
+}
+
+#[test]
+fn test_late_staker_does_not_capture_backlog() {
+    let (mut context, mut vcontract) = setup_staking();
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.stake_lp(lp_tokens(100.).into());
+    collect_staking_fees(&mut context, &mut vcontract);
+
+    // Bob leaves after 10% of the stream
+    advance_time(&mut context, 100);
+    vcontract.unstake_lp(lp_tokens(100.).into());
+    assert_eq!(pending_rewards(&vcontract, Bob), near(1) / 20);
+
+    // The rest of the stream goes to the treasury, not to a dust staker
+    set_predecessor(&mut context, Admin);
+    set_deposit(&mut context, 1);
+    vcontract.stake_lp(lp_tokens(0.001).into());
+    advance_time(&mut context, 1000);
+    refresh_price(&mut context, &mut vcontract);
+    assert_eq!(pending_rewards(&vcontract, Admin), 0);
+
+    // Nothing is left reserved for stakers
+    set_predecessor(&mut context, Admin);
+    vcontract.withdraw_fees(Some(vec![near_id()]));
+    assert_eq!(vcontract.get_asset_info(near_id()).accumulated_fees.0, 0);
+}
+
+#[test]
+fn test_claim_staking_rewards() {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+    collect_staking_fees(&mut context, &mut vcontract);
+    advance_time(&mut context, 1000);
+
+    let pool_amount = vcontract.get_asset_info(near_id()).pool_amount.0;
+    vcontract.claim_staking_rewards(None, None);
+    assert!(vcontract
+        .get_staker(get_account(Bob))
+        .pending_rewards
+        .is_empty());
+
+    // Rewards are paid in NEAR without a swap and taken off the asset's books
+    let asset = vcontract.get_asset_info(near_id());
+    assert_eq!(asset.pool_amount.0, pool_amount);
+    assert_eq!(asset.accumulated_fees.0, near(1) + near(1) / 2);
+    assert!(vcontract.get_staking_pool().total_distributed_usd.0 > 0);
+}
+
+fn setup_swapped_claim() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+    collect_staking_fees(&mut context, &mut vcontract);
+    advance_time(&mut context, 1000);
+
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    update_asset_price(&mut vcontract, usdc_id(), dollars(1));
+    vcontract
+        .contract_mut()
+        .add_liquidity(&AssetId::from(usdc_id()), usdc(1000));
+    set_predecessor(&mut context, Bob);
+    (context, vcontract)
+}
+
+#[test]
+fn test_claim_staking_rewards_swapped() {
+    let (_context, mut vcontract) = setup_swapped_claim();
+
+    let usdc_balance = vcontract.get_asset_info(usdc_id()).pool_amount.0;
+    vcontract.claim_staking_rewards(Some(usdc_id()), Some(U128(2_500_000)));
+
+    // 0.5 NEAR swapped to 2.5 USDC
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        usdc_balance - 2_500_000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Exceeded slippage tolerance")]
+fn test_claim_staking_rewards_min_out() {
+    let (_context, mut vcontract) = setup_swapped_claim();
+    vcontract.claim_staking_rewards(Some(usdc_id()), Some(U128(2_500_001)));
+}
+
+#[test]
+fn test_withdraw_fees_keeps_staking_fees() {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+    collect_staking_fees(&mut context, &mut vcontract);
+
+    set_predecessor(&mut context, Admin);
+    vcontract.withdraw_fees(Some(vec![near_id()]));
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).accumulated_fees.0,
+        near(1) / 2
+    );
+}
+
+#[test]
+fn test_staking_apr() {
+    let (mut context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+    collect_staking_fees(&mut context, &mut vcontract);
+    assert_eq!(vcontract.get_staking_pool().apr_bps.0, 0);
+
+    advance_time(&mut context, 60 * 60);
+    refresh_price(&mut context, &mut vcontract);
+    vcontract.claim_staking_rewards(None, None);
+
+    assert!(vcontract.get_staking_pool().apr_bps.0 > 0);
+}
+
+#[test]
+#[should_panic(expected = "LP tokens are locked")]
+fn test_stake_locked_lp() {
+    let (mut context, mut vcontract) = setup_staking();
+    set_predecessor(&mut context, Admin);
+    vcontract.set_lp_cooldown_sec(60);
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    let balance = vcontract.ft_balance_of(get_account(Bob)).0;
+    set_deposit(&mut context, 1);
+    vcontract.stake_lp(balance.into());
+}
+
+#[test]
+#[should_panic(expected = "Not enough staked LP tokens")]
+fn test_unstake_too_much() {
+    let (_context, mut vcontract) = setup_staking();
+    vcontract.stake_lp(lp_tokens(100.).into());
+    vcontract.unstake_lp(lp_tokens(101.).into());
+}
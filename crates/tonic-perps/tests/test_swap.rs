@@ -215,6 +215,7 @@ fn test_swap_high_level() {
             min_out: None,
             referrer_id: None,
             output_token_id: near_id(),
+            amount_out: None,
         }))
         .unwrap(),
     );
@@ -224,3 +225,104 @@ fn test_swap_high_level() {
 
     assert_eq!(near_asset.pool_amount.0, near(99));
 }
+
+fn setup_exact_out() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 0,
+        swap_fee_bps: 30,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 0,
+    });
+
+    let contract = vcontract.contract_mut();
+    contract.add_liquidity(&AssetId::NEAR, near(1000));
+    contract.add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+    (context, vcontract)
+}
+
+#[test]
+fn test_swap_exact_out() {
+    let (_context, mut vcontract) = setup_exact_out();
+
+    let expected_in = vcontract
+        .get_swap_amount_in(near_id(), usdc_id(), dollars(100).into())
+        .0;
+    let (amount_in, amount_out) = vcontract.contract_mut().swap_exact_out(
+        &AssetId::NEAR,
+        &AssetId::from(usdc_id()),
+        dollars(100),
+        near(21),
+    );
+    assert_eq!(amount_in, expected_in);
+    // 20 NEAR / (1 - 0.3%), rounded up
+    assert!(amount_in > near(20) * 10_000 / 9_970);
+    assert!(amount_in < near(20) * 10_000 / 9_970 + near(1) / 1000);
+    assert!(amount_out >= dollars(100));
+    assert!(amount_out <= dollars(100) + 1);
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1000) + amount_in
+    );
+}
+
+#[test]
+#[should_panic(expected = "Exceeded slippage tolerance")]
+fn test_swap_exact_out_max_amount_in() {
+    let (_context, mut vcontract) = setup_exact_out();
+    let _ = vcontract.contract_mut().swap_exact_out(
+        &AssetId::NEAR,
+        &AssetId::from(usdc_id()),
+        dollars(100),
+        near(20),
+    );
+}
+
+#[test]
+fn test_swap_exact_out_ft_refund() {
+    let (mut context, mut vcontract) = setup_exact_out();
+
+    set_predecessor_token(&mut context, usdc_id());
+    let unused = vcontract.ft_on_transfer(
+        get_account(Bob),
+        U128(dollars(20)),
+        serde_json::ser::to_string(&Action::Swap(SwapParams {
+            min_out: None,
+            referrer_id: None,
+            output_token_id: near_id(),
+            amount_out: Some(near(2).into()),
+        }))
+        .unwrap(),
+    );
+    let unused = match unused {
+        near_sdk::PromiseOrValue::Value(unused) => unused.0,
+        _ => panic!("Expected a value"),
+    };
+
+    // $10 / (1 - 0.3%) are used, the rest is refunded
+    let amount_in = dollars(20) - unused;
+    assert!(amount_in > dollars(10) * 10_000 / 9_970);
+    assert!(amount_in <= dollars(10) * 10_000 / 9_970 + 2);
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        dollars(5000) + amount_in
+    );
+}
+
+#[test]
+fn test_swap_near_exact_out() {
+    let (mut context, mut vcontract) = setup_exact_out();
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(30));
+    vcontract.swap_near(usdc_id(), None, None, Some(dollars(100).into()));
+
+    // Only the NEAR needed is added to the pool, the rest is refunded
+    let pool_amount = vcontract.get_asset_info(near_id()).pool_amount.0;
+    assert!(pool_amount > near(1020));
+    assert!(pool_amount < near(1021));
+}
@@ -41,6 +41,8 @@ fn test_decrease_near_fee() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let near_before = vcontract.get_asset_info(near_id());
@@ -103,6 +105,8 @@ fn test_decrease_usdc_fee() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let usdc_before = vcontract.get_asset_info(usdc_id());
@@ -163,6 +167,8 @@ fn test_decrease_all_fee() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     set_deposit(&mut context, near(5));
@@ -182,6 +188,8 @@ fn test_decrease_all_fee() {
         size_delta: U128(dollars(100)),
         referrer_id: None,
         output_token_id: None,
+        reduce_only: None,
+        close_position: None,
     });
 
     let near_before = vcontract.get_asset_info(near_id());
@@ -0,0 +1,298 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U64;
+use near_sdk::test_utils::VMContextBuilder;
+
+fn setup_twap() -> (VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    vcontract.add_admin(get_account(Alice), AdminRole::Liquidator);
+
+    let contract = vcontract.contract_mut();
+    contract.add_liquidity(&AssetId::NEAR, near(1000));
+    contract.add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+    (context, vcontract)
+}
+
+/// Move time forward and refresh prices so they don't become stale
+fn advance_time(context: &mut VMContextBuilder, vcontract: &mut VContract, seconds: u64) {
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(seconds).as_nanos() as u64,
+    );
+    set_predecessor(context, Admin);
+    update_near_price(vcontract, dollars(5));
+    update_asset_price(vcontract, usdc_id(), dollars(1));
+}
+
+fn place_long_twap(
+    context: &mut VMContextBuilder,
+    vcontract: &mut VContract,
+    num_slices: u16,
+) -> U64 {
+    set_predecessor(context, Bob);
+    set_deposit(context, near(20));
+    vcontract.add_twap_order(TwapOrderParameters {
+        underlying_id: near_id(),
+        is_long: true,
+        size_delta: (dollars(100) * u128::from(num_slices)).into(),
+        num_slices,
+        interval_sec: 60,
+    })
+}
+
+/// Execute the next slice as a keeper
+fn execute_slice(
+    context: &mut VMContextBuilder,
+    vcontract: &mut VContract,
+    twap_order_id: u64,
+) -> TransferInfo {
+    set_predecessor(context, Alice);
+    vcontract.contract_mut().execute_twap_slice(twap_order_id)
+}
+
+fn get_long_position_size(vcontract: &VContract) -> u128 {
+    let position_id = vcontract
+        .contract()
+        .get_position_for_user(&get_account(Bob), &AssetId::NEAR, &AssetId::NEAR, true)
+        .unwrap();
+    vcontract.get_position(&position_id).unwrap().size.0
+}
+
+#[test]
+fn test_twap_order_executes_in_slices() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 3);
+    assert_eq!(vcontract.get_user_twap_orders(get_account(Bob)).len(), 1);
+    assert_eq!(
+        vcontract.get_executable_twap_orders(None),
+        vec![twap_order_id]
+    );
+
+    let transfer_info = execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    assert_eq!(transfer_info.amount(), 0);
+    assert_eq!(get_long_position_size(&vcontract), dollars(100));
+    assert!(vcontract.get_executable_twap_orders(None).is_empty());
+
+    for _ in 0..2 {
+        advance_time(&mut context, &mut vcontract, 60);
+        execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    }
+    assert_eq!(get_long_position_size(&vcontract), dollars(300));
+    assert!(vcontract.get_twap_order(twap_order_id).is_none());
+}
+
+#[test]
+#[should_panic(expected = "TWAP slice is not ready to be executed")]
+fn test_twap_slice_not_ready() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+
+    execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    advance_time(&mut context, &mut vcontract, 30);
+    execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+}
+
+#[test]
+fn test_twap_slice_skipped_on_open_interest() {
+    let (mut context, mut vcontract) = setup_twap();
+    vcontract.set_open_interest_limits(
+        near_id(),
+        OpenInterestLimits {
+            long: dollars(150),
+            short: dollars(1_000_000),
+        },
+    );
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+
+    execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    assert_eq!(get_long_position_size(&vcontract), dollars(100));
+
+    // The second slice would exceed the open interest limit. It is skipped
+    // and, being the last one, its collateral is refunded.
+    advance_time(&mut context, &mut vcontract, 60);
+    let transfer_info = execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    assert_eq!(get_long_position_size(&vcontract), dollars(100));
+    assert_eq!(transfer_info.asset_id(), AssetId::NEAR);
+    assert_eq!(transfer_info.amount(), near(10));
+    assert!(vcontract.get_twap_order(twap_order_id).is_none());
+}
+
+#[test]
+fn test_twap_slice_skipped_on_stale_price() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+    execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+
+    // Prices aren't refreshed. The slice is skipped instead of panicking.
+    context.block_timestamp(
+        context.context.block_timestamp + std::time::Duration::from_secs(120).as_nanos() as u64,
+    );
+    let transfer_info = execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    assert_eq!(get_long_position_size(&vcontract), dollars(100));
+    assert_eq!(transfer_info.amount(), near(10));
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|log| log.contains("\"status\":\"skipped\"")
+            && log.contains("Asset price is too stale")));
+}
+
+#[test]
+fn test_twap_slice_skipped_on_fees() {
+    let (mut context, mut vcontract) = setup_twap();
+    vcontract.set_fee_parameters(FeeParameters {
+        tax_bps: 0,
+        stable_tax_bps: 0,
+        mint_burn_fee_bps: 0,
+        swap_fee_bps: 0,
+        stable_swap_fee_bps: 0,
+        margin_fee_bps: 500,
+    });
+
+    // $300 slices with $50 of collateral each. The leverage is within the
+    // limit after the margin fee, but not after the fees of closing.
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(20));
+    let twap_order_id = vcontract.add_twap_order(TwapOrderParameters {
+        underlying_id: near_id(),
+        is_long: true,
+        size_delta: dollars(600).into(),
+        num_slices: 2,
+        interval_sec: 60,
+    });
+
+    let transfer_info = execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+    assert_eq!(transfer_info.amount(), 0);
+    assert!(vcontract
+        .contract()
+        .get_position_for_user(&get_account(Bob), &AssetId::NEAR, &AssetId::NEAR, true)
+        .is_none());
+    let order = vcontract.get_twap_order(twap_order_id).unwrap();
+    assert_eq!(order.remaining_slices, 1);
+    assert_eq!(order.remaining_collateral.0, near(20));
+    assert!(near_sdk::test_utils::get_logs()
+        .iter()
+        .any(|log| log.contains("\"status\":\"skipped\"")));
+}
+
+#[test]
+fn test_remove_twap_order_refunds_collateral() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 4);
+    execute_slice(&mut context, &mut vcontract, twap_order_id.0);
+
+    let transfer_info = vcontract
+        .contract_mut()
+        .remove_twap_order(&get_account(Bob), twap_order_id.0);
+    assert_eq!(transfer_info.amount(), near(15));
+    assert!(vcontract.get_twap_order(twap_order_id).is_none());
+}
+
+#[test]
+#[should_panic(expected = "You do not own this TWAP order")]
+fn test_remove_twap_order_not_owner() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+
+    let _ = vcontract
+        .contract_mut()
+        .remove_twap_order(&get_account(Alice), twap_order_id.0);
+}
+
+#[test]
+#[should_panic(expected = "TWAP order exceeds maximum leverage")]
+fn test_twap_order_max_leverage() {
+    let (mut context, mut vcontract) = setup_twap();
+    place_long_twap(&mut context, &mut vcontract, 20);
+}
+
+#[test]
+fn test_short_twap_order_with_ft() {
+    let (mut context, mut vcontract) = setup_twap();
+
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        dollars(100).into(),
+        serde_json::to_string(&Action::PlaceTwapOrder(TwapOrderParameters {
+            underlying_id: near_id(),
+            is_long: false,
+            size_delta: dollars(500).into(),
+            num_slices: 5,
+            interval_sec: 60,
+        }))
+        .unwrap(),
+    );
+    let orders = vcontract.get_user_twap_orders(get_account(Bob));
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].collateral_id, usdc_id());
+    assert_eq!(orders[0].slice_size.0, dollars(100));
+
+    execute_slice(&mut context, &mut vcontract, orders[0].id.0);
+    let position_id = vcontract
+        .contract()
+        .get_position_for_user(
+            &get_account(Bob),
+            &AssetId::from(usdc_id()),
+            &AssetId::NEAR,
+            false,
+        )
+        .unwrap();
+    let position = vcontract.get_position(&position_id).unwrap();
+    assert_eq!(position.size.0, dollars(100));
+    assert_eq!(position.collateral.0, dollars(20));
+}
+
+#[test]
+#[should_panic(expected = "caller must be have a Liquidator role")]
+fn test_twap_slice_only_liquidator() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+
+    set_predecessor(&mut context, Bob);
+    let _ = vcontract.contract_mut().execute_twap_slice(twap_order_id.0);
+}
+
+#[test]
+fn test_remove_twap_order_while_paused() {
+    let (mut context, mut vcontract) = setup_twap();
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+
+    set_predecessor(&mut context, Admin);
+    vcontract.set_state(ContractState::Paused);
+    let transfer_info = vcontract
+        .contract_mut()
+        .remove_twap_order(&get_account(Bob), twap_order_id.0);
+    assert_eq!(transfer_info.amount(), near(20));
+}
+
+#[test]
+fn test_twap_order_storage() {
+    let (mut context, mut vcontract) = setup_twap();
+    let initial_balance = vcontract.get_order_storage_balance(get_account(Bob)).0;
+
+    let twap_order_id = place_long_twap(&mut context, &mut vcontract, 2);
+    assert!(vcontract.get_order_storage_balance(get_account(Bob)).0 < initial_balance);
+
+    let _ = vcontract
+        .contract_mut()
+        .remove_twap_order(&get_account(Bob), twap_order_id.0);
+    assert_eq!(
+        vcontract.get_order_storage_balance(get_account(Bob)).0,
+        initial_balance
+    );
+}
+
+#[test]
+#[should_panic(expected = "Not enough order storage balance")]
+fn test_twap_order_storage_required() {
+    let (mut context, mut vcontract) = setup_twap();
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+    vcontract.withdraw_order_storage(None);
+
+    place_long_twap(&mut context, &mut vcontract, 2);
+}
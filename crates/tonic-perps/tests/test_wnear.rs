@@ -0,0 +1,211 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{
+    testing_env, AccountId, PromiseOrValue, PromiseResult, RuntimeFeesConfig, VMConfig,
+};
+
+fn wnear_id() -> String {
+    "wrap.near".to_string()
+}
+
+fn setup_wnear() -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    context.account_balance(near(10000));
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_wnear_id(Some(wnear_id().parse().unwrap()));
+
+    let contract = vcontract.contract_mut();
+    contract.add_liquidity(&AssetId::NEAR, near(1000));
+    contract.add_liquidity(&AssetId::from(usdc_id()), dollars(5000));
+    (context, vcontract)
+}
+
+#[test]
+fn test_wnear_deposit_is_near() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    set_predecessor_token(&mut context, wnear_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        near(100).into(),
+        serde_json::to_string(&Action::MintLp(MintLpParams {
+            min_out: None,
+            referrer_id: None,
+        }))
+        .unwrap(),
+    );
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1100)
+    );
+    assert_eq!(
+        vcontract.ft_balance_of(get_account(Bob)).0,
+        lp_tokens(500.0)
+    );
+}
+
+#[test]
+fn test_swap_wnear() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    // wNEAR in
+    set_predecessor_token(&mut context, wnear_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        near(100).into(),
+        serde_json::to_string(&Action::Swap(SwapParams {
+            output_token_id: usdc_id(),
+            min_out: None,
+            referrer_id: None,
+            amount_out: None,
+        }))
+        .unwrap(),
+    );
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1100)
+    );
+
+    // wNEAR out
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        dollars(100).into(),
+        serde_json::to_string(&Action::Swap(SwapParams {
+            output_token_id: wnear_id(),
+            min_out: None,
+            referrer_id: None,
+            amount_out: None,
+        }))
+        .unwrap(),
+    );
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1080)
+    );
+}
+
+#[test]
+fn test_wnear_exact_out_refund() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    set_predecessor_token(&mut context, wnear_id());
+    let unused = vcontract.ft_on_transfer(
+        get_account(Bob),
+        near(100).into(),
+        serde_json::to_string(&Action::Swap(SwapParams {
+            output_token_id: usdc_id(),
+            min_out: None,
+            referrer_id: None,
+            amount_out: Some(dollars(250).into()),
+        }))
+        .unwrap(),
+    );
+    // About 50 NEAR swapped, the rest is refunded as wNEAR
+    match unused {
+        PromiseOrValue::Value(unused) => assert!(unused.0 > near(49) && unused.0 < near(50)),
+        _ => panic!("Expected a refund amount"),
+    }
+}
+
+#[test]
+fn test_burn_lp_for_wnear() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+    vcontract.set_wnear_id(Some(wnear_id().parse().unwrap()));
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(100));
+    vcontract.mint_lp_near(None, None);
+
+    set_deposit(&mut context, 1);
+    let amount_out = vcontract.burn_lp_token(lp_tokens(250.0).into(), wnear_id(), None, None);
+    assert_eq!(amount_out.0, near(50));
+    assert_eq!(vcontract.get_asset_info(near_id()).pool_amount.0, near(50));
+}
+
+#[test]
+fn test_decrease_position_to_wnear() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, near(10));
+    let position_id = vcontract.increase_position(IncreasePositionRequest {
+        underlying_id: near_id(),
+        size_delta: dollars(100).into(),
+        is_long: true,
+        referrer_id: None,
+    });
+
+    set_deposit(&mut context, 1);
+    let transfer_info = vcontract.contract_mut().decrease_position(
+        position_id,
+        0,
+        dollars(100),
+        None,
+        false,
+        Some(wnear_id()),
+    );
+    assert_eq!(transfer_info.asset_id(), AssetId::from(wnear_id()));
+    assert_eq!(transfer_info.amount(), near(10));
+}
+
+/// Run a callback of the contract with a failed promise result
+fn set_promise_failed(context: &mut VMContextBuilder) {
+    testing_env!(
+        context.predecessor_account_id(get_account(Alice)).build(),
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![PromiseResult::Failed],
+    );
+}
+
+#[test]
+fn test_unwrap_near_failure() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    set_promise_failed(&mut context);
+    vcontract.on_unwrap_near(near(100).into());
+    assert_eq!(vcontract.get_pending_wnear_unwrap().0, near(100));
+
+    let _ = vcontract.retry_unwrap_near();
+    assert_eq!(vcontract.get_pending_wnear_unwrap().0, 0);
+}
+
+#[test]
+fn test_send_wnear_failure() {
+    let (mut context, mut vcontract) = setup_wnear();
+
+    // The receiver is paid in NEAR and the wrapped NEAR is unwrapped later
+    set_promise_failed(&mut context);
+    vcontract.on_send_wnear(TransferInfo::new(
+        &get_account(Bob),
+        &AssetId::from(wnear_id()),
+        near(10),
+    ));
+    assert_eq!(vcontract.get_pending_wnear_unwrap().0, near(10));
+}
+
+#[test]
+#[should_panic(expected = "Nothing to unwrap")]
+fn test_retry_unwrap_near_empty() {
+    let (_context, mut vcontract) = setup_wnear();
+    let _ = vcontract.retry_unwrap_near();
+}
+
+#[test]
+#[should_panic]
+fn test_wnear_cannot_be_an_asset() {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    vcontract.add_asset(wnear_id(), 24, false, 10);
+    let wnear_id: AccountId = wnear_id().parse().unwrap();
+    vcontract.set_wnear_id(Some(wnear_id));
+}
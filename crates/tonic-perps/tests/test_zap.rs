@@ -0,0 +1,136 @@
+mod common;
+
+use common::*;
+use near_contract_standards::fungible_token::{
+    core::FungibleTokenCore, receiver::FungibleTokenReceiver,
+};
+
+fn setup_zap(
+    near_lp_support: LpSupportState,
+) -> (near_sdk::test_utils::VMContextBuilder, VContract) {
+    let (mut context, mut vcontract) = setup();
+    set_predecessor(&mut context, Admin);
+    update_near_price(&mut vcontract, dollars(5));
+
+    // $5000 of NEAR and $1000 of USDC, USDC is under its target weight
+    set_deposit(&mut context, near(1000));
+    vcontract.mint_lp_near(None, None);
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Admin),
+        usdc(1000).into(),
+        serde_json::to_string(&Action::MintLp(MintLpParams {
+            min_out: None,
+            referrer_id: None,
+        }))
+        .unwrap(),
+    );
+
+    set_predecessor(&mut context, Admin);
+    vcontract.set_asset_state(
+        near_id(),
+        AssetState {
+            perps: PerpsState::Enabled,
+            lp_support: near_lp_support,
+            swap: SwapState::Enabled,
+        },
+    );
+
+    set_predecessor(&mut context, Bob);
+    (context, vcontract)
+}
+
+#[test]
+fn test_zap_in_asset() {
+    let (_context, vcontract) = setup_zap(LpSupportState::Enabled);
+    assert_eq!(vcontract.get_zap_in_asset(), usdc_id());
+}
+
+#[test]
+fn test_zap_in_lp() {
+    let (mut context, mut vcontract) = setup_zap(LpSupportState::BurnOnly);
+
+    // NEAR can't be minted with, so it's swapped to USDC first
+    set_deposit(&mut context, near(10));
+    let minted = vcontract.zap_in_lp_near(None, None);
+    assert_eq!(minted.0, lp_tokens(50.0));
+    assert_eq!(vcontract.ft_balance_of(get_account(Bob)).0, lp_tokens(50.0));
+
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1010)
+    );
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        usdc(1000)
+    );
+}
+
+#[test]
+#[should_panic(expected = "exceeded slippage tolerance")]
+fn test_zap_in_lp_slippage() {
+    let (mut context, mut vcontract) = setup_zap(LpSupportState::BurnOnly);
+
+    set_deposit(&mut context, near(10));
+    vcontract.zap_in_lp_near(Some(lp_tokens(51.0).into()), None);
+}
+
+#[test]
+fn test_zap_out_lp() {
+    let (mut context, mut vcontract) = setup_zap(LpSupportState::Disabled);
+
+    set_predecessor_token(&mut context, usdc_id());
+    vcontract.ft_on_transfer(
+        get_account(Bob),
+        usdc(50).into(),
+        serde_json::to_string(&Action::ZapInLp(MintLpParams {
+            min_out: None,
+            referrer_id: None,
+        }))
+        .unwrap(),
+    );
+    set_predecessor(&mut context, Bob);
+    set_deposit(&mut context, 1);
+
+    // NEAR can't be burned into, so LP tokens are burned into USDC and swapped
+    let amount_out = vcontract.zap_out_lp(lp_tokens(50.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, near(10));
+    assert_eq!(vcontract.ft_balance_of(get_account(Bob)).0, 0);
+    assert_eq!(vcontract.get_asset_info(near_id()).pool_amount.0, near(990));
+}
+
+#[test]
+fn test_zap_out_lp_direct() {
+    let (mut context, mut vcontract) = setup_zap(LpSupportState::Enabled);
+
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+
+    // No swap needed when the output asset can be burned into
+    set_deposit(&mut context, 1);
+    let amount_out = vcontract.zap_out_lp(lp_tokens(50.0).into(), near_id(), None, None);
+    assert_eq!(amount_out.0, near(10));
+    assert_eq!(
+        vcontract.get_asset_info(near_id()).pool_amount.0,
+        near(1000)
+    );
+    assert_eq!(
+        vcontract.get_asset_info(usdc_id()).pool_amount.0,
+        usdc(1000)
+    );
+}
+
+#[test]
+#[should_panic(expected = "exceeded slippage tolerance")]
+fn test_zap_out_lp_slippage() {
+    let (mut context, mut vcontract) = setup_zap(LpSupportState::Enabled);
+
+    set_deposit(&mut context, near(10));
+    vcontract.mint_lp_near(None, None);
+    vcontract.zap_out_lp(
+        lp_tokens(50.0).into(),
+        usdc_id(),
+        Some(usdc(51).into()),
+        None,
+    );
+}
@@ -1,6 +1,6 @@
 import {
     addDecreaseLimitOrder, addIncreaseLimitOrder, NEAR_ID, near, dollars, updateIndexPrice, test,
-    increasePosition, mintLp, mintLpFtTransfer
+    increasePosition, mintLp, mintLpFtTransfer, orderStorageDeposit
 }
     from './utils.ava';
 
@@ -23,6 +23,7 @@ test('limit orders: add and remove', async (t) => {
     t.is(position.size, dollars(400));
 
     await updateIndexPrice(root, contract, NEAR_ID, dollars(5));
+    await orderStorageDeposit(root, contract);
 
     const limitOrderId = await addDecreaseLimitOrder(root, contract, dollars(4), dollars(400), NEAR_ID, NEAR_ID, true, dollars(150), '0');
 
@@ -63,6 +64,7 @@ test('limit orders: execute', async (t) => {
     t.is(position.size, dollars(400));
 
     await updateIndexPrice(root, contract, NEAR_ID, dollars(5));
+    await orderStorageDeposit(root, contract);
 
     // Execute this one. Should close the position.
     await addDecreaseLimitOrder(root, contract, dollars(4), dollars(400), NEAR_ID, NEAR_ID, true, dollars(150), '0');
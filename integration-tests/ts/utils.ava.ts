@@ -59,6 +59,12 @@ export async function addDecreaseLimitOrder(
     });
 }
 
+export async function orderStorageDeposit(root: NearAccount, contract: NearAccount) {
+    await root.call(contract, 'deposit_order_storage', {}, {
+        attachedDeposit: NEAR.parse('1 N').toJSON(),
+    });
+}
+
 export async function addIncreaseLimitOrder(
     root: NearAccount,
     contract: NearAccount,